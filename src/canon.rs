@@ -0,0 +1,131 @@
+use crate::layout::{Layout, MAX_SIZE};
+use crate::transform::{line_orders, Transformation};
+use crate::QQWingError;
+
+/**
+ * The largest board a canonical form is worked out for. Every order of the
+ * rows and columns is tried, which is too many on bigger boards.
+ */
+const MAX_CANONICAL_SIZE: usize = 9;
+
+/**
+ * The canonical form of a puzzle: the one board, of all those it can be
+ * turned into by renaming values, reordering bands, stacks and the rows and
+ * columns within them, and transposing, that comes first. Boards are
+ * compared cell by cell in reading order with empty cells first, after
+ * renaming the values in the order they are first met, so two puzzles have
+ * the same canonical form exactly when they are the same puzzle in disguise.
+ * Returns the canonical form and the transformation that turns the puzzle
+ * into it, or an error if the board is not made of plain boxes of at most
+ * 9 values.
+ */
+pub fn canonical_form(
+    layout: &Layout,
+    values: &[u8],
+) -> Result<(Vec<u8>, Transformation), QQWingError> {
+    check_layout(layout)?;
+    let size = layout.get_size();
+    let board_size = layout.get_board_size();
+    if values.len() != board_size {
+        return Err(QQWingError::InvalidPuzzle {
+            expected: board_size,
+            found: values.len(),
+        });
+    }
+    if let Some(position) = values.iter().position(|&v| v as usize > size) {
+        return Err(QQWingError::InvalidValue {
+            position,
+            value: values[position],
+        });
+    }
+
+    let row_orders = line_orders(size, layout.get_box_rows());
+    let column_orders = line_orders(size, layout.get_box_columns());
+    let transposes = match layout.get_box_rows() == layout.get_box_columns() {
+        true => vec![false, true],
+        false => vec![false],
+    };
+    let mut best = vec![u8::MAX; board_size];
+    let mut best_transformation = Transformation::identity(size);
+    let mut candidate = vec![0; board_size];
+    for transpose in transposes {
+        let board = Transformation {
+            transpose,
+            ..Transformation::identity(size)
+        }
+        .apply(layout, values);
+        for rows in &row_orders {
+            for columns in &column_orders {
+                // New names for the values in the order they are met.
+                let mut names = [0u8; MAX_SIZE + 1];
+                let mut next = 1;
+                let mut better = false;
+                'cells: for (row, &from_row) in rows.iter().enumerate() {
+                    for (column, &from_column) in columns.iter().enumerate() {
+                        let value = board[from_row * size + from_column] as usize;
+                        if value != 0 && names[value] == 0 {
+                            names[value] = next;
+                            next += 1;
+                        }
+                        let position = row * size + column;
+                        let name = names[value];
+                        if !better {
+                            if name > best[position] {
+                                break 'cells;
+                            }
+                            better = name < best[position];
+                        }
+                        candidate[position] = name;
+                    }
+                }
+                if better {
+                    best.copy_from_slice(&candidate);
+                    best_transformation = Transformation {
+                        transpose,
+                        rows: rows.clone(),
+                        columns: columns.clone(),
+                        digits: digits(&names, size),
+                    };
+                }
+            }
+        }
+    }
+    Ok((best, best_transformation))
+}
+
+/**
+ * Whether two puzzles on the same board are the same puzzle in disguise,
+ * that is whether they have the same canonical form.
+ */
+pub fn equivalent(layout: &Layout, first: &[u8], second: &[u8]) -> Result<bool, QQWingError> {
+    Ok(canonical_form(layout, first)?.0 == canonical_form(layout, second)?.0)
+}
+
+/**
+ * Check that a board has a canonical form: plain boxes, no rules that
+ * reordering rows and columns would break, and few enough values to try
+ * every order.
+ */
+fn check_layout(layout: &Layout) -> Result<(), QQWingError> {
+    match layout.is_plain() && layout.get_size() <= MAX_CANONICAL_SIZE {
+        true => Ok(()),
+        false => Err(QQWingError::NoCanonicalForm),
+    }
+}
+
+/**
+ * The value each value becomes, from the names given to the values met in
+ * the puzzle, with the values it doesn't hold named after them in order.
+ */
+fn digits(names: &[u8], size: usize) -> Vec<u8> {
+    let mut next = names.iter().filter(|&&name| name != 0).count() as u8 + 1;
+    (1..=size)
+        .map(|value| match names[value] {
+            0 => {
+                next += 1;
+                next - 1
+            }
+            name => name,
+        })
+        .collect()
+}
@@ -0,0 +1,407 @@
+use crate::killer::{fit_cage, max_sum, parse_cell};
+use crate::layout::Layout;
+use crate::logtype::LogType;
+use crate::QQWingError;
+
+///
+/// A rule over some cells of the board that cuts down the values they may
+/// take, given the values each of them may still take. Every kind of clue
+/// is one of these, so that the solver and the solution counter treat them
+/// all alike and new kinds only have to say which cells they cover and how
+/// they prune.
+///
+pub(crate) trait ClueRule {
+    /**
+     * The cells of the clue, in the order prune() takes their values.
+     */
+    fn cells(&self, layout: &Layout) -> Vec<usize>;
+
+    /**
+     * Remove the values that can't be part of any way of keeping to the
+     * clue, given one bit per value for each cell where a placed cell has
+     * only its own value. Returns false if no way is left at all. When every
+     * cell has one value this checks the clue.
+     */
+    fn prune(&self, layout: &Layout, values: &mut [u32]) -> bool;
+
+    /**
+     * The technique logged when the clue removes possibilities.
+     */
+    fn log_type(&self) -> LogType;
+}
+
+///
+/// A thermometer: the values must strictly increase from the bulb along
+/// the cells.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thermometer {
+    /**
+     * The cells from the bulb to the tip.
+     */
+    cells: Vec<usize>,
+}
+
+impl Thermometer {
+    pub fn new(cells: Vec<usize>) -> Self {
+        Self { cells }
+    }
+
+    pub fn get_cells(&self) -> &[usize] {
+        &self.cells
+    }
+}
+
+impl ClueRule for Thermometer {
+    fn cells(&self, _layout: &Layout) -> Vec<usize> {
+        self.cells.clone()
+    }
+
+    fn prune(&self, _layout: &Layout, values: &mut [u32]) -> bool {
+        for i in 1..values.len() {
+            if values[i - 1] == 0 {
+                return false;
+            }
+            // Above the smallest value the cell before can take.
+            values[i] &= !((1 << lowest(values[i - 1])) - 1);
+        }
+        for i in (0..values.len() - 1).rev() {
+            if values[i + 1] == 0 {
+                return false;
+            }
+            // Below the largest value the cell after can take.
+            values[i] &= (1 << (highest(values[i + 1]) - 1)) - 1;
+        }
+        values.iter().all(|&v| v != 0)
+    }
+
+    fn log_type(&self) -> LogType {
+        LogType::Thermometer
+    }
+}
+
+///
+/// An arrow: the value in the circle is the sum of the values along the
+/// arrow's shaft, which may repeat where the rules allow it.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arrow {
+    circle: usize,
+
+    /**
+     * The cells of the shaft, from the circle outwards.
+     */
+    shaft: Vec<usize>,
+}
+
+impl Arrow {
+    pub fn new(circle: usize, shaft: Vec<usize>) -> Self {
+        Self { circle, shaft }
+    }
+
+    pub fn get_circle(&self) -> usize {
+        self.circle
+    }
+
+    pub fn get_shaft(&self) -> &[usize] {
+        &self.shaft
+    }
+}
+
+impl ClueRule for Arrow {
+    fn cells(&self, _layout: &Layout) -> Vec<usize> {
+        let mut cells = vec![self.circle];
+        cells.extend(&self.shaft);
+        cells
+    }
+
+    fn prune(&self, _layout: &Layout, values: &mut [u32]) -> bool {
+        if values.contains(&0) {
+            return false;
+        }
+        let (circle, shaft) = values.split_at_mut(1);
+        let low: u32 = shaft.iter().map(|&v| lowest(v)).sum();
+        let high: u32 = shaft.iter().map(|&v| highest(v)).sum();
+        circle[0] &= range(low, high);
+        if circle[0] == 0 {
+            return false;
+        }
+        let (circle_low, circle_high) = (lowest(circle[0]), highest(circle[0]));
+        for value in shaft.iter_mut() {
+            let others_low = low - lowest(*value);
+            let others_high = high - highest(*value);
+            *value &= range(
+                circle_low.saturating_sub(others_high),
+                circle_high.saturating_sub(others_low),
+            );
+            if *value == 0 {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn log_type(&self) -> LogType {
+        LogType::Arrow
+    }
+}
+
+/// A row or column of the board, numbered from 0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Line {
+    Row(usize),
+    Column(usize),
+}
+
+///
+/// A sandwich clue: the values between the smallest and the largest value
+/// in a row or column, 1 and 9 on a 9x9 board, add up to the sum.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sandwich {
+    line: Line,
+    sum: u32,
+}
+
+impl Sandwich {
+    pub fn new(line: Line, sum: u32) -> Self {
+        Self { line, sum }
+    }
+
+    pub fn get_line(&self) -> Line {
+        self.line
+    }
+
+    pub fn get_sum(&self) -> u32 {
+        self.sum
+    }
+}
+
+impl ClueRule for Sandwich {
+    fn cells(&self, layout: &Layout) -> Vec<usize> {
+        match self.line {
+            Line::Row(row) => layout.row_cells(row).to_vec(),
+            Line::Column(column) => layout.column_cells(column).to_vec(),
+        }
+    }
+
+    fn prune(&self, layout: &Layout, values: &mut [u32]) -> bool {
+        let smallest = 1;
+        let largest = 1 << (layout.get_size() - 1);
+        let middle = layout.all_values() & !smallest & !largest;
+        let mut kept = vec![0; values.len()];
+        // Try every place for the smallest and the largest value, keeping
+        // the values the cells between them can add up to the sum with.
+        for a in 0..values.len() {
+            if values[a] & smallest == 0 {
+                continue;
+            }
+            for b in 0..values.len() {
+                if b == a || values[b] & largest == 0 {
+                    continue;
+                }
+                let (start, end) = (a.min(b) + 1, a.max(b));
+                let between: Vec<u32> = values[start..end].iter().map(|&v| v & middle).collect();
+                let Some(fit) = fit_cage(&between, 0, self.sum) else {
+                    continue;
+                };
+                for (i, kept) in kept.iter_mut().enumerate() {
+                    *kept |= match i {
+                        i if i == a => smallest,
+                        i if i == b => largest,
+                        i if (start..end).contains(&i) => fit.values[i - start],
+                        i => values[i] & middle,
+                    };
+                }
+            }
+        }
+        for (value, kept) in values.iter_mut().zip(kept) {
+            *value &= kept;
+        }
+        values.iter().all(|&v| v != 0)
+    }
+
+    fn log_type(&self) -> LogType {
+        LogType::Sandwich
+    }
+}
+
+///
+/// A clue drawn on the board or written outside it, such as a thermometer,
+/// an arrow or a sandwich sum.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum Clue {
+    Thermometer(Thermometer),
+    Arrow(Arrow),
+    Sandwich(Sandwich),
+}
+
+impl Clue {
+    pub(crate) fn rule(&self) -> &dyn ClueRule {
+        match self {
+            Clue::Thermometer(thermometer) => thermometer,
+            Clue::Arrow(arrow) => arrow,
+            Clue::Sandwich(sandwich) => sandwich,
+        }
+    }
+}
+
+/**
+ * Read clues from text, one clue per line or separated by ';'. A
+ * thermometer is "thermo:" and its cells from the bulb, an arrow "arrow:"
+ * and its cells from the circle, both written as r1c1 and separated by
+ * spaces or commas, such as "thermo: r1c1, r1c2, r2c3". A sandwich clue is
+ * "sandwich:", a row such as r1 or a column such as c3, '=' and the sum,
+ * such as "sandwich: r1=15". Blank lines are skipped. Returns an error if a
+ * clue can't be read or doesn't fit the board, see check_clues().
+ */
+pub fn parse_clues(layout: &Layout, text: &str) -> Result<Vec<Clue>, QQWingError> {
+    let mut clues = Vec::new();
+    for definition in text.split(['\n', ';']).map(str::trim) {
+        if definition.is_empty() {
+            continue;
+        }
+        let syntax = || QQWingError::ClueSyntax(definition.to_string());
+        let (kind, rest) = definition.split_once(':').ok_or_else(syntax)?;
+        let kind = kind.trim().to_ascii_lowercase();
+        if kind == "sandwich" {
+            let (line, sum) = rest.split_once('=').ok_or_else(syntax)?;
+            let line = line.trim().to_ascii_lowercase();
+            let index: usize = line
+                .get(1..)
+                .and_then(|i| i.parse().ok())
+                .ok_or_else(syntax)?;
+            let line = match (line.as_bytes()[0], index) {
+                (_, 0) => return Err(syntax()),
+                (b'r', index) => Line::Row(index - 1),
+                (b'c', index) => Line::Column(index - 1),
+                _ => return Err(syntax()),
+            };
+            let sum = sum.trim().parse().map_err(|_| syntax())?;
+            clues.push(Clue::Sandwich(Sandwich::new(line, sum)));
+            continue;
+        }
+        let cells = rest
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|word| !word.is_empty())
+            .map(|word| parse_cell(layout, word).ok_or_else(syntax))
+            .collect::<Result<Vec<usize>, QQWingError>>()?;
+        clues.push(match kind.as_str() {
+            "thermo" => Clue::Thermometer(Thermometer::new(cells)),
+            "arrow" if !cells.is_empty() => Clue::Arrow(Arrow::new(cells[0], cells[1..].to_vec())),
+            _ => return Err(syntax()),
+        });
+    }
+    check_clues(layout, &clues)?;
+    Ok(clues)
+}
+
+/**
+ * Write clues one per line in the form read by parse_clues().
+ */
+pub fn clues_to_string(layout: &Layout, clues: &[Clue]) -> String {
+    let cell_names = |cells: &[usize]| -> String {
+        let names: Vec<String> = cells
+            .iter()
+            .map(|&cell| {
+                format!(
+                    "r{}c{}",
+                    layout.cell_to_row(cell) + 1,
+                    layout.cell_to_column(cell) + 1
+                )
+            })
+            .collect();
+        names.join(",")
+    };
+    let lines: Vec<String> = clues
+        .iter()
+        .map(|clue| match clue {
+            Clue::Thermometer(thermometer) => format!("thermo: {}", cell_names(&thermometer.cells)),
+            Clue::Arrow(_) => format!("arrow: {}", cell_names(&clue.rule().cells(layout))),
+            Clue::Sandwich(sandwich) => match sandwich.line {
+                Line::Row(row) => format!("sandwich: r{}={}", row + 1, sandwich.sum),
+                Line::Column(column) => format!("sandwich: c{}={}", column + 1, sandwich.sum),
+            },
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/**
+ * Check that every thermometer and arrow has at least two different cells
+ * on the board, each touching the one before it, and a thermometer no more
+ * cells than there are values. A sandwich clue must be on a row or column
+ * of the board with a sum the values between the smallest and the largest
+ * can make.
+ */
+pub(crate) fn check_clues(layout: &Layout, clues: &[Clue]) -> Result<(), QQWingError> {
+    for (index, clue) in clues.iter().enumerate() {
+        let fits = match clue {
+            Clue::Sandwich(sandwich) => {
+                let middle = layout.all_values() & !1 & !(1 << (layout.get_size() - 1));
+                let line = match sandwich.line {
+                    Line::Row(row) => row,
+                    Line::Column(column) => column,
+                };
+                line < layout.get_size()
+                    && sandwich.sum <= max_sum(middle, middle.count_ones() as usize)
+            }
+            _ => {
+                let cells = clue.rule().cells(layout);
+                let mut sorted = cells.clone();
+                sorted.sort_unstable();
+                sorted.dedup();
+                let longest = match clue {
+                    Clue::Thermometer(_) => layout.get_size(),
+                    _ => layout.get_board_size(),
+                };
+                cells.len() >= 2
+                    && cells.len() <= longest
+                    && sorted.len() == cells.len()
+                    && cells.iter().all(|&cell| cell < layout.get_board_size())
+                    && cells
+                        .windows(2)
+                        .all(|pair| touching(layout, pair[0], pair[1]))
+            }
+        };
+        if !fits {
+            return Err(QQWingError::InvalidClue(index + 1));
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Whether two cells are next to each other, diagonally or not.
+ */
+fn touching(layout: &Layout, a: usize, b: usize) -> bool {
+    layout.cell_to_row(a).abs_diff(layout.cell_to_row(b)) <= 1
+        && layout.cell_to_column(a).abs_diff(layout.cell_to_column(b)) <= 1
+}
+
+/**
+ * The smallest value in a non-empty set of values.
+ */
+fn lowest(values: u32) -> u32 {
+    values.trailing_zeros() + 1
+}
+
+/**
+ * The largest value in a non-empty set of values.
+ */
+fn highest(values: u32) -> u32 {
+    32 - values.leading_zeros()
+}
+
+/**
+ * The values from low to high, one bit per value.
+ */
+fn range(low: u32, high: u32) -> u32 {
+    let low = low.max(1);
+    let high = high.min(32);
+    if low > high {
+        return 0;
+    }
+    (u32::MAX >> (32 - high)) & !((1 << (low - 1)) - 1)
+}
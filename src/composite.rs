@@ -0,0 +1,517 @@
+use rand::{seq::SliceRandom, thread_rng};
+use strum::{EnumIter, EnumString};
+
+use crate::counter::SolutionCounter;
+use crate::difficulty::Difficulty;
+use crate::layout::Layout;
+use crate::options::{Budget, GenerateOptions};
+use crate::symmetry::Symmetry;
+use crate::{PrintStyle, QQWingError, NL, UNIQUE_NODE_LIMIT};
+
+/// The arrangements of overlapping 9x9 grids that have a name.
+#[derive(Debug, PartialEq, Clone, EnumString, EnumIter)]
+pub enum CompositeKind {
+    /// Five grids on a 21x21 canvas: one in the middle sharing each of its
+    /// corner boxes with one of the other four.
+    SAMURAI,
+    /// Two grids on a 15x15 canvas, the bottom right box of the first being
+    /// the top left box of the second.
+    TWOGRID,
+    /// Four grids on a 12x12 canvas, each offset one box from the next so
+    /// that the middle box is in all four.
+    BUTTERFLY,
+}
+
+///
+/// The shape of a composite puzzle: several 9x9 grids placed on a larger
+/// canvas so that where they overlap they share whole boxes. Every row,
+/// column and box of every grid must hold each value once, and a shared box
+/// is one box of both grids, so values placed in it count in the rows and
+/// columns of both. The cells of the puzzle are the canvas squares that are
+/// in at least one grid, numbered in reading order, and the units each cell
+/// is in are worked out once when the layout is made.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompositeLayout {
+    /**
+     * The shape of each of the grids.
+     */
+    grid: Layout,
+
+    /**
+     * The canvas row and column of the top left cell of each grid.
+     */
+    offsets: Vec<(usize, usize)>,
+
+    /**
+     * The number of rows and columns of the canvas.
+     */
+    height: usize,
+    width: usize,
+
+    /**
+     * The cell at each canvas square, None for squares in no grid, and the
+     * canvas square of each cell.
+     */
+    canvas: Vec<Option<usize>>,
+    positions: Vec<usize>,
+
+    /**
+     * For each grid, the cell at each of its positions in reading order.
+     */
+    grid_cells: Vec<Vec<usize>>,
+
+    /**
+     * The cells of every row, column and box of every grid, each shared box
+     * once, and the units each cell is in.
+     */
+    units: Vec<Vec<usize>>,
+    cell_units: Vec<Vec<usize>>,
+}
+
+impl CompositeLayout {
+    /**
+     * One of the named arrangements of grids.
+     */
+    pub fn new(kind: CompositeKind) -> Self {
+        let offsets: &[(usize, usize)] = match kind {
+            CompositeKind::SAMURAI => &[(0, 0), (0, 12), (6, 6), (12, 0), (12, 12)],
+            CompositeKind::TWOGRID => &[(0, 0), (6, 6)],
+            CompositeKind::BUTTERFLY => &[(0, 0), (0, 3), (3, 0), (3, 3)],
+        };
+        Self::with_grids(offsets).expect("named arrangements line up with the boxes")
+    }
+
+    /**
+     * Grids placed with their top left cells at the given canvas rows and
+     * columns. Returns an error if there are no grids, two grids are in the
+     * same place, or a grid doesn't line up with the boxes of the others.
+     */
+    pub fn with_grids(offsets: &[(usize, usize)]) -> Result<Self, QQWingError> {
+        let grid = Layout::default();
+        let size = grid.get_size();
+        let lined_up = offsets.iter().all(|&(row, column)| {
+            row % grid.get_box_rows() == 0 && column % grid.get_box_columns() == 0
+        });
+        let repeated = (1..offsets.len()).any(|i| offsets[..i].contains(&offsets[i]));
+        if offsets.is_empty() || !lined_up || repeated {
+            return Err(QQWingError::InvalidComposite(offsets.len()));
+        }
+        let height = offsets.iter().map(|&(row, _)| row).max().unwrap_or(0) + size;
+        let width = offsets.iter().map(|&(_, column)| column).max().unwrap_or(0) + size;
+
+        let mut covered = vec![false; height * width];
+        for &(top, left) in offsets {
+            for cell in 0..grid.get_board_size() {
+                let row = top + grid.cell_to_row(cell);
+                let column = left + grid.cell_to_column(cell);
+                covered[row * width + column] = true;
+            }
+        }
+        let positions: Vec<usize> = (0..height * width).filter(|&p| covered[p]).collect();
+        let mut canvas = vec![None; height * width];
+        for (cell, &position) in positions.iter().enumerate() {
+            canvas[position] = Some(cell);
+        }
+        let grid_cells: Vec<Vec<usize>> = offsets
+            .iter()
+            .map(|&(top, left)| {
+                (0..grid.get_board_size())
+                    .map(|cell| {
+                        let row = top + grid.cell_to_row(cell);
+                        let column = left + grid.cell_to_column(cell);
+                        canvas[row * width + column].unwrap_or_default()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut units: Vec<Vec<usize>> = Vec::new();
+        for cells in &grid_cells {
+            for unit in 0..size {
+                for grid_unit in [
+                    grid.row_cells(unit),
+                    grid.column_cells(unit),
+                    grid.section_cells(unit),
+                ] {
+                    let mut unit_cells: Vec<usize> = grid_unit.iter().map(|&p| cells[p]).collect();
+                    unit_cells.sort_unstable();
+                    if !units.contains(&unit_cells) {
+                        units.push(unit_cells);
+                    }
+                }
+            }
+        }
+        let mut cell_units = vec![Vec::new(); positions.len()];
+        for (index, unit) in units.iter().enumerate() {
+            for &cell in unit {
+                cell_units[cell].push(index);
+            }
+        }
+        Ok(Self {
+            grid,
+            offsets: offsets.to_vec(),
+            height,
+            width,
+            canvas,
+            positions,
+            grid_cells,
+            units,
+            cell_units,
+        })
+    }
+
+    /**
+     * The shape of each of the grids.
+     */
+    pub fn get_grid(&self) -> &Layout {
+        &self.grid
+    }
+
+    /**
+     * The canvas row and column of the top left cell of each grid.
+     */
+    pub fn get_offsets(&self) -> &[(usize, usize)] {
+        &self.offsets
+    }
+
+    /**
+     * The number of cells in the puzzle, counting each shared cell once.
+     */
+    pub fn get_cell_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    /**
+     * The cells of a grid in its reading order, so that the values of a
+     * puzzle at them are a 9x9 puzzle of their own.
+     */
+    pub fn grid_cells(&self, grid: usize) -> &[usize] {
+        &self.grid_cells[grid]
+    }
+
+    /**
+     * The canvas row and column of a cell.
+     */
+    pub fn cell_to_row_column(&self, cell: usize) -> (usize, usize) {
+        let position = self.positions[cell];
+        (position / self.width, position % self.width)
+    }
+
+    /**
+     * Read the cells of a puzzle from text, one character per cell in
+     * reading order of the canvas. Whitespace, which stands for the squares
+     * in no grid in the printed puzzle, and the box borders '|', '-' and '+'
+     * are skipped. Each symbol from value_to_symbol() is a value and any
+     * other character an empty cell. Returns an error if the number of cells
+     * doesn't match.
+     */
+    pub fn parse(&self, text: &str) -> Result<Vec<u8>, QQWingError> {
+        let cells: Vec<u8> = text
+            .chars()
+            .filter(|&c| !c.is_whitespace() && !"|-+".contains(c))
+            .map(|c| self.grid.symbol_to_value(c).unwrap_or(0))
+            .collect();
+        if cells.len() != self.get_cell_count() {
+            return Err(QQWingError::InvalidPuzzle {
+                expected: self.get_cell_count(),
+                found: cells.len(),
+            });
+        }
+        Ok(cells)
+    }
+
+    /**
+     * Whether a box of the canvas, counted in boxes from the top left, is
+     * part of some grid. Grids line up with each other's boxes, so a box is
+     * either wholly in the puzzle or not at all.
+     */
+    fn is_box_used(&self, box_row: usize, box_column: usize) -> bool {
+        let row = box_row * self.grid.get_box_rows();
+        let column = box_column * self.grid.get_box_columns();
+        row < self.height && column < self.width && self.canvas[row * self.width + column].is_some()
+    }
+}
+
+///
+/// A composite puzzle, such as a Samurai, and its solution. Solving and
+/// counting search the whole puzzle at once, so that a value placed in a
+/// shared box rules that value out in the rows, columns and boxes of every
+/// grid the box is in.
+///
+#[derive(Debug, Clone)]
+pub struct Composite {
+    layout: CompositeLayout,
+
+    /**
+     * The givens, and the solution once solved, one value per cell with 0
+     * for empty cells.
+     */
+    puzzle: Vec<u8>,
+    solution: Vec<u8>,
+
+    /**
+     * The style with which to print puzzles and solutions
+     */
+    pub print_style: PrintStyle,
+}
+
+impl Composite {
+    pub fn new(layout: CompositeLayout) -> Self {
+        let cell_count = layout.get_cell_count();
+        Self {
+            layout,
+            puzzle: vec![0; cell_count],
+            solution: vec![0; cell_count],
+            print_style: PrintStyle::READABLE,
+        }
+    }
+
+    pub fn get_layout(&self) -> &CompositeLayout {
+        &self.layout
+    }
+
+    /**
+     * Set the givens, one value per cell with 0 for empty cells. Returns an
+     * error if the puzzle is malformed or two givens conflict.
+     */
+    pub fn set_puzzle(&mut self, puzzle: Vec<u8>) -> Result<(), QQWingError> {
+        if puzzle.len() != self.layout.get_cell_count() {
+            return Err(QQWingError::InvalidPuzzle {
+                expected: self.layout.get_cell_count(),
+                found: puzzle.len(),
+            });
+        }
+        let size = self.layout.grid.get_size();
+        let mut unit_values = vec![0u32; self.layout.units.len()];
+        for (position, &value) in puzzle.iter().enumerate() {
+            if value as usize > size {
+                return Err(QQWingError::InvalidValue { position, value });
+            }
+            if value == 0 {
+                continue;
+            }
+            let value_bit = 1 << (value - 1);
+            let units = &self.layout.cell_units[position];
+            if units.iter().any(|&unit| unit_values[unit] & value_bit != 0) {
+                return Err(QQWingError::PositionImpossible {
+                    position,
+                    value,
+                    round: 0,
+                });
+            }
+            for &unit in units {
+                unit_values[unit] |= value_bit;
+            }
+        }
+        self.solution.clone_from(&puzzle);
+        self.puzzle = puzzle;
+        Ok(())
+    }
+
+    pub fn get_puzzle(&self) -> &[u8] {
+        &self.puzzle
+    }
+
+    pub fn get_solution(&self) -> &[u8] {
+        &self.solution
+    }
+
+    /**
+     * The givens of one grid as a 9x9 puzzle.
+     */
+    pub fn get_grid_puzzle(&self, grid: usize) -> Vec<u8> {
+        self.layout
+            .grid_cells(grid)
+            .iter()
+            .map(|&cell| self.puzzle[cell])
+            .collect()
+    }
+
+    /**
+     * Solve the puzzle, returning whether it has a solution.
+     */
+    pub fn solve(&mut self) -> bool {
+        let mut counter = self.solution_counter(&self.puzzle);
+        counter.count(1);
+        match counter.get_solution().map(<[u8]>::to_vec) {
+            Some(solution) => {
+                self.solution = solution;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /**
+     * Count the solutions of the puzzle, but return two any time there are
+     * two or more.
+     */
+    pub fn count_solutions_limited(&self) -> u32 {
+        self.solution_counter(&self.puzzle).count(2)
+    }
+
+    /**
+     * A solution counter over the rows, columns and boxes of every grid at
+     * once, starting from one value per cell.
+     */
+    fn solution_counter(&self, cells: &[u8]) -> SolutionCounter<'_> {
+        SolutionCounter::with_units(&self.layout.grid, &self.layout.units, cells)
+    }
+
+    /// Generate a new composite puzzle with a unique solution.
+    pub fn generate_puzzle(&mut self) -> Result<(), QQWingError> {
+        self.generate_puzzle_with_options(&GenerateOptions::default())
+    }
+
+    /**
+     * Fill a random grid across all the grids and take givens out of it, in
+     * random order, for as long as the whole puzzle is quickly shown to
+     * stay unique. Only the time limit and cancellation token of the
+     * options can be used. Returns QQWingError::UnsupportedCompositeOption
+     * if any other option is set, QQWingError::BudgetExhausted or
+     * QQWingError::Cancelled if generation is stopped, or
+     * QQWingError::UnsatisfiableLayout if the grids can't be filled in,
+     * leaving the puzzle empty.
+     */
+    pub fn generate_puzzle_with_options(
+        &mut self,
+        options: &GenerateOptions,
+    ) -> Result<(), QQWingError> {
+        let unsupported = [
+            (options.difficulty != Difficulty::UNKNOWN, "difficulty"),
+            (options.symmetry != Symmetry::NONE, "symmetry"),
+            (options.killer, "killer"),
+            (options.kropki, "kropki"),
+            (options.xv, "xv"),
+            (options.greater_than, "greater_than"),
+            (options.parity, "parity"),
+            (options.max_attempts.is_some(), "max_attempts"),
+        ]
+        .into_iter()
+        .find_map(|(used, option)| used.then_some(option));
+        if let Some(option) = unsupported {
+            return Err(QQWingError::UnsupportedCompositeOption(option));
+        }
+        let budget = Budget::new(options);
+        let result = self.remove_givens(&budget);
+        if result.is_err() {
+            self.puzzle.fill(0);
+        }
+        self.solution.clone_from(&self.puzzle);
+        result
+    }
+
+    fn remove_givens(&mut self, budget: &Budget) -> Result<(), QQWingError> {
+        let empty = vec![0; self.layout.get_cell_count()];
+        let mut fill = self.solution_counter(&empty);
+        fill.set_random(true);
+        fill.count(1);
+        self.puzzle = fill
+            .get_solution()
+            .ok_or(QQWingError::UnsatisfiableLayout)?
+            .to_vec();
+
+        let mut order: Vec<usize> = (0..self.puzzle.len()).collect();
+        order.shuffle(&mut thread_rng());
+        for position in order {
+            budget.check()?;
+            let saved_value = self.puzzle[position];
+            self.puzzle[position] = 0;
+            // As for single boards, a count that runs too long counts as
+            // not unique.
+            let mut counter = self.solution_counter(&self.puzzle);
+            counter.set_node_limit(UNIQUE_NODE_LIMIT);
+            if counter.count(2) != 1 || counter.is_exhausted() {
+                self.puzzle[position] = saved_value;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_print_style(&mut self, print_style: PrintStyle) {
+        self.print_style = print_style;
+    }
+
+    /**
+     * Print the puzzle.
+     */
+    pub fn print_puzzle(&self) {
+        println!("{}", self.values_to_string(&self.puzzle));
+    }
+
+    /**
+     * Print the solution.
+     */
+    pub fn print_solution(&self) {
+        println!("{}", self.values_to_string(&self.solution));
+    }
+
+    /**
+     * Write one value per cell in the print style. The one line styles list
+     * the cells in reading order of the canvas, COMPACT draws the canvas with
+     * a space for squares in no grid, and READABLE also draws the borders of
+     * the boxes that are in the puzzle.
+     */
+    pub fn values_to_string(&self, values: &[u8]) -> String {
+        let layout = &self.layout;
+        let grid = &layout.grid;
+        let mut sb = String::new();
+        if self.print_style == PrintStyle::ONELINE || self.print_style == PrintStyle::CSV {
+            sb.extend(values.iter().map(|&value| grid.value_to_symbol(value)));
+            if self.print_style == PrintStyle::CSV {
+                sb.push(',');
+            }
+            return sb;
+        }
+        let readable = self.print_style == PrintStyle::READABLE;
+        let box_rows = grid.get_box_rows();
+        let box_columns = grid.get_box_columns();
+        let box_row_count = layout.height / box_rows;
+        let box_column_count = layout.width / box_columns;
+        for row in 0..layout.height {
+            let mut line = String::new();
+            for column in 0..layout.width {
+                let symbol = match layout.canvas[row * layout.width + column] {
+                    Some(cell) => grid.value_to_symbol(values[cell]),
+                    None => ' ',
+                };
+                if readable {
+                    line.push(' ');
+                }
+                line.push(symbol);
+                let box_column = column / box_columns;
+                if readable
+                    && column % box_columns == box_columns - 1
+                    && box_column + 1 < box_column_count
+                {
+                    let box_row = row / box_rows;
+                    let border = layout.is_box_used(box_row, box_column)
+                        || layout.is_box_used(box_row, box_column + 1);
+                    line.push_str(if border { " |" } else { "  " });
+                }
+            }
+            sb.push_str(line.trim_end());
+            sb.push_str(NL);
+            let box_row = row / box_rows;
+            if readable && row % box_rows == box_rows - 1 && box_row + 1 < box_row_count {
+                let used = |box_row: usize, box_column: usize| {
+                    layout.is_box_used(box_row, box_column)
+                        || layout.is_box_used(box_row + 1, box_column)
+                };
+                let mut line = String::new();
+                for box_column in 0..box_column_count {
+                    let segment = if used(box_row, box_column) { '-' } else { ' ' };
+                    line.extend(std::iter::repeat_n(segment, 2 * box_columns + 1));
+                    if box_column + 1 < box_column_count {
+                        let corner = used(box_row, box_column) || used(box_row, box_column + 1);
+                        line.push(if corner { '|' } else { ' ' });
+                    }
+                }
+                sb.push_str(line.trim_end());
+                sb.push_str(NL);
+            }
+        }
+        sb
+    }
+}
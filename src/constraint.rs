@@ -0,0 +1,81 @@
+use std::fmt;
+
+use crate::layout::Layout;
+
+///
+/// A rule of the caller's own on top of the rules qqwing knows about,
+/// registered with QQWing::add_constraint(). The solver, the solution
+/// counter and so the generator all keep to every registered constraint.
+/// Every method has a default that adds nothing, so a constraint only needs
+/// to implement the hooks it uses. Values are passed as one bit per value,
+/// bit 0 for the value 1, and cells by their position in reading order.
+///
+pub trait Constraint: Send + Sync {
+    /**
+     * The cells, besides those in the same row, column, section and extra
+     * units, that may not hold the same value as the cell. Peers are made
+     * mutual, so a constraint only has to name each pair once.
+     */
+    fn peers(&self, _layout: &Layout, _cell: usize) -> Vec<usize> {
+        Vec::new()
+    }
+
+    /**
+     * The values a cell may take before anything is placed. Bits for values
+     * above the size of the board are ignored.
+     */
+    fn restrict(&self, _layout: &Layout, _cell: usize) -> u32 {
+        u32::MAX
+    }
+
+    /**
+     * The values to take out of the possibilities of other cells once a
+     * value has been placed in a cell, as pairs of a cell and the values to
+     * remove. The grid holds the value in each cell with 0 for empty cells,
+     * and the possibilities the values each empty cell may still take, 0 for
+     * the cells that are filled. Removing every possibility of a cell tells
+     * the search it has gone wrong.
+     */
+    fn propagate(
+        &self,
+        _layout: &Layout,
+        _grid: &[u8],
+        _possibilities: &[u32],
+        _cell: usize,
+    ) -> Vec<(usize, u32)> {
+        Vec::new()
+    }
+}
+
+impl fmt::Debug for dyn Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Constraint")
+    }
+}
+
+/**
+ * The peers each cell has from the constraints, each pair both ways round
+ * and named once.
+ */
+pub(crate) fn constraint_peers(
+    layout: &Layout,
+    constraints: &[Box<dyn Constraint>],
+) -> Vec<Vec<usize>> {
+    let board_size = layout.get_board_size();
+    let mut peers = vec![Vec::new(); board_size];
+    for constraint in constraints {
+        for cell in 0..board_size {
+            for peer in constraint.peers(layout, cell) {
+                if peer < board_size && peer != cell {
+                    peers[cell].push(peer);
+                    peers[peer].push(cell);
+                }
+            }
+        }
+    }
+    for cell_peers in peers.iter_mut() {
+        cell_peers.sort_unstable();
+        cell_peers.dedup();
+    }
+    peers
+}
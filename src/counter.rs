@@ -0,0 +1,599 @@
+use rand::{seq::SliceRandom, thread_rng};
+
+use crate::clues::Clue;
+use crate::constraint::Constraint;
+use crate::killer::{max_sum, min_sum, Cage};
+use crate::layout::Layout;
+use crate::pairs::PairRule;
+
+///
+/// A bare backtracking search over the board, kept apart from the logical
+/// solver so that uniqueness checks don't pay for the solving techniques at
+/// every node. Each row, column, section and extra unit keeps a mask of the
+/// values placed in it, so that the values left for a cell are found with a
+/// few bitwise operations, along with the values of any cells a ruled out
+/// knight's or king's move away. The units may instead be given by the
+/// caller, as for the overlapping grids of a composite puzzle. Placing a value narrows what the rules
+/// between pairs of cells leave its partners, so that a non-consecutive
+/// neighbour loses the values either side of it straight away. Clues such
+/// as thermometers are pruned afresh at every node,
+/// which also checks them once the grid is full, and the caller's
+/// constraints are asked what each placement rules out. The search branches on the
+/// empty cell with the fewest values left, unless some value has only one
+/// place left in a unit, and gives up on a branch as soon as a value has no
+/// place left in a unit.
+///
+#[derive(Debug, Clone)]
+pub(crate) struct SolutionCounter<'a> {
+    layout: &'a Layout,
+
+    /**
+     * The value in each cell, 0 where empty.
+     */
+    cells: Vec<u8>,
+
+    /**
+     * The values each cell may take regardless of its units, one bit per
+     * value.
+     */
+    allowed: Vec<u32>,
+
+    /**
+     * The cells of each unit, the units each cell is in and the values
+     * placed in each unit, one bit per value.
+     */
+    units: Vec<&'a [usize]>,
+    cell_units: Vec<Vec<usize>>,
+    unit_values: Vec<u32>,
+
+    /**
+     * Whether the layout's ruled out knight's and king's moves apply, which
+     * they don't to units of the caller's own.
+     */
+    moves: bool,
+
+    /**
+     * The killer cage each cell is in, if any, and for each cage the values
+     * placed in it, the sum its empty cells still have to make and how many
+     * of them there are.
+     */
+    cell_cages: Vec<Option<usize>>,
+    cage_values: Vec<u32>,
+    cage_sums: Vec<u32>,
+    cage_empty: Vec<usize>,
+
+    /**
+     * The rules between pairs of cells, and the rules each cell is in.
+     */
+    pair_rules: &'a [PairRule],
+    cell_pair_rules: Vec<Vec<usize>>,
+
+    /**
+     * The clues and the cells of each.
+     */
+    clues: &'a [Clue],
+    clue_cells: Vec<Vec<usize>>,
+
+    /**
+     * The constraints of the caller's own, and the peers each cell has from
+     * them.
+     */
+    constraints: &'a [Box<dyn Constraint>],
+    constraint_peers: &'a [Vec<usize>],
+
+    /**
+     * The values the pair rules, clues and constraints leave each cell at
+     * the current node, one bit per value, empty when there are none.
+     */
+    limits: Vec<u32>,
+
+    /**
+     * False when the starting grid repeats a value in a unit or breaks a
+     * cage.
+     */
+    consistent: bool,
+
+    /**
+     * The number of cells the search may fill in before giving up, 0 for no
+     * limit, and the number it has filled in so far.
+     */
+    max_nodes: u64,
+    nodes: u64,
+
+    /**
+     * Whether to try the values of a cell in random order, so that a search
+     * from an empty grid fills in a random solution.
+     */
+    random: bool,
+
+    /**
+     * The first solution found.
+     */
+    solution: Option<Vec<u8>>,
+}
+
+impl<'a> SolutionCounter<'a> {
+    /**
+     * Start a search from one value per cell, 0 for empty ones.
+     */
+    pub(crate) fn new(layout: &'a Layout, grid: &[u8]) -> Self {
+        let allowed = vec![layout.all_values(); layout.get_board_size()];
+        Self::with_allowed(layout, grid, &allowed)
+    }
+
+    /**
+     * Start a search from one value per cell where each empty cell may only
+     * take the values in its allowed mask.
+     */
+    pub(crate) fn with_allowed(layout: &'a Layout, grid: &[u8], allowed: &[u32]) -> Self {
+        Self::start(layout, layout.units().collect(), true, grid, allowed)
+    }
+
+    /**
+     * Start a search over cells in units of the caller's own, one value per
+     * cell, 0 for empty ones, with the values of the layout. The layout's
+     * own units and moves don't apply, so it only gives the values.
+     */
+    pub(crate) fn with_units(layout: &'a Layout, units: &'a [Vec<usize>], grid: &[u8]) -> Self {
+        let allowed = vec![layout.all_values(); grid.len()];
+        let units = units.iter().map(|unit| unit.as_slice()).collect();
+        Self::start(layout, units, false, grid, &allowed)
+    }
+
+    fn start(
+        layout: &'a Layout,
+        units: Vec<&'a [usize]>,
+        moves: bool,
+        grid: &[u8],
+        allowed: &[u32],
+    ) -> Self {
+        let cell_count = allowed.len();
+        let mut cell_units = vec![Vec::new(); cell_count];
+        for (index, unit) in units.iter().enumerate() {
+            for &position in unit.iter() {
+                cell_units[position].push(index);
+            }
+        }
+        let mut counter = Self {
+            layout,
+            cells: vec![0; cell_count],
+            allowed: allowed.to_vec(),
+            unit_values: vec![0; units.len()],
+            units,
+            cell_units,
+            moves,
+            cell_cages: vec![None; cell_count],
+            cage_values: Vec::new(),
+            cage_sums: Vec::new(),
+            cage_empty: Vec::new(),
+            pair_rules: &[],
+            cell_pair_rules: Vec::new(),
+            clues: &[],
+            clue_cells: Vec::new(),
+            constraints: &[],
+            constraint_peers: &[],
+            limits: Vec::new(),
+            consistent: true,
+            max_nodes: 0,
+            nodes: 0,
+            random: false,
+            solution: None,
+        };
+        for (position, &value) in grid.iter().enumerate().take(cell_count) {
+            if value == 0 {
+                continue;
+            }
+            if counter.placed_values(position) & (1 << (value - 1)) != 0 {
+                counter.consistent = false;
+            }
+            counter.place(position, value);
+        }
+        counter
+    }
+
+    /**
+     * Make the search keep to killer cages as well as the units.
+     */
+    pub(crate) fn set_cages(&mut self, cages: &[Cage]) {
+        self.cage_values = vec![0; cages.len()];
+        self.cage_sums = vec![0; cages.len()];
+        self.cage_empty = vec![0; cages.len()];
+        for (index, cage) in cages.iter().enumerate() {
+            let mut placed_sum = 0;
+            for &position in cage.get_cells() {
+                self.cell_cages[position] = Some(index);
+                let value = self.cells[position];
+                if value == 0 {
+                    self.cage_empty[index] += 1;
+                    continue;
+                }
+                if self.cage_values[index] & (1 << (value - 1)) != 0 {
+                    self.consistent = false;
+                }
+                self.cage_values[index] |= 1 << (value - 1);
+                placed_sum += value as u32;
+            }
+            match cage.get_sum().checked_sub(placed_sum) {
+                Some(left) if self.cage_empty[index] > 0 || left == 0 => {
+                    self.cage_sums[index] = left;
+                }
+                _ => self.consistent = false,
+            }
+        }
+    }
+
+    /**
+     * Make the search keep to rules between pairs of cells as well. Each
+     * cell is limited to the values that fit some value of its partner, or
+     * the value placed there.
+     */
+    pub(crate) fn set_pair_rules(&mut self, rules: &'a [PairRule]) {
+        self.cell_pair_rules = vec![Vec::new(); self.cells.len()];
+        self.pair_rules = rules;
+        if rules.is_empty() {
+            return;
+        }
+        self.start_limits();
+        for (index, rule) in rules.iter().enumerate() {
+            let [first, second] = rule.get_cells();
+            self.cell_pair_rules[first].push(index);
+            self.cell_pair_rules[second].push(index);
+            let (a, b) = (self.cells[first], self.cells[second]);
+            if a != 0 && b != 0 && rule.support(first, 1 << (a - 1)) & (1 << (b - 1)) == 0 {
+                self.consistent = false;
+            }
+            for (position, partner) in [(first, second), (second, first)] {
+                let left = match self.cells[partner] {
+                    0 => self.allowed[partner],
+                    value => 1 << (value - 1),
+                };
+                self.limits[position] &= rule.support(partner, left);
+            }
+        }
+    }
+
+    /**
+     * Make the search keep to thermometers, arrows and sandwich sums as
+     * well.
+     */
+    pub(crate) fn set_clues(&mut self, clues: &'a [Clue]) {
+        self.clue_cells = clues
+            .iter()
+            .map(|clue| clue.rule().cells(self.layout))
+            .collect();
+        self.clues = clues;
+        self.start_limits();
+    }
+
+    /**
+     * Make the search keep to constraints of the caller's own as well, with
+     * the peers each cell has from them. What the givens rule out is taken
+     * out straight away.
+     */
+    pub(crate) fn set_constraints(
+        &mut self,
+        constraints: &'a [Box<dyn Constraint>],
+        peers: &'a [Vec<usize>],
+    ) {
+        self.constraints = constraints;
+        self.constraint_peers = peers;
+        if constraints.is_empty() {
+            return;
+        }
+        self.start_limits();
+        for (position, cell_peers) in peers.iter().enumerate() {
+            let value = self.cells[position];
+            if value == 0 {
+                continue;
+            }
+            let restricted = constraints
+                .iter()
+                .any(|c| c.restrict(self.layout, position) & (1 << (value - 1)) == 0);
+            let repeated = cell_peers.iter().any(|&peer| self.cells[peer] == value);
+            if restricted || repeated {
+                self.consistent = false;
+            }
+            self.propagate_constraints(position);
+        }
+    }
+
+    fn start_limits(&mut self) {
+        if self.limits.is_empty() {
+            self.limits = vec![self.layout.all_values(); self.cells.len()];
+        }
+    }
+
+    /**
+     * Narrow the limits of the partners of a cell by what the rules between
+     * them leave once a value has been placed in it.
+     */
+    fn propagate_pair_rules(&mut self, position: usize, value: u8) {
+        for &rule in &self.cell_pair_rules[position] {
+            let rule = &self.pair_rules[rule];
+            self.limits[rule.partner(position)] &= rule.support(position, 1 << (value - 1));
+        }
+    }
+
+    /**
+     * Narrow the limits by what the constraints rule out once a value has
+     * been placed in a cell.
+     */
+    fn propagate_constraints(&mut self, position: usize) {
+        let possibilities: Vec<u32> = (0..self.cells.len())
+            .map(|p| match self.cells[p] {
+                0 => self.free_values(p),
+                _ => 0,
+            })
+            .collect();
+        for constraint in self.constraints {
+            let eliminations =
+                constraint.propagate(self.layout, &self.cells, &possibilities, position);
+            for (cell, values) in eliminations {
+                if let Some(limit) = self.limits.get_mut(cell) {
+                    *limit &= !values;
+                }
+            }
+        }
+    }
+
+    /**
+     * Give up the search after filling in this many cells, 0 for no limit.
+     * A search that gives up reports the solutions found so far, and
+     * is_exhausted() tells it apart from a complete one.
+     */
+    pub(crate) fn set_node_limit(&mut self, max_nodes: u64) {
+        self.max_nodes = max_nodes;
+    }
+
+    pub(crate) fn is_exhausted(&self) -> bool {
+        self.max_nodes != 0 && self.nodes >= self.max_nodes
+    }
+
+    /**
+     * Try the values of each cell in random order, so that counting one
+     * solution from an empty grid fills in a random one.
+     */
+    pub(crate) fn set_random(&mut self, random: bool) {
+        self.random = random;
+    }
+
+    /**
+     * The first solution found, if any.
+     */
+    pub(crate) fn get_solution(&self) -> Option<&[u8]> {
+        self.solution.as_deref()
+    }
+
+    /**
+     * Count the solutions, stopping once limit of them have been found. A
+     * limit of zero counts every solution.
+     */
+    pub(crate) fn count(&mut self, limit: u32) -> u32 {
+        if !self.consistent {
+            return 0;
+        }
+        let mut solutions = 0;
+        self.search(limit, &mut solutions);
+        solutions
+    }
+
+    fn search(&mut self, limit: u32, solutions: &mut u32) {
+        if self.clues.is_empty() {
+            self.search_node(limit, solutions);
+            return;
+        }
+        // What the clues leave a cell here still holds further down, so the
+        // limits only narrow until the search backs out of this node.
+        let saved = self.limits.clone();
+        if self.prune_clues() {
+            self.search_node(limit, solutions);
+        }
+        self.limits = saved;
+    }
+
+    /**
+     * Narrow the values each clue leaves its cells to those that fit some
+     * way of keeping to it. Returns false if some clue can't be kept.
+     */
+    fn prune_clues(&mut self) -> bool {
+        for clue in 0..self.clues.len() {
+            let mut values: Vec<u32> = self.clue_cells[clue]
+                .iter()
+                .map(|&position| match self.cells[position] {
+                    0 => self.free_values(position),
+                    value => 1 << (value - 1),
+                })
+                .collect();
+            if !self.clues[clue].rule().prune(self.layout, &mut values) {
+                return false;
+            }
+            for (i, value) in values.into_iter().enumerate() {
+                let position = self.clue_cells[clue][i];
+                self.limits[position] &= value;
+            }
+        }
+        true
+    }
+
+    fn search_node(&mut self, limit: u32, solutions: &mut u32) {
+        let board_size = self.cells.len();
+        let mut best_position = board_size;
+        let mut best_values = 0;
+        let mut best_count = u32::MAX;
+        for position in 0..board_size {
+            if self.cells[position] != 0 {
+                continue;
+            }
+            let values = self.free_values(position);
+            let count = values.count_ones();
+            if count < best_count {
+                best_position = position;
+                best_values = values;
+                best_count = count;
+                if count <= 1 {
+                    break;
+                }
+            }
+        }
+        if best_position == board_size {
+            *solutions += 1;
+            if self.solution.is_none() {
+                self.solution = Some(self.cells.clone());
+            }
+            return;
+        }
+        if best_count > 1 {
+            match self.find_hidden_single() {
+                Some(Some((position, value_bit))) => {
+                    best_position = position;
+                    best_values = value_bit;
+                }
+                Some(None) => {}
+                None => return,
+            }
+        }
+
+        let mut values: Vec<u8> = (0..u32::BITS)
+            .filter(|&bit| best_values & (1 << bit) != 0)
+            .map(|bit| bit as u8 + 1)
+            .collect();
+        if self.random {
+            values.shuffle(&mut thread_rng());
+        }
+        for value in values {
+            if self.is_exhausted() {
+                return;
+            }
+            self.nodes += 1;
+            self.place(best_position, value);
+            if self.pair_rules.is_empty() && self.constraints.is_empty() {
+                self.search(limit, solutions);
+            } else {
+                let saved = self.limits.clone();
+                self.propagate_pair_rules(best_position, value);
+                if !self.constraints.is_empty() {
+                    self.propagate_constraints(best_position);
+                }
+                self.search(limit, solutions);
+                self.limits = saved;
+            }
+            self.unplace(best_position, value);
+            if limit != 0 && *solutions >= limit {
+                return;
+            }
+        }
+    }
+
+    /**
+     * Look for a value with only one place left in a unit, returning that
+     * place and the value's bit. Returns None if some value has no place
+     * left in a unit, so the branch has no solutions.
+     */
+    fn find_hidden_single(&self) -> Option<Option<(usize, u32)>> {
+        let all_values = self.layout.all_values();
+        let mut found = None;
+        for (&placed, &cells) in self.unit_values.iter().zip(&self.units) {
+            let mut once = 0;
+            let mut more = 0;
+            for &position in cells {
+                if self.cells[position] == 0 {
+                    let values = self.free_values(position);
+                    more |= once & values;
+                    once |= values;
+                }
+            }
+            if (placed | once) != all_values {
+                return None;
+            }
+            let single = once & !more;
+            if found.is_none() && single != 0 {
+                let value_bit = single & single.wrapping_neg();
+                found = cells
+                    .iter()
+                    .copied()
+                    .find(|&position| {
+                        self.cells[position] == 0 && self.free_values(position) & value_bit != 0
+                    })
+                    .map(|position| (position, value_bit));
+            }
+        }
+        Some(found)
+    }
+
+    fn free_values(&self, position: usize) -> u32 {
+        let mut free = self.allowed[position] & !self.placed_values(position);
+        if let Some(cage) = self.cell_cages[position] {
+            free &= self.cage_range(cage);
+        }
+        if let Some(&limit) = self.limits.get(position) {
+            free &= limit;
+        }
+        free
+    }
+
+    /**
+     * The values that could go in an empty cell of a cage and still leave a
+     * sum the other empty cells can make. At the last empty cell that is
+     * only the value that completes the sum.
+     */
+    fn cage_range(&self, cage: usize) -> u32 {
+        let all_values = self.layout.all_values();
+        let free = all_values & !self.cage_values[cage];
+        let others = self.cage_empty[cage] - 1;
+        let left = self.cage_sums[cage];
+        let low = min_sum(free, others);
+        if low > left {
+            return 0;
+        }
+        let smallest = left.saturating_sub(max_sum(free, others)).max(1);
+        let largest = (left - low).min(self.layout.get_size() as u32);
+        if smallest > largest {
+            return 0;
+        }
+        free & ((1 << largest) - 1) & !((1 << (smallest - 1)) - 1)
+    }
+
+    fn placed_values(&self, position: usize) -> u32 {
+        let mut placed = 0;
+        for &unit in &self.cell_units[position] {
+            placed |= self.unit_values[unit];
+        }
+        let move_peers: &[usize] = match self.moves {
+            true => self.layout.move_peers(position),
+            false => &[],
+        };
+        let constraint_peers = self.constraint_peers.get(position).into_iter().flatten();
+        for &peer in move_peers.iter().chain(constraint_peers) {
+            if self.cells[peer] != 0 {
+                placed |= 1 << (self.cells[peer] - 1);
+            }
+        }
+        placed
+    }
+
+    fn place(&mut self, position: usize, value: u8) {
+        let value_bit = 1 << (value - 1);
+        self.cells[position] = value;
+        for &unit in &self.cell_units[position] {
+            self.unit_values[unit] |= value_bit;
+        }
+        if let Some(cage) = self.cell_cages[position] {
+            self.cage_values[cage] |= value_bit;
+            self.cage_sums[cage] -= value as u32;
+            self.cage_empty[cage] -= 1;
+        }
+    }
+
+    fn unplace(&mut self, position: usize, value: u8) {
+        let value_bit = !(1 << (value - 1));
+        self.cells[position] = 0;
+        for &unit in &self.cell_units[position] {
+            self.unit_values[unit] &= value_bit;
+        }
+        if let Some(cage) = self.cell_cages[position] {
+            self.cage_values[cage] &= value_bit;
+            self.cage_sums[cage] += value as u32;
+            self.cage_empty[cage] += 1;
+        }
+    }
+}
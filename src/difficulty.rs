@@ -8,4 +8,3 @@ pub enum Difficulty {
     MEDIUM,
     EXPERT,
 }
-
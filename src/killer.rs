@@ -0,0 +1,310 @@
+use std::collections::{HashMap, HashSet};
+
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng};
+
+use crate::layout::Layout;
+use crate::QQWingError;
+
+///
+/// A killer cage: a group of cells whose values add up to a sum, with no
+/// value repeated inside the cage.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cage {
+    /**
+     * The cells of the cage, in reading order.
+     */
+    cells: Vec<usize>,
+
+    /**
+     * The sum of the values in the cage.
+     */
+    sum: u32,
+}
+
+impl Cage {
+    pub fn new(mut cells: Vec<usize>, sum: u32) -> Self {
+        cells.sort_unstable();
+        Self { cells, sum }
+    }
+
+    pub fn get_cells(&self) -> &[usize] {
+        &self.cells
+    }
+
+    pub fn get_sum(&self) -> u32 {
+        self.sum
+    }
+}
+
+/**
+ * Read cages from text, one cage per line or separated by ';'. Each cage is
+ * its sum, a ':' and its cells written as r1c1 and separated by spaces or
+ * commas, such as "10: r1c1, r1c2". Blank lines are skipped. Returns an
+ * error if a cage can't be read or doesn't fit the board, see check_cages().
+ */
+pub fn parse_cages(layout: &Layout, text: &str) -> Result<Vec<Cage>, QQWingError> {
+    let mut cages = Vec::new();
+    for definition in text.split(['\n', ';']).map(str::trim) {
+        if definition.is_empty() {
+            continue;
+        }
+        let syntax = || QQWingError::CageSyntax(definition.to_string());
+        let (sum, cells) = definition.split_once(':').ok_or_else(syntax)?;
+        let sum = sum.trim().parse().map_err(|_| syntax())?;
+        let cells = cells
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|word| !word.is_empty())
+            .map(|word| parse_cell(layout, word).ok_or_else(syntax))
+            .collect::<Result<Vec<usize>, QQWingError>>()?;
+        cages.push(Cage::new(cells, sum));
+    }
+    check_cages(layout, &cages)?;
+    Ok(cages)
+}
+
+/**
+ * Read a cell written as r1c1, numbered from 1.
+ */
+pub(crate) fn parse_cell(layout: &Layout, word: &str) -> Option<usize> {
+    let word = word.to_ascii_lowercase();
+    let (row, column) = word.strip_prefix('r')?.split_once('c')?;
+    let row: usize = row.parse().ok()?;
+    let column: usize = column.parse().ok()?;
+    let size = layout.get_size();
+    if row == 0 || column == 0 || row > size || column > size {
+        return None;
+    }
+    Some(layout.row_column_to_cell(row - 1, column - 1))
+}
+
+/**
+ * Write cages one per line in the form read by parse_cages().
+ */
+pub fn cages_to_string(layout: &Layout, cages: &[Cage]) -> String {
+    let lines: Vec<String> = cages
+        .iter()
+        .map(|cage| {
+            let cells: Vec<String> = cage
+                .cells
+                .iter()
+                .map(|&cell| {
+                    format!(
+                        "r{}c{}",
+                        layout.cell_to_row(cell) + 1,
+                        layout.cell_to_column(cell) + 1
+                    )
+                })
+                .collect();
+            format!("{}: {}", cage.sum, cells.join(","))
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/**
+ * Check that every cage has cells on the board that are in no other cage,
+ * no more cells than there are values, and a sum that that many different
+ * values can make.
+ */
+pub(crate) fn check_cages(layout: &Layout, cages: &[Cage]) -> Result<(), QQWingError> {
+    let mut caged = vec![false; layout.get_board_size()];
+    for (index, cage) in cages.iter().enumerate() {
+        let fits = !cage.cells.is_empty()
+            && cage.cells.len() <= layout.get_size()
+            && min_sum(layout.all_values(), cage.cells.len()) <= cage.sum
+            && cage.sum <= max_sum(layout.all_values(), cage.cells.len());
+        if !fits {
+            return Err(QQWingError::InvalidCage(index + 1));
+        }
+        for &cell in &cage.cells {
+            if cell >= caged.len() || caged[cell] {
+                return Err(QQWingError::InvalidCage(index + 1));
+            }
+            caged[cell] = true;
+        }
+    }
+    Ok(())
+}
+
+/**
+ * The sum of the values in a set of values, one bit per value.
+ */
+pub(crate) fn mask_sum(values: u32) -> u32 {
+    let mut sum = 0;
+    let mut bits = values;
+    while bits != 0 {
+        sum += bits.trailing_zeros() + 1;
+        bits &= bits - 1;
+    }
+    sum
+}
+
+/**
+ * The smallest sum of count different values from a set of values, or
+ * u32::MAX if the set has fewer values than that.
+ */
+pub(crate) fn min_sum(values: u32, count: usize) -> u32 {
+    if (values.count_ones() as usize) < count {
+        return u32::MAX;
+    }
+    let mut bits = values;
+    let mut sum = 0;
+    for _ in 0..count {
+        sum += bits.trailing_zeros() + 1;
+        bits &= bits - 1;
+    }
+    sum
+}
+
+/**
+ * The largest sum of count different values from a set of values, or 0 if
+ * the set has fewer values than that.
+ */
+pub(crate) fn max_sum(values: u32, count: usize) -> u32 {
+    if (values.count_ones() as usize) < count {
+        return 0;
+    }
+    let mut bits = values;
+    let mut sum = 0;
+    for _ in 0..count {
+        let high = 31 - bits.leading_zeros();
+        sum += high + 1;
+        bits &= !(1 << high);
+    }
+    sum
+}
+
+///
+/// The ways the empty cells of a cage can still be filled in: the values
+/// each cell can take in at least one of them, and the values that every
+/// one of them uses.
+///
+#[derive(Debug, Clone)]
+pub(crate) struct CageFit {
+    pub(crate) values: Vec<u32>,
+    pub(crate) required: u32,
+}
+
+/**
+ * Work out how a group of empty cells can be filled with different values
+ * that add up to a sum, each cell taking one of its candidates and no cell
+ * taking a value in used. Returns None if they can't be.
+ */
+pub(crate) fn fit_cage(candidates: &[u32], used: u32, sum: u32) -> Option<CageFit> {
+    let mut memo = HashMap::new();
+    if !fits(candidates, 0, used, sum, &mut memo) {
+        return None;
+    }
+    // Walk the ways that work, visiting each partial filling once.
+    let mut values = vec![0; candidates.len()];
+    let mut required = u32::MAX;
+    let mut seen = HashSet::new();
+    let mut stack = vec![(0, used, sum)];
+    while let Some((index, used, left)) = stack.pop() {
+        if index == candidates.len() {
+            required &= used;
+            continue;
+        }
+        let mut bits = candidates[index] & !used;
+        while bits != 0 {
+            let value_bit = bits & bits.wrapping_neg();
+            bits &= bits - 1;
+            let value = value_bit.trailing_zeros() + 1;
+            if value > left
+                || !fits(
+                    candidates,
+                    index + 1,
+                    used | value_bit,
+                    left - value,
+                    &mut memo,
+                )
+            {
+                continue;
+            }
+            values[index] |= value_bit;
+            if seen.insert((index + 1, used | value_bit)) {
+                stack.push((index + 1, used | value_bit, left - value));
+            }
+        }
+    }
+    Some(CageFit {
+        values,
+        required: required & !used,
+    })
+}
+
+/**
+ * Whether the cells from index on can be filled in with values not in used
+ * that add up to left. The sum left follows from the values used, so
+ * (index, used) is enough to remember the answer by.
+ */
+fn fits(
+    candidates: &[u32],
+    index: usize,
+    used: u32,
+    left: u32,
+    memo: &mut HashMap<(usize, u32), bool>,
+) -> bool {
+    let count = candidates.len() - index;
+    if count == 0 {
+        return left == 0;
+    }
+    let free = !used & candidates[index..].iter().fold(0, |all, &c| all | c);
+    if left < min_sum(free, count) || left > max_sum(free, count) {
+        return false;
+    }
+    if let Some(&known) = memo.get(&(index, used)) {
+        return known;
+    }
+    let mut bits = candidates[index] & !used;
+    let mut found = false;
+    while bits != 0 && !found {
+        let value_bit = bits & bits.wrapping_neg();
+        bits &= bits - 1;
+        let value = value_bit.trailing_zeros() + 1;
+        found = value <= left && fits(candidates, index + 1, used | value_bit, left - value, memo);
+    }
+    memo.insert((index, used), found);
+    found
+}
+
+/**
+ * Split a filled grid into random cages of joined up cells, mostly of two
+ * to four cells, with no value repeated in a cage.
+ */
+pub(crate) fn carve_cages(layout: &Layout, grid: &[u8]) -> Vec<Cage> {
+    let mut rng = thread_rng();
+    let board_size = layout.get_board_size();
+    let mut caged = vec![false; board_size];
+    let mut order: Vec<usize> = (0..board_size).collect();
+    order.shuffle(&mut rng);
+    let mut cages = Vec::new();
+    for start in order {
+        if caged[start] {
+            continue;
+        }
+        let target = rng.gen_range(2..=5).min(layout.get_size());
+        let mut cells = vec![start];
+        let mut values = 1u32 << (grid[start] - 1);
+        caged[start] = true;
+        while cells.len() < target {
+            let growable: Vec<usize> = cells
+                .iter()
+                .flat_map(|&cell| layout.neighbors(cell))
+                .filter(|&n| !caged[n] && values & (1 << (grid[n] - 1)) == 0)
+                .collect();
+            let Some(&next) = growable.choose(&mut rng) else {
+                break;
+            };
+            caged[next] = true;
+            values |= 1 << (grid[next] - 1);
+            cells.push(next);
+        }
+        let sum = cells.iter().map(|&cell| grid[cell] as u32).sum();
+        cages.push(Cage::new(cells, sum));
+    }
+    cages.sort_by_key(|cage| cage.cells[0]);
+    cages
+}
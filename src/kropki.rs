@@ -0,0 +1,194 @@
+use rand::random;
+
+use crate::killer::parse_cell;
+use crate::layout::Layout;
+use crate::logtype::LogType;
+use crate::pairs::{adjacent_pairs, PairRule};
+use crate::QQWingError;
+
+/// The colour of a Kropki dot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DotColor {
+    /// The two values are consecutive, such as 4 and 5.
+    White,
+    /// One value is double the other, such as 3 and 6.
+    Black,
+}
+
+///
+/// A Kropki dot on the edge between two cells next to each other.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dot {
+    /**
+     * The two cells, in reading order.
+     */
+    cells: [usize; 2],
+
+    color: DotColor,
+}
+
+impl Dot {
+    pub fn new(first: usize, second: usize, color: DotColor) -> Self {
+        Self {
+            cells: [first.min(second), first.max(second)],
+            color,
+        }
+    }
+
+    pub fn get_cells(&self) -> [usize; 2] {
+        self.cells
+    }
+
+    pub fn get_color(&self) -> DotColor {
+        self.color
+    }
+}
+
+/**
+ * Read dots from text, one dot per line or separated by ';'. Each dot is w
+ * for white or b for black, a ':' and its two cells written as r1c1 and
+ * separated by spaces or commas, such as "w: r1c1, r1c2". Blank lines are
+ * skipped. Returns an error if a dot can't be read or doesn't fit the
+ * board, see check_dots().
+ */
+pub fn parse_dots(layout: &Layout, text: &str) -> Result<Vec<Dot>, QQWingError> {
+    let mut dots = Vec::new();
+    for definition in text.split(['\n', ';']).map(str::trim) {
+        if definition.is_empty() {
+            continue;
+        }
+        let syntax = || QQWingError::DotSyntax(definition.to_string());
+        let (color, cells) = definition.split_once(':').ok_or_else(syntax)?;
+        let color = match color.trim().to_ascii_lowercase().as_str() {
+            "w" => DotColor::White,
+            "b" => DotColor::Black,
+            _ => return Err(syntax()),
+        };
+        let cells = cells
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|word| !word.is_empty())
+            .map(|word| parse_cell(layout, word).ok_or_else(syntax))
+            .collect::<Result<Vec<usize>, QQWingError>>()?;
+        let [first, second] = cells[..] else {
+            return Err(syntax());
+        };
+        dots.push(Dot::new(first, second, color));
+    }
+    check_dots(layout, &dots)?;
+    Ok(dots)
+}
+
+/**
+ * Write dots one per line in the form read by parse_dots().
+ */
+pub fn dots_to_string(layout: &Layout, dots: &[Dot]) -> String {
+    let lines: Vec<String> = dots
+        .iter()
+        .map(|dot| {
+            let [first, second] = dot.cells;
+            format!(
+                "{}: r{}c{},r{}c{}",
+                match dot.color {
+                    DotColor::White => "w",
+                    DotColor::Black => "b",
+                },
+                layout.cell_to_row(first) + 1,
+                layout.cell_to_column(first) + 1,
+                layout.cell_to_row(second) + 1,
+                layout.cell_to_column(second) + 1
+            )
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/**
+ * Check that every dot is between two cells next to each other, and that
+ * no two dots are between the same cells.
+ */
+pub(crate) fn check_dots(layout: &Layout, dots: &[Dot]) -> Result<(), QQWingError> {
+    for (index, dot) in dots.iter().enumerate() {
+        let [first, second] = dot.cells;
+        let fits = second < layout.get_board_size()
+            && layout.neighbors(first).contains(&second)
+            && !dots[..index].iter().any(|other| other.cells == dot.cells);
+        if !fits {
+            return Err(QQWingError::InvalidDot(index + 1));
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Put a dot between every two cells next to each other in a filled grid
+ * whose values are consecutive or one double the other. A pair that is
+ * both, 1 and 2, gets a dot of either colour.
+ */
+pub(crate) fn place_dots(layout: &Layout, grid: &[u8]) -> Vec<Dot> {
+    adjacent_pairs(layout)
+        .filter_map(|(first, second)| {
+            let (a, b) = (grid[first] as u32, grid[second] as u32);
+            let color = match (consecutive(a, b), double(a, b)) {
+                (true, true) if random() => DotColor::Black,
+                (true, _) => DotColor::White,
+                (false, true) => DotColor::Black,
+                (false, false) => return None,
+            };
+            Some(Dot::new(first, second, color))
+        })
+        .collect()
+}
+
+/**
+ * The rules the dots set, and with negative set, the rule that cells next
+ * to each other without a dot are neither consecutive nor one double the
+ * other.
+ */
+pub(crate) fn dot_rules(layout: &Layout, dots: &[Dot], negative: bool) -> Vec<PairRule> {
+    let mut rules: Vec<PairRule> = dots
+        .iter()
+        .map(|dot| {
+            let [first, second] = dot.cells;
+            match dot.color {
+                DotColor::White => {
+                    PairRule::new(layout, first, second, LogType::KropkiDot, consecutive)
+                }
+                DotColor::Black => PairRule::new(layout, first, second, LogType::KropkiDot, double),
+            }
+        })
+        .collect();
+    if negative {
+        rules.extend(
+            adjacent_pairs(layout)
+                .filter(|&(first, second)| !dots.iter().any(|dot| dot.cells == [first, second]))
+                .map(|(first, second)| {
+                    PairRule::new(layout, first, second, LogType::KropkiDot, |a, b| {
+                        !consecutive(a, b) && !double(a, b)
+                    })
+                }),
+        );
+    }
+    rules
+}
+
+/**
+ * The rules that cells next to each other may not hold consecutive values.
+ */
+pub(crate) fn non_consecutive_rules(layout: &Layout) -> Vec<PairRule> {
+    adjacent_pairs(layout)
+        .map(|(first, second)| {
+            PairRule::new(layout, first, second, LogType::NonConsecutive, |a, b| {
+                !consecutive(a, b)
+            })
+        })
+        .collect()
+}
+
+fn consecutive(a: u32, b: u32) -> bool {
+    a.abs_diff(b) == 1
+}
+
+fn double(a: u32, b: u32) -> bool {
+    a == 2 * b || b == 2 * a
+}
@@ -0,0 +1,826 @@
+use rand::{thread_rng, Rng};
+
+use crate::counter::SolutionCounter;
+use crate::{QQWingError, BOARD_SIZE};
+
+/// Largest number of values a board can have, so that every value has a
+/// symbol and a bit in a u32 mask.
+pub const MAX_SIZE: usize = 25;
+
+/// Symbols used to read and print values, 1-9 followed by letters.
+const SYMBOLS: &[u8; MAX_SIZE] = b"123456789ABCDEFGHIJKLMNOP";
+
+/// Region maps with_random_regions() tries before giving up, as the rules
+/// on top of the regions may leave no map that can be filled in.
+const RANDOM_REGION_TRIES: usize = 100;
+
+///
+/// The shape of a board: how many rows and columns its boxes have, and so
+/// how many values, rows, columns and sections there are. A board with boxes
+/// of 2 rows by 3 columns is a 6x6 board with the values 1-6, one with boxes
+/// of 3 rows by 4 columns a 12x12 board with the values 1-12. Jigsaw boards
+/// replace the boxes with irregular regions given by a region map. Extra
+/// units that must also hold every value can be added on top: the two main
+/// diagonals of Sudoku-X, the four windows of Windoku or regions of the
+/// caller's own. Anti-knight and anti-king boards also keep a value away
+/// from cells a chess knight's or king's move from it, and non-consecutive
+/// boards keep consecutive values apart. The cells of each unit are worked
+/// out once when the layout is made.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    /**
+     * The number of rows in each box.
+     */
+    box_rows: usize,
+
+    /**
+     * The number of columns in each box.
+     */
+    box_columns: usize,
+
+    /**
+     * The number of values, which is also the number of cells in each row,
+     * column and section.
+     */
+    size: usize,
+
+    /**
+     * Whether the sections are irregular regions rather than boxes.
+     */
+    jigsaw: bool,
+
+    /**
+     * The section each cell is in.
+     */
+    cell_sections: Vec<usize>,
+
+    /**
+     * The cells of each row, column and section, in reading order.
+     */
+    rows: Vec<Vec<usize>>,
+    columns: Vec<Vec<usize>>,
+    sections: Vec<Vec<usize>>,
+
+    /**
+     * The cells of the units on top of the rows, columns and sections: the
+     * two main diagonals on a Sudoku-X board, top left to bottom right
+     * first, followed by any extra regions.
+     */
+    extra_units: Vec<Vec<usize>>,
+
+    /**
+     * How many of the extra units are diagonals, 2 or 0.
+     */
+    diagonal_count: usize,
+
+    /**
+     * The extra units each cell is in.
+     */
+    cell_extra_units: Vec<Vec<usize>>,
+
+    /**
+     * Whether a value may not be a chess knight's move, or a king's move,
+     * away from the same value.
+     */
+    anti_knight: bool,
+    anti_king: bool,
+
+    /**
+     * The cells a knight's or king's move away from each cell, for the
+     * moves that are ruled out.
+     */
+    cell_move_peers: Vec<Vec<usize>>,
+
+    /**
+     * Whether cells next to each other in a row or column may not hold
+     * consecutive values.
+     */
+    non_consecutive: bool,
+}
+
+impl Default for Layout {
+    /**
+     * The standard 9x9 board with 3x3 boxes.
+     */
+    fn default() -> Self {
+        Self::boxes(3, 3)
+    }
+}
+
+impl Layout {
+    /**
+     * A board whose boxes have the given number of rows and columns. Returns
+     * an error if either is less than 2, which would make the boxes the same
+     * as rows or columns, or the board would have more than MAX_SIZE values.
+     */
+    pub fn new(box_rows: usize, box_columns: usize) -> Result<Self, QQWingError> {
+        if box_rows < 2 || box_columns < 2 || box_rows * box_columns > MAX_SIZE {
+            return Err(QQWingError::InvalidLayout(box_rows, box_columns));
+        }
+        Ok(Self::boxes(box_rows, box_columns))
+    }
+
+    /**
+     * A board with the given number of values, with boxes as close to square
+     * as the size allows and never taller than they are wide: 2x2 for 4, 2x3
+     * for 6, 3x3 for 9, 3x4 for 12, 4x4 for 16 and 5x5 for 25. Returns an
+     * error for sizes, such as primes, that can't be split into boxes.
+     */
+    pub fn for_size(size: usize) -> Result<Self, QQWingError> {
+        let (box_rows, box_columns) = box_shape(size);
+        Self::new(box_rows, box_columns)
+    }
+
+    /**
+     * A board with the given number of cells, which must be a square.
+     */
+    pub fn for_board_size(board_size: usize) -> Result<Self, QQWingError> {
+        Self::for_size(size_of_board(board_size)?)
+    }
+
+    /**
+     * A jigsaw board whose sections are given by a region map, holding the
+     * region (0 to one less than the size) of each cell. Returns an error if
+     * the map isn't the size of a board, or doesn't split it into as many
+     * regions as there are values with as many cells each.
+     */
+    pub fn with_regions(regions: &[usize]) -> Result<Self, QQWingError> {
+        // The regions don't need the board to split into boxes, so any size
+        // will do.
+        let size = size_of_board(regions.len())?;
+        let mut region_sizes = vec![0; size];
+        for &region in regions {
+            if region >= size {
+                return Err(QQWingError::InvalidRegions(size));
+            }
+            region_sizes[region] += 1;
+        }
+        if region_sizes.iter().any(|&cells| cells != size) {
+            return Err(QQWingError::InvalidRegions(size));
+        }
+        let (box_rows, box_columns) = box_shape(size);
+        Ok(Self::build(box_rows, box_columns, regions.to_vec(), true))
+    }
+
+    /**
+     * Read a region map from text, one character per cell where cells with
+     * the same character are in the same region. Whitespace is ignored, so
+     * the map can be written one row per line.
+     */
+    pub fn parse_regions(text: &str) -> Result<Self, QQWingError> {
+        let mut labels = Vec::new();
+        let regions: Vec<usize> = text
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| {
+                labels.iter().position(|&l| l == c).unwrap_or_else(|| {
+                    labels.push(c);
+                    labels.len() - 1
+                })
+            })
+            .collect();
+        let expected = size_of_board(regions.len()).map_or(BOARD_SIZE, |size| size * size);
+        if regions.len() != expected {
+            return Err(QQWingError::InvalidRegionMap {
+                expected,
+                found: regions.len(),
+            });
+        }
+        Self::with_regions(&regions)
+    }
+
+    /**
+     * A jigsaw board with random connected regions that can be filled in.
+     * The regions start out as the boxes of for_size() and are reshaped by
+     * swapping cells across their borders.
+     */
+    pub fn random_regions(size: usize) -> Result<Self, QQWingError> {
+        Self::for_size(size)?.with_random_regions()
+    }
+
+    /**
+     * The same board with its sections reshaped into random connected
+     * regions, keeping any extra units, such that it can still be filled
+     * in. Returns an error if no such regions turn up after a number of
+     * tries, as with rules no regions can satisfy.
+     */
+    pub fn with_random_regions(&self) -> Result<Self, QQWingError> {
+        let board_size = self.get_board_size();
+        let mut rng = thread_rng();
+        for _ in 0..RANDOM_REGION_TRIES {
+            let mut regions = self.cell_sections.clone();
+            for _ in 0..board_size * 8 {
+                let cell = rng.gen_range(0..board_size);
+                let neighbors = self.neighbors(cell);
+                let neighbor = neighbors[rng.gen_range(0..neighbors.len())];
+                let (from, to) = (regions[cell], regions[neighbor]);
+                if from == to {
+                    continue;
+                }
+                // Move the cell across the border and a cell of the other
+                // region back the other way, so both keep their size.
+                let back: Vec<usize> = (0..board_size)
+                    .filter(|&other| {
+                        other != cell
+                            && regions[other] == to
+                            && self
+                                .neighbors(other)
+                                .iter()
+                                .any(|&n| n != cell && regions[n] == from)
+                    })
+                    .collect();
+                if back.is_empty() {
+                    continue;
+                }
+                let other = back[rng.gen_range(0..back.len())];
+                regions[cell] = to;
+                regions[other] = from;
+                if !self.is_connected(&regions, from) || !self.is_connected(&regions, to) {
+                    regions[cell] = from;
+                    regions[other] = to;
+                }
+            }
+            // Some region maps can't be filled in at all. Rather than prove
+            // it, give up on maps that aren't filled in quickly.
+            let mut layout = Self::build(self.box_rows, self.box_columns, regions, true);
+            layout.extra_units.clone_from(&self.extra_units);
+            layout.diagonal_count = self.diagonal_count;
+            layout.index_extra_units();
+            layout.anti_knight = self.anti_knight;
+            layout.anti_king = self.anti_king;
+            layout.cell_move_peers.clone_from(&self.cell_move_peers);
+            layout.non_consecutive = self.non_consecutive;
+            let empty = vec![0; board_size];
+            let mut counter = SolutionCounter::new(&layout, &empty);
+            counter.set_node_limit(board_size as u64 * 100);
+            if counter.count(1) == 1 {
+                return Ok(layout);
+            }
+        }
+        Err(QQWingError::NoRandomRegions(RANDOM_REGION_TRIES))
+    }
+
+    fn boxes(box_rows: usize, box_columns: usize) -> Self {
+        let size = box_rows * box_columns;
+        let cell_sections = (0..size * size)
+            .map(|cell| {
+                let row = cell / size;
+                let column = cell % size;
+                row / box_rows * box_rows + column / box_columns
+            })
+            .collect();
+        Self::build(box_rows, box_columns, cell_sections, false)
+    }
+
+    fn build(box_rows: usize, box_columns: usize, cell_sections: Vec<usize>, jigsaw: bool) -> Self {
+        let size = box_rows * box_columns;
+        let board_size = size * size;
+        let rows = (0..size)
+            .map(|row| (0..size).map(|column| row * size + column).collect())
+            .collect();
+        let columns = (0..size)
+            .map(|column| (0..size).map(|row| row * size + column).collect())
+            .collect();
+        let sections = (0..size)
+            .map(|section| {
+                (0..board_size)
+                    .filter(|&cell| cell_sections[cell] == section)
+                    .collect()
+            })
+            .collect();
+        Self {
+            box_rows,
+            box_columns,
+            size,
+            jigsaw,
+            cell_sections,
+            rows,
+            columns,
+            sections,
+            extra_units: Vec::new(),
+            diagonal_count: 0,
+            cell_extra_units: vec![Vec::new(); board_size],
+            anti_knight: false,
+            anti_king: false,
+            cell_move_peers: vec![Vec::new(); board_size],
+            non_consecutive: false,
+        }
+    }
+
+    /**
+     * The same board with the two main diagonals as extra units, as in
+     * Sudoku-X.
+     */
+    pub fn with_diagonals(mut self) -> Self {
+        if self.diagonal_count > 0 {
+            return self;
+        }
+        let size = self.size;
+        let diagonals = [
+            (0..size).map(|i| i * size + i).collect(),
+            (0..size).map(|i| i * size + size - 1 - i).collect(),
+        ];
+        self.extra_units.splice(0..0, diagonals);
+        self.diagonal_count = 2;
+        self.index_extra_units();
+        self
+    }
+
+    /**
+     * The same board with the windows of Windoku (Hyper Sudoku) as extra
+     * regions: boxes set in by one cell from the edges and from each other,
+     * four of them on a 9x9 board.
+     */
+    pub fn with_windows(mut self) -> Self {
+        let across = (self.size - 1) / (self.box_columns + 1);
+        let down = (self.size - 1) / (self.box_rows + 1);
+        for window_row in 0..down {
+            for window_column in 0..across {
+                let top = 1 + window_row * (self.box_rows + 1);
+                let left = 1 + window_column * (self.box_columns + 1);
+                let cells = (0..self.box_rows)
+                    .flat_map(|row| {
+                        (0..self.box_columns)
+                            .map(move |column| (top + row) * self.size + left + column)
+                    })
+                    .collect();
+                self.extra_units.push(cells);
+            }
+        }
+        self.index_extra_units();
+        self
+    }
+
+    /**
+     * The same board with an extra region of the caller's own, which must
+     * hold every value like a row, column or section does. Returns an error
+     * if the region doesn't have as many different cells of the board as
+     * there are values.
+     */
+    pub fn with_extra_region(mut self, cells: &[usize]) -> Result<Self, QQWingError> {
+        let mut cells = cells.to_vec();
+        cells.sort_unstable();
+        cells.dedup();
+        if cells.len() != self.size || cells.iter().any(|&cell| cell >= self.get_board_size()) {
+            return Err(QQWingError::InvalidExtraRegion(self.size));
+        }
+        self.extra_units.push(cells);
+        self.index_extra_units();
+        Ok(self)
+    }
+
+    /**
+     * The same board with extra regions read from a map, one character per
+     * cell as for parse_regions(), where cells with the same character are
+     * in the same extra region and '.' marks a cell in none. Returns an
+     * error if the map isn't the size of the board or a region doesn't have
+     * as many cells as there are values.
+     */
+    pub fn with_extra_region_map(self, text: &str) -> Result<Self, QQWingError> {
+        let map: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+        if map.len() != self.get_board_size() {
+            return Err(QQWingError::InvalidRegionMap {
+                expected: self.get_board_size(),
+                found: map.len(),
+            });
+        }
+        let mut labels: Vec<char> = map.iter().copied().filter(|&c| c != '.').collect();
+        labels.sort_unstable();
+        labels.dedup();
+        labels.into_iter().try_fold(self, |layout, label| {
+            let cells: Vec<usize> = (0..map.len()).filter(|&cell| map[cell] == label).collect();
+            layout.with_extra_region(&cells)
+        })
+    }
+
+    /**
+     * The same board where no value may be a chess knight's move away from
+     * the same value.
+     */
+    pub fn with_anti_knight(mut self) -> Self {
+        self.anti_knight = true;
+        self.index_move_peers();
+        self
+    }
+
+    /**
+     * The same board where no value may be a chess king's move away from
+     * the same value, so not even diagonally next to it.
+     */
+    pub fn with_anti_king(mut self) -> Self {
+        self.anti_king = true;
+        self.index_move_peers();
+        self
+    }
+
+    /**
+     * The same board where cells next to each other in a row or column may
+     * not hold consecutive values.
+     */
+    pub fn with_non_consecutive(mut self) -> Self {
+        self.non_consecutive = true;
+        self
+    }
+
+    /**
+     * Work out the cells a ruled out move away from each cell.
+     */
+    fn index_move_peers(&mut self) {
+        let mut moves: Vec<(isize, isize)> = Vec::new();
+        if self.anti_knight {
+            moves.extend([
+                (-2, -1),
+                (-2, 1),
+                (-1, -2),
+                (-1, 2),
+                (1, -2),
+                (1, 2),
+                (2, -1),
+                (2, 1),
+            ]);
+        }
+        if self.anti_king {
+            moves.extend([
+                (-1, -1),
+                (-1, 0),
+                (-1, 1),
+                (0, -1),
+                (0, 1),
+                (1, -1),
+                (1, 0),
+                (1, 1),
+            ]);
+        }
+        let size = self.size as isize;
+        for cell in 0..self.get_board_size() {
+            let row = self.cell_to_row(cell) as isize;
+            let column = self.cell_to_column(cell) as isize;
+            self.cell_move_peers[cell] = moves
+                .iter()
+                .map(|&(down, across)| (row + down, column + across))
+                .filter(|&(r, c)| (0..size).contains(&r) && (0..size).contains(&c))
+                .map(|(r, c)| (r * size + c) as usize)
+                .collect();
+        }
+    }
+
+    /**
+     * Work out which extra units each cell is in.
+     */
+    fn index_extra_units(&mut self) {
+        for units in &mut self.cell_extra_units {
+            units.clear();
+        }
+        for (unit, cells) in self.extra_units.iter().enumerate() {
+            for &cell in cells {
+                self.cell_extra_units[cell].push(unit);
+            }
+        }
+    }
+
+    /**
+     * The cells next to a cell, above, below, left and right of it.
+     */
+    pub(crate) fn neighbors(&self, cell: usize) -> Vec<usize> {
+        let row = self.cell_to_row(cell);
+        let column = self.cell_to_column(cell);
+        let mut neighbors = Vec::with_capacity(4);
+        if row > 0 {
+            neighbors.push(cell - self.size);
+        }
+        if row + 1 < self.size {
+            neighbors.push(cell + self.size);
+        }
+        if column > 0 {
+            neighbors.push(cell - 1);
+        }
+        if column + 1 < self.size {
+            neighbors.push(cell + 1);
+        }
+        neighbors
+    }
+
+    /**
+     * Whether the cells of a region in a region map all join up.
+     */
+    fn is_connected(&self, regions: &[usize], region: usize) -> bool {
+        let Some(start) = regions.iter().position(|&r| r == region) else {
+            return true;
+        };
+        let mut seen = vec![false; regions.len()];
+        let mut stack = vec![start];
+        seen[start] = true;
+        let mut reached = 0;
+        while let Some(cell) = stack.pop() {
+            reached += 1;
+            for neighbor in self.neighbors(cell) {
+                if !seen[neighbor] && regions[neighbor] == region {
+                    seen[neighbor] = true;
+                    stack.push(neighbor);
+                }
+            }
+        }
+        reached == regions.iter().filter(|&&r| r == region).count()
+    }
+
+    pub fn get_box_rows(&self) -> usize {
+        self.box_rows
+    }
+
+    pub fn get_box_columns(&self) -> usize {
+        self.box_columns
+    }
+
+    /**
+     * Whether the sections are the irregular regions of a jigsaw board.
+     */
+    pub fn is_jigsaw(&self) -> bool {
+        self.jigsaw
+    }
+
+    /**
+     * Whether the two main diagonals are units, as in Sudoku-X.
+     */
+    pub fn has_diagonals(&self) -> bool {
+        self.diagonal_count > 0
+    }
+
+    /**
+     * Whether no value may be a chess knight's move away from the same
+     * value.
+     */
+    pub fn has_anti_knight(&self) -> bool {
+        self.anti_knight
+    }
+
+    /**
+     * Whether no value may be a chess king's move away from the same value.
+     */
+    pub fn has_anti_king(&self) -> bool {
+        self.anti_king
+    }
+
+    /**
+     * Whether cells next to each other in a row or column may not hold
+     * consecutive values.
+     */
+    pub fn has_non_consecutive(&self) -> bool {
+        self.non_consecutive
+    }
+
+    /**
+     * Whether the board is only rows, columns and boxes, with no irregular
+     * regions, extra units or rules between cells.
+     */
+    pub fn is_plain(&self) -> bool {
+        !self.jigsaw
+            && self.extra_units.is_empty()
+            && !self.anti_knight
+            && !self.anti_king
+            && !self.non_consecutive
+    }
+
+    /**
+     * The cells of the extra regions, such as the windows of Windoku, not
+     * counting the diagonals.
+     */
+    pub fn get_extra_regions(&self) -> &[Vec<usize>] {
+        &self.extra_units[self.diagonal_count..]
+    }
+
+    /**
+     * The section (region) each cell is in.
+     */
+    pub fn get_regions(&self) -> &[usize] {
+        &self.cell_sections
+    }
+
+    /**
+     * The region map as text, one symbol per cell as read by
+     * parse_regions(), with the first region as 1.
+     */
+    pub fn regions_to_string(&self) -> String {
+        self.cell_sections
+            .iter()
+            .map(|&region| self.value_to_symbol(region as u8 + 1))
+            .collect()
+    }
+
+    /**
+     * The number of values, and of cells in each row, column and section.
+     */
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+
+    /**
+     * The number of cells on the board.
+     */
+    pub fn get_board_size(&self) -> usize {
+        self.size * self.size
+    }
+
+    /**
+     * Every value set, one bit per value.
+     */
+    pub(crate) fn all_values(&self) -> u32 {
+        (1 << self.size) - 1
+    }
+
+    /**
+     * The symbol a value is printed with: 1-9, then A for 10, B for 11 and
+     * so on, or '.' for an empty cell.
+     */
+    pub fn value_to_symbol(&self, value: u8) -> char {
+        match value {
+            0 => '.',
+            v => SYMBOLS
+                .get(v as usize - 1)
+                .map_or('?', |&symbol| symbol as char),
+        }
+    }
+
+    /**
+     * The value (1 to the size) a symbol stands for, ignoring case, or None
+     * if it isn't a value on this board.
+     */
+    pub fn symbol_to_value(&self, symbol: char) -> Option<u8> {
+        let symbol = symbol.to_ascii_uppercase() as u32;
+        SYMBOLS[..self.size]
+            .iter()
+            .position(|&s| s as u32 == symbol)
+            .map(|index| index as u8 + 1)
+    }
+
+    /**
+     * Read the cells of a board from text. Text without spaces or commas is
+     * read one character per cell, where each symbol from
+     * value_to_symbol() is a value and any other character an empty cell.
+     * Text with spaces or commas is read one word per cell so that values
+     * above 9 can also be written as numbers: a number or symbol is a value,
+     * a word of '|', '-' and '+' marking box borders is skipped and anything
+     * else is an empty cell. Returns an error if the number of cells is not
+     * the board size, or a number is too big for the board.
+     */
+    pub fn parse(&self, text: &str) -> Result<Vec<u8>, QQWingError> {
+        let text = text.trim();
+        let mut cells = Vec::with_capacity(self.get_board_size());
+        if text.contains(|c: char| c.is_whitespace() || c == ',') {
+            for word in text.split(|c: char| c.is_whitespace() || c == ',') {
+                if word.is_empty()
+                    || word == "|"
+                    || (word.len() > 1 && word.chars().all(|c| "|-+".contains(c)))
+                {
+                    continue;
+                }
+                let value = match word.parse::<usize>() {
+                    Ok(value) if value > self.size => {
+                        return Err(QQWingError::InvalidValue {
+                            position: cells.len(),
+                            value: value.min(u8::MAX as usize) as u8,
+                        });
+                    }
+                    Ok(value) => value as u8,
+                    Err(_) => {
+                        let mut chars = word.chars();
+                        match (chars.next(), chars.next()) {
+                            (Some(symbol), None) => self.symbol_to_value(symbol).unwrap_or(0),
+                            _ => 0,
+                        }
+                    }
+                };
+                cells.push(value);
+            }
+        } else {
+            cells.extend(text.chars().map(|c| self.symbol_to_value(c).unwrap_or(0)));
+        }
+        if cells.len() != self.get_board_size() {
+            return Err(QQWingError::InvalidPuzzle {
+                expected: self.get_board_size(),
+                found: cells.len(),
+            });
+        }
+        Ok(cells)
+    }
+
+    pub(crate) fn cell_to_row(&self, cell: usize) -> usize {
+        cell / self.size
+    }
+
+    pub(crate) fn cell_to_column(&self, cell: usize) -> usize {
+        cell % self.size
+    }
+
+    pub(crate) fn cell_to_section(&self, cell: usize) -> usize {
+        self.cell_sections[cell]
+    }
+
+    pub(crate) fn row_column_to_cell(&self, row: usize, column: usize) -> usize {
+        row * self.size + column
+    }
+
+    /**
+     * The cells (in reading order) of a row.
+     */
+    pub(crate) fn row_cells(&self, row: usize) -> &[usize] {
+        &self.rows[row]
+    }
+
+    /**
+     * The cells (in reading order) of a column.
+     */
+    pub(crate) fn column_cells(&self, column: usize) -> &[usize] {
+        &self.columns[column]
+    }
+
+    /**
+     * The cells (in reading order) of a section.
+     */
+    pub(crate) fn section_cells(&self, section: usize) -> &[usize] {
+        &self.sections[section]
+    }
+
+    /**
+     * The cells of every unit: the rows, then the columns, the sections and
+     * the extra units.
+     */
+    pub(crate) fn units(&self) -> impl Iterator<Item = &[usize]> {
+        self.rows
+            .iter()
+            .chain(&self.columns)
+            .chain(&self.sections)
+            .chain(&self.extra_units)
+            .map(|unit| unit.as_slice())
+    }
+
+    /**
+     * The number of extra units, diagonals and extra regions together.
+     */
+    pub(crate) fn get_extra_unit_count(&self) -> usize {
+        self.extra_units.len()
+    }
+
+    /**
+     * The cells that may not hold the same value as a cell because they are
+     * a ruled out knight's or king's move away from it.
+     */
+    pub(crate) fn move_peers(&self, cell: usize) -> &[usize] {
+        &self.cell_move_peers[cell]
+    }
+
+    /**
+     * The number of diagonals, which come first among the extra units.
+     */
+    pub(crate) fn get_diagonal_count(&self) -> usize {
+        self.diagonal_count
+    }
+
+    /**
+     * Whether an extra unit is one of the diagonals rather than an extra
+     * region.
+     */
+    pub(crate) fn is_diagonal(&self, unit: usize) -> bool {
+        unit < self.diagonal_count
+    }
+
+    /**
+     * The cells (in reading order) of an extra unit.
+     */
+    pub(crate) fn extra_unit_cells(&self, unit: usize) -> &[usize] {
+        &self.extra_units[unit]
+    }
+
+    /**
+     * The extra units a cell is in, none for most cells.
+     */
+    pub(crate) fn cell_to_extra_units(&self, cell: usize) -> &[usize] {
+        &self.cell_extra_units[cell]
+    }
+}
+
+/**
+ * The number of values on a board with the given number of cells, which
+ * must be a square of at most MAX_SIZE.
+ */
+fn size_of_board(board_size: usize) -> Result<usize, QQWingError> {
+    (1..=MAX_SIZE)
+        .find(|size| size * size == board_size)
+        .ok_or(QQWingError::InvalidBoardSize(board_size))
+}
+
+/**
+ * The boxes as close to square as a size allows and never taller than they
+ * are wide, as rows and columns. Sizes that can't be split have boxes of a
+ * single row.
+ */
+// usize::is_multiple_of() needs Rust 1.87.
+#[allow(clippy::manual_is_multiple_of)]
+fn box_shape(size: usize) -> (usize, usize) {
+    let box_rows = (1..=size)
+        .take_while(|rows| rows * rows <= size)
+        .filter(|rows| size % rows == 0)
+        .last()
+        .unwrap_or(1);
+    (box_rows, size / box_rows)
+}
@@ -14,40 +14,186 @@
 //! You should have received a copy of the GNU General Public License along with this program; if not, write to the Free Software Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301 USA.
 
 use rand::{self, random, seq::SliceRandom, thread_rng};
-use std::usize;
+use std::sync::Arc;
+use std::time::Duration;
 use strum::{EnumIter, EnumString};
 use thiserror::Error;
 use tracing::{debug, info};
 
+use clues::Clue;
+use constraint::Constraint;
+use counter::SolutionCounter;
 use difficulty::Difficulty;
+use killer::{Cage, CageFit};
+use kropki::Dot;
+use layout::{Layout, MAX_SIZE};
 use logitem::LogItem;
 use logtype::LogType;
-use symmetry::Symmetry;
-
+use markers::Marker;
+use observer::SolveObserver;
+use options::{Budget, GenerateOptions};
+use pairs::PairRule;
+use parity::Parity;
+use player::PlayerState;
+use symmetry::{Symmetries, Symmetry};
+use transform::Transformation;
+
+/// Module for canonical forms of puzzles.
+pub mod canon;
+/// Module for thermometer, arrow and sandwich clues.
+pub mod clues;
+/// Module for puzzles made of several overlapping grids.
+pub mod composite;
+/// Module for constraints of the caller's own.
+pub mod constraint;
+/// Module for the fast solution counter.
+mod counter;
 /// Module for puzzle difficulty.
 pub mod difficulty;
+/// Module for killer cages.
+pub mod killer;
+/// Module for Kropki dots.
+pub mod kropki;
+/// Module for board layouts.
+pub mod layout;
 /// Module for log item.
 pub mod logitem;
 /// Module for log type.
 pub mod logtype;
+/// Module for XV and greater-than markers.
+pub mod markers;
+/// Module for solver observers.
+pub mod observer;
+/// Module for generation options.
+pub mod options;
+/// Module for rules between pairs of cells.
+mod pairs;
+/// Module for even-odd shading.
+pub mod parity;
+/// Module for a game in progress.
+pub mod player;
+/// Module for board snapshots.
+pub mod state;
+/// Module for taking solve steps one at a time.
+pub mod step;
 /// Module for puzzle symmetry.
 pub mod symmetry;
+/// Module for transformations of a board.
+pub mod transform;
+/// Module for checking a player's grid.
+pub mod validate;
 const UNSET_VALUE: usize = 4294967295;
 const NL: &str = "\n";
-const GRID_SIZE: usize = 3;
-const ROW_COL_SEC_SIZE: usize = GRID_SIZE * GRID_SIZE;
-const SEC_GROUP_SIZE: usize = ROW_COL_SEC_SIZE * GRID_SIZE;
-pub const BOARD_SIZE: usize = ROW_COL_SEC_SIZE * ROW_COL_SEC_SIZE;
-const POSSIBILITY_SIZE: usize = BOARD_SIZE * ROW_COL_SEC_SIZE;
+/// Number of cells on a standard 9x9 board.
+pub const BOARD_SIZE: usize = 81;
+/// Cells a uniqueness check of generation may fill in before giving up.
+const UNIQUE_NODE_LIMIT: u64 = 1000;
+/// Cells a random fill may fill in for each cell of the board before
+/// starting over.
+const FILL_NODE_LIMIT: u64 = 10;
+/// Fewest givens any 9x9 sudoku with a unique solution can have.
+const MIN_GIVENS: usize = 17;
 
 #[derive(Error, Debug)]
 pub enum QQWingError {
-    #[error("Marking position that already has been marked.")]
-    PositionAlreadyMarked,
-    #[error("Marking position that was marked another round.")]
-    PositionMarkedAnotherRound,
-    #[error("Marking impossible position.")]
-    PositionImpossible,
+    #[error("Marking position {position} with {value} in round {round}, but it already has been marked.")]
+    PositionAlreadyMarked {
+        position: usize,
+        value: u8,
+        round: u16,
+    },
+    #[error("Marking position {position} with {value} in round {round}, but it was marked in round {marked_round}.")]
+    PositionMarkedAnotherRound {
+        position: usize,
+        value: u8,
+        round: u16,
+        marked_round: u16,
+    },
+    #[error("Marking position {position} with {value} in round {round}, but that value is impossible there.")]
+    PositionImpossible {
+        position: usize,
+        value: u8,
+        round: u16,
+    },
+    #[error("Puzzle must have {expected} cells, found {found}.")]
+    InvalidPuzzle { expected: usize, found: usize },
+    #[error("Value {value} at position {position} is not a value on the board.")]
+    InvalidValue { position: usize, value: u8 },
+    #[error("Boxes of {0}x{1} don't make a board, boxes need at least 2 rows and columns and the board at most {MAX_SIZE} values.")]
+    InvalidLayout(usize, usize),
+    #[error("A board of {0} cells is not a square of at most {MAX_SIZE}x{MAX_SIZE}.")]
+    InvalidBoardSize(usize),
+    #[error("Region map must have {expected} cells, found {found}.")]
+    InvalidRegionMap { expected: usize, found: usize },
+    #[error("Parity map must have {expected} cells, found {found}.")]
+    InvalidParityMap { expected: usize, found: usize },
+    #[error("A {0}x{0} board needs {0} regions of {0} cells each.")]
+    InvalidRegions(usize),
+    #[error("An extra region must have {0} different cells on the board.")]
+    InvalidExtraRegion(usize),
+    #[error("No random regions that can be filled in were found in {0} tries.")]
+    NoRandomRegions(usize),
+    #[error("The rules of the board leave no way to fill it in.")]
+    UnsatisfiableLayout,
+    #[error("Could not read cage \"{0}\", expected a sum and its cells such as 10: r1c1, r1c2.")]
+    CageSyntax(String),
+    #[error("Cage {0} has no cells, a cell off the board or in another cage, or a sum its cells can't make.")]
+    InvalidCage(usize),
+    #[error("Could not read dot \"{0}\", expected w or b and two cells such as w: r1c1, r1c2.")]
+    DotSyntax(String),
+    #[error("Dot {0} is not between two cells next to each other, or is between the same cells as another dot.")]
+    InvalidDot(usize),
+    #[error(
+        "Could not read marker \"{0}\", expected x, v, > or < and two cells such as x: r1c1, r1c2."
+    )]
+    MarkerSyntax(String),
+    #[error("Marker {0} is not between two cells next to each other, or is between the same cells as another marker.")]
+    InvalidMarker(usize),
+    #[error("Could not read clue \"{0}\", expected thermo: or arrow: and cells such as thermo: r1c1, r1c2, or sandwich: and a row or column and its sum such as sandwich: r1=15.")]
+    ClueSyntax(String),
+    #[error("Clue {0} has a cell off the board or repeated, cells that don't touch the one before, or a sum it can't make.")]
+    InvalidClue(usize),
+    #[error("A composite of {0} grids needs at least one grid, each in its own place and lined up with the boxes of the others.")]
+    InvalidComposite(usize),
+    #[error("Composite puzzles can't be generated with the {0} option.")]
+    UnsupportedCompositeOption(&'static str),
+    #[error("Mask must have {expected} cells, found {found}.")]
+    InvalidMask { expected: usize, found: usize },
+    #[error("Mask has only {givens} givens, at least {needed} are needed for a unique puzzle.")]
+    MaskTooSparse { givens: usize, needed: usize },
+    #[error("Generation budget exhausted after {attempts} attempts in {elapsed:?}.")]
+    BudgetExhausted { attempts: u32, elapsed: Duration },
+    #[error("Generation was cancelled.")]
+    Cancelled,
+    #[error("Canonical forms are only for boards of plain boxes with at most 9 values.")]
+    NoCanonicalForm,
+    #[error("Could not read transformation \"{0}\", expected the order of the rows, columns and values such as t r213456789 c123456789 d987654321.")]
+    TransformationSyntax(String),
+    #[error("The transformation doesn't fit the board, or the board has rules besides its rows, columns and boxes.")]
+    InvalidTransformation,
+    #[error("The snapshot was taken of a board with another layout or other rules.")]
+    StateMismatch,
+}
+
+/// A change made to the board in one round, kept so that it can be undone.
+#[derive(Debug, Clone, Copy)]
+enum Change {
+    /// A value was placed in a cell.
+    Place { round: u16, position: usize },
+    /// Possibilities were removed from a cell, one bit per value.
+    Eliminate {
+        round: u16,
+        position: usize,
+        values: u32,
+    },
+}
+
+impl Change {
+    fn round(&self) -> u16 {
+        match self {
+            Change::Place { round, .. } | Change::Eliminate { round, .. } => *round,
+        }
+    }
 }
 
 /// The board containing all the memory structures and methods for solving or
@@ -57,55 +203,162 @@ pub struct QQWing {
     /**
      * The last round of solving
      */
-    last_solve_round: u8,
+    last_solve_round: u16,
+
+    /**
+     * The shape of the board, shared with snapshots of it.
+     */
+    layout: Arc<Layout>,
+
+    /**
+     * The integers, one per cell, that make up a sudoku puzzle. Givens are
+     * 1 to the size of the board, unknowns are 0. Once initialized, this
+     * puzzle remains as is. The answer is worked out in "solution".
+     */
+    puzzle: Vec<u8>,
+
+    /**
+     * The integers entered by the player on top of the puzzle, one per cell,
+     * where the player has filled in a cell and 0 elsewhere. These are kept
+     * apart from the givens so that the puzzle itself stays as it was set.
+     */
+    entries: Vec<u8>,
 
     /**
-     * The 81 integers that make up a sudoku puzzle. Givens are 1-9, unknowns
-     * are 0. Once initialized, this puzzle remains as is. The answer is worked
-     * out in "solution".
+     * The player's pencil marks, one bit per value for each cell. Bit 0
+     * stands for 1, bit 1 for 2 and so on. None means the player has not
+     * marked the cell and every value is still possible.
      */
-    puzzle: [u8; BOARD_SIZE],
+    candidate_marks: Vec<Option<u32>>,
 
     /**
-     * The 81 integers that make up a sudoku puzzle. The solution is built here,
-     * after completion all will be 1-9.
+     * The integers, one per cell, that make up a sudoku puzzle. The solution
+     * is built here, after completion all will be filled in.
      */
-    solution: [u8; BOARD_SIZE],
+    solution: Vec<u8>,
 
     /**
      * Recursion depth at which each of the numbers in the solution were placed.
      * Useful for backing out solve branches that don't lead to a solution.
      */
-    solution_round: [u8; BOARD_SIZE],
+    solution_round: Vec<u16>,
+
+    /**
+     * The possible values for each square, one bit per value with bit 0 for
+     * 1, bit 1 for 2 and so on. A set bit means the value could still be
+     * filled in according to the Sudoku rules. A square that has been marked
+     * has no possibilities left.
+     */
+    candidates: Vec<u32>,
+
+    /**
+     * The values marked so far in each row, one bit per value.
+     */
+    row_values: Vec<u32>,
+
+    /**
+     * The values marked so far in each column, one bit per value.
+     */
+    column_values: Vec<u32>,
+
+    /**
+     * The values marked so far in each section, one bit per value.
+     */
+    section_values: Vec<u32>,
+
+    /**
+     * The values marked so far in each extra unit, such as the diagonals of
+     * a Sudoku-X board, one bit per value.
+     */
+    extra_values: Vec<u32>,
 
     /**
-     * The 729 integers that make up a the possible values for a Sudoku puzzle.
-     * (9 possibilities for each of 81 squares). If possibilities[i] is zero,
-     * then the possibility could still be filled in according to the Sudoku
-     * rules. When a possibility is eliminated, possibilities[i] is assigned the
-     * round (recursion level) at which it was determined that it could not be a
-     * possibility.
+     * The cages of a killer puzzle, none for other puzzles, and the cage
+     * each cell is in.
      */
-    possibilities: [u8; POSSIBILITY_SIZE],
+    cages: Vec<Cage>,
+    cell_cages: Vec<Option<usize>>,
 
     /**
-     * An array the size of the board (81) containing each of the numbers 0-n
-     * exactly once. This array may be shuffled so that operations that need to
-     * look at each cell can do so in a random order.
+     * The Kropki dots of the puzzle, none for other puzzles, and whether
+     * cells next to each other without a dot are neither consecutive nor
+     * one double the other.
      */
-    random_board_array: [u8; BOARD_SIZE],
+    dots: Vec<Dot>,
+    negative_dots: bool,
 
     /**
-     * An array with one element for each position (9), in some random order to
-     * be used when trying each position in turn during guesses.
+     * The XV and greater-than markers of the puzzle, none for other
+     * puzzles.
      */
-    random_possibility_array: [u8; ROW_COL_SEC_SIZE],
+    markers: Vec<Marker>,
+
+    /**
+     * The rules between pairs of cells set by the layout, the dots and the
+     * markers, and the rules each cell is in.
+     */
+    pair_rules: Vec<PairRule>,
+    cell_pair_rules: Vec<Vec<usize>>,
+
+    /**
+     * The thermometers, arrows and sandwich sums of the puzzle, none for
+     * other puzzles, and the cells of each clue in the order its rule takes
+     * them.
+     */
+    clues: Vec<Clue>,
+    clue_cells: Vec<Vec<usize>>,
+
+    /**
+     * The shading of each cell of an even-odd puzzle, None for cells that
+     * aren't shaded and for every cell of other puzzles.
+     */
+    parity: Vec<Option<Parity>>,
+
+    /**
+     * The constraints registered by the caller, and the peers each cell has
+     * from them.
+     */
+    constraints: Vec<Box<dyn Constraint>>,
+    constraint_peers: Vec<Vec<usize>>,
+
+    /**
+     * Counts the changes to the layout and the rules, so that a snapshot
+     * can tell whether the board still has the rules it was taken with.
+     */
+    rules_version: u64,
+
+    /**
+     * Every value placed and possibility removed, oldest first, along with
+     * the round (recursion level) in which it was done. Rounds are nested, so
+     * backing out a solve branch that doesn't lead to a solution only has to
+     * pop its changes off the end.
+     */
+    trail: Vec<Change>,
+
+    /**
+     * An array the size of the board containing each of the cells exactly
+     * once. This array may be shuffled so that operations that need to look
+     * at each cell can do so in a random order.
+     */
+    random_board_array: Vec<usize>,
+
+    /**
+     * An array with one element for each value index, in some random order to
+     * be used when trying each value in turn during guesses.
+     */
+    random_possibility_array: Vec<usize>,
 
     /**
      * Whether or not to record history
      */
     record_history: bool,
 
+    /**
+     * Whether CSV stats go on past the classic columns to the counts for
+     * the variant rules and the symmetries
+     */
+    extra_stats: bool,
+
     /**
      * Whether or not to print history as it happens
      */
@@ -124,28 +377,109 @@ pub struct QQWing {
      */
     solve_instructions: Vec<LogItem>,
 
+    /**
+     * Receives solve and generation events as they happen, if set.
+     */
+    observer: Option<Box<dyn SolveObserver>>,
+
     /**
      * The style with which to print puzzles and solutions
      */
     pub print_style: PrintStyle,
 }
 
+impl Default for QQWing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl QQWing {
     pub fn new() -> Self {
-        Self {
+        Self::with_layout(Layout::default())
+    }
+
+    /**
+     * A board of the given shape, such as Layout::for_size(6) for 6x6
+     * puzzles with 2x3 boxes.
+     */
+    pub fn with_layout(layout: Layout) -> Self {
+        let mut qqwing = Self {
+            layout: Arc::new(Layout::default()),
             last_solve_round: 0,
-            puzzle: [0; BOARD_SIZE],
-            solution: [0; BOARD_SIZE],
-            solution_round: [0; BOARD_SIZE],
-            possibilities: [0; POSSIBILITY_SIZE],
-            random_possibility_array: core::array::from_fn::<u8, ROW_COL_SEC_SIZE, _>(|i| i as u8),
-            random_board_array: core::array::from_fn::<u8, BOARD_SIZE, _>(|i| i as u8),
+            puzzle: Vec::new(),
+            entries: Vec::new(),
+            candidate_marks: Vec::new(),
+            solution: Vec::new(),
+            solution_round: Vec::new(),
+            candidates: Vec::new(),
+            row_values: Vec::new(),
+            column_values: Vec::new(),
+            section_values: Vec::new(),
+            extra_values: Vec::new(),
+            cages: Vec::new(),
+            cell_cages: Vec::new(),
+            dots: Vec::new(),
+            negative_dots: false,
+            markers: Vec::new(),
+            pair_rules: Vec::new(),
+            cell_pair_rules: Vec::new(),
+            clues: Vec::new(),
+            clue_cells: Vec::new(),
+            parity: Vec::new(),
+            constraints: Vec::new(),
+            constraint_peers: Vec::new(),
+            rules_version: 0,
+            trail: Vec::new(),
+            random_possibility_array: Vec::new(),
+            random_board_array: Vec::new(),
             record_history: false,
+            extra_stats: false,
             log_history: false,
             solve_history: Vec::new(),
             solve_instructions: Vec::new(),
+            observer: None,
             print_style: PrintStyle::READABLE,
-        }
+        };
+        qqwing.set_layout(layout);
+        qqwing
+    }
+
+    /**
+     * Change the shape of the board. This clears the puzzle, its cages,
+     * dots, markers, clues and shading and anything entered on it, but keeps
+     * the registered constraints.
+     */
+    pub fn set_layout(&mut self, layout: Layout) {
+        let size = layout.get_size();
+        let board_size = layout.get_board_size();
+        self.puzzle = vec![0; board_size];
+        self.entries = vec![0; board_size];
+        self.candidate_marks = vec![None; board_size];
+        self.solution = vec![0; board_size];
+        self.solution_round = vec![0; board_size];
+        self.candidates = vec![layout.all_values(); board_size];
+        self.row_values = vec![0; size];
+        self.column_values = vec![0; size];
+        self.section_values = vec![0; size];
+        self.extra_values = vec![0; layout.get_extra_unit_count()];
+        self.cages.clear();
+        self.cell_cages = vec![None; board_size];
+        self.parity = vec![None; board_size];
+        self.trail.clear();
+        self.random_possibility_array = (0..size).collect();
+        self.random_board_array = (0..board_size).collect();
+        self.solve_history.clear();
+        self.solve_instructions.clear();
+        self.layout = Arc::new(layout);
+        self.markers.clear();
+        self.store_dots(Vec::new(), false);
+        self.store_clues(Vec::new());
+        self.store_constraint_peers();
+    }
+
+    pub fn get_layout(&self) -> &Layout {
+        &self.layout
     }
 
     /**
@@ -153,58 +487,478 @@ impl QQWing {
      * out in the solution
      */
     fn get_given_count(&self) -> u32 {
-        let mut count = 0;
-        for i in 0..BOARD_SIZE {
-            if self.puzzle[i] != 0 {
-                count += 1;
+        self.puzzle.iter().filter(|&&value| value != 0).count() as u32
+    }
+
+    /**
+     * Check that a board's worth of values fits the layout, with each value
+     * between 0 for unknown and the size of the board.
+     */
+    fn check_values(&self, values: &[u8]) -> Result<(), QQWingError> {
+        let board_size = self.layout.get_board_size();
+        if values.len() != board_size {
+            return Err(QQWingError::InvalidPuzzle {
+                expected: board_size,
+                found: values.len(),
+            });
+        }
+        match values
+            .iter()
+            .position(|&v| v as usize > self.layout.get_size())
+        {
+            Some(position) => Err(QQWingError::InvalidValue {
+                position,
+                value: values[position],
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /**
+     * Set the board to the given puzzle. The given puzzle must be an array
+     * with one integer per cell, 1 to the size of the board for givens and 0
+     * for unknown. Returns an error if the puzzle is malformed or two givens
+     * conflict.
+     */
+    pub fn set_puzzle(&mut self, init_puzzle: Vec<u8>) -> Result<(), QQWingError> {
+        self.check_values(&init_puzzle)?;
+        self.puzzle = init_puzzle;
+        self.entries.fill(0);
+        self.candidate_marks.fill(None);
+        self.reset()
+    }
+
+    /**
+     * Turn the puzzle into the same puzzle in disguise, moving its givens and
+     * renaming their values by the transformation. Everything else on the
+     * board is transformed alike: the entries and pencil marks, the solution
+     * so far and the solve steps that led to it, so a solved puzzle stays
+     * solved, with the same difficulty. Returns an error if the
+     * transformation doesn't fit the board, or the puzzle has rules besides
+     * its rows, columns and boxes, which the transformation would not carry
+     * over.
+     */
+    pub fn transform(&mut self, transformation: &Transformation) -> Result<(), QQWingError> {
+        let plain = self.layout.is_plain()
+            && self.cages.is_empty()
+            && self.dots.is_empty()
+            && !self.negative_dots
+            && self.markers.is_empty()
+            && self.clues.is_empty()
+            && !self.has_parity()
+            && self.constraints.is_empty();
+        if !plain || !transformation.fits(&self.layout) {
+            return Err(QQWingError::InvalidTransformation);
+        }
+        let layout = Arc::clone(&self.layout);
+        let sources = transformation.sources(&layout);
+        let mut targets = vec![0; sources.len()];
+        for (to, &from) in sources.iter().enumerate() {
+            targets[from] = to;
+        }
+
+        self.puzzle = transformation.apply(&layout, &self.puzzle);
+        self.entries = transformation.apply(&layout, &self.entries);
+        self.solution = transformation.apply(&layout, &self.solution);
+        self.solution_round = sources
+            .iter()
+            .map(|&from| self.solution_round[from])
+            .collect();
+        self.candidates = sources
+            .iter()
+            .map(|&from| transformation.relabel_values(self.candidates[from]))
+            .collect();
+        self.candidate_marks = sources
+            .iter()
+            .map(|&from| {
+                self.candidate_marks[from].map(|marks| transformation.relabel_values(marks))
+            })
+            .collect();
+
+        self.row_values.fill(0);
+        self.column_values.fill(0);
+        self.section_values.fill(0);
+        for (position, &value) in self.solution.iter().enumerate() {
+            if value != 0 {
+                let value_bit = 1 << (value - 1);
+                self.row_values[layout.cell_to_row(position)] |= value_bit;
+                self.column_values[layout.cell_to_column(position)] |= value_bit;
+                self.section_values[layout.cell_to_section(position)] |= value_bit;
+            }
+        }
+
+        for change in &mut self.trail {
+            match change {
+                Change::Place { position, .. } => *position = targets[*position],
+                Change::Eliminate {
+                    position, values, ..
+                } => {
+                    *position = targets[*position];
+                    *values = transformation.relabel_values(*values);
+                }
+            }
+        }
+
+        // Steps without a cell or value, such as rollbacks, keep theirs.
+        let move_item = |item: &LogItem| {
+            let log_type = match transformation.transpose {
+                true => item.log_type.transposed(),
+                false => item.log_type.clone(),
+            };
+            let value = match transformation.digits.get(item.get_value().wrapping_sub(1)) {
+                Some(&digit) => digit as usize,
+                None => item.get_value(),
+            };
+            let position = targets
+                .get(item.get_position())
+                .copied()
+                .unwrap_or(item.get_position());
+            item.moved(log_type, value, position)
+        };
+        self.solve_history = self.solve_history.iter().map(move_item).collect();
+        self.solve_instructions = self.solve_instructions.iter().map(move_item).collect();
+        Ok(())
+    }
+
+    /**
+     * Turn the puzzle into the same puzzle in disguise by a transformation
+     * picked at random, and return the transformation. Returns an error as
+     * transform() does.
+     */
+    pub fn shuffle(&mut self) -> Result<Transformation, QQWingError> {
+        let transformation = Transformation::random(&self.layout);
+        self.transform(&transformation)?;
+        Ok(transformation)
+    }
+
+    /**
+     * Make the puzzle a killer puzzle with the given cages, or a classic one
+     * with no cages. The givens are kept, and the board is reset to them.
+     * Returns an error if a cage doesn't fit the board, or a given repeats a
+     * value in its cage, and keeps the old cages.
+     */
+    pub fn set_cages(&mut self, cages: Vec<Cage>) -> Result<(), QQWingError> {
+        killer::check_cages(&self.layout, &cages)?;
+        let old = std::mem::take(&mut self.cages);
+        self.store_cages(cages);
+        self.reset_or_undo(|board| board.store_cages(old))
+    }
+
+    /**
+     * Reset the board to the givens under rules just changed, and if the
+     * givens break them, undo the change and reset under the old rules.
+     */
+    fn reset_or_undo(&mut self, undo: impl FnOnce(&mut Self)) -> Result<(), QQWingError> {
+        let error = match self.reset() {
+            Ok(()) => return Ok(()),
+            Err(error) => error,
+        };
+        undo(self);
+        // The old rules may not have held either, as after a bad set_puzzle().
+        let _ = self.reset();
+        Err(error)
+    }
+
+    fn store_cages(&mut self, cages: Vec<Cage>) {
+        self.rules_version += 1;
+        self.cell_cages.fill(None);
+        for (index, cage) in cages.iter().enumerate() {
+            for &position in cage.get_cells() {
+                self.cell_cages[position] = Some(index);
             }
         }
-        count
+        self.cages = cages;
+    }
+
+    /**
+     * The cages of a killer puzzle, empty for other puzzles.
+     */
+    pub fn get_cages(&self) -> &[Cage] {
+        &self.cages
+    }
+
+    /**
+     * Give the puzzle Kropki dots, or none. With negative set, cells next to
+     * each other without a dot are neither consecutive nor one double the
+     * other. The givens are kept, and the board is reset to them. Returns an
+     * error if a dot doesn't fit the board, or the givens break a dot, and
+     * keeps the old dots.
+     */
+    pub fn set_dots(&mut self, dots: Vec<Dot>, negative: bool) -> Result<(), QQWingError> {
+        kropki::check_dots(&self.layout, &dots)?;
+        let old = std::mem::take(&mut self.dots);
+        let old_negative = self.negative_dots;
+        self.store_dots(dots, negative);
+        self.reset_or_undo(|board| board.store_dots(old, old_negative))
+    }
+
+    fn store_dots(&mut self, dots: Vec<Dot>, negative: bool) {
+        self.rules_version += 1;
+        self.dots = dots;
+        self.negative_dots = negative;
+        self.store_pair_rules();
+    }
+
+    /**
+     * The Kropki dots of the puzzle, empty for other puzzles.
+     */
+    pub fn get_dots(&self) -> &[Dot] {
+        &self.dots
+    }
+
+    /**
+     * Whether cells next to each other without a dot are neither
+     * consecutive nor one double the other.
+     */
+    pub fn has_negative_dots(&self) -> bool {
+        self.negative_dots
+    }
+
+    /**
+     * Give the puzzle XV and greater-than markers, or none. The givens are
+     * kept, and the board is reset to them. Returns an error if a marker
+     * doesn't fit the board, or the givens break a marker, and keeps the old
+     * markers.
+     */
+    pub fn set_markers(&mut self, markers: Vec<Marker>) -> Result<(), QQWingError> {
+        markers::check_markers(&self.layout, &markers)?;
+        let old = std::mem::take(&mut self.markers);
+        self.store_markers(markers);
+        self.reset_or_undo(|board| board.store_markers(old))
+    }
+
+    fn store_markers(&mut self, markers: Vec<Marker>) {
+        self.rules_version += 1;
+        self.markers = markers;
+        self.store_pair_rules();
+    }
+
+    /**
+     * The XV and greater-than markers of the puzzle, empty for other
+     * puzzles.
+     */
+    pub fn get_markers(&self) -> &[Marker] {
+        &self.markers
+    }
+
+    /**
+     * Give the puzzle thermometers, arrows and sandwich sums, or none. The
+     * givens are kept, and the board is reset to them. Returns an error if a
+     * clue doesn't fit the board, or the givens break a clue, and keeps the
+     * old clues.
+     */
+    pub fn set_clues(&mut self, clues: Vec<Clue>) -> Result<(), QQWingError> {
+        clues::check_clues(&self.layout, &clues)?;
+        let old = std::mem::take(&mut self.clues);
+        self.store_clues(clues);
+        self.reset_or_undo(|board| board.store_clues(old))
+    }
+
+    fn store_clues(&mut self, clues: Vec<Clue>) {
+        self.rules_version += 1;
+        self.clue_cells = clues
+            .iter()
+            .map(|clue| clue.rule().cells(&self.layout))
+            .collect();
+        self.clues = clues;
+    }
+
+    /**
+     * The thermometers, arrows and sandwich sums of the puzzle, empty for
+     * other puzzles.
+     */
+    pub fn get_clues(&self) -> &[Clue] {
+        &self.clues
+    }
+
+    /**
+     * Shade the cells of an even-odd puzzle, one entry per cell, or with
+     * every entry None make it a puzzle without shading. The givens are
+     * kept, and the board is reset to them, so the shaded cells start with
+     * only the values of their parity. Returns an error if the shading is
+     * not the size of the board, or a given has the wrong parity, and keeps
+     * the old shading.
+     */
+    pub fn set_parity(&mut self, shading: Vec<Option<Parity>>) -> Result<(), QQWingError> {
+        if shading.len() != self.layout.get_board_size() {
+            return Err(QQWingError::InvalidParityMap {
+                expected: self.layout.get_board_size(),
+                found: shading.len(),
+            });
+        }
+        let old = std::mem::replace(&mut self.parity, shading);
+        self.rules_version += 1;
+        self.reset_or_undo(|board| {
+            board.parity = old;
+            board.rules_version += 1;
+        })
+    }
+
+    /**
+     * The shading of each cell, None for cells that aren't shaded.
+     */
+    pub fn get_parity(&self) -> &[Option<Parity>] {
+        &self.parity
     }
 
     /**
-     * Set the board to the given puzzle. The given puzzle must be an array of 81 integers.
+     * Whether any cell is shaded even or odd.
      */
-    pub fn set_puzzle(&mut self, init_puzzle: Vec<u8>) -> bool {
-        for i in 0..BOARD_SIZE {
-            self.puzzle[i] = init_puzzle[i];
+    pub fn has_parity(&self) -> bool {
+        self.parity.iter().any(Option::is_some)
+    }
+
+    /**
+     * The values a cell may take before anything is placed, by its shading
+     * and the constraints, one bit per value.
+     */
+    fn initial_values(&self, position: usize) -> u32 {
+        let mut values = match self.parity[position] {
+            Some(parity) => parity.values(&self.layout),
+            None => self.layout.all_values(),
+        };
+        for constraint in &self.constraints {
+            values &= constraint.restrict(&self.layout, position);
         }
+        values
+    }
+
+    /**
+     * Register a constraint of the caller's own, which the solver, the
+     * solution counter and the generator keep to from then on, along with
+     * those registered before. Constraints stay registered when the layout
+     * changes. The givens are kept, and the board is reset to them. Returns
+     * an error if the givens break the constraint, which is then not
+     * registered.
+     */
+    pub fn add_constraint(&mut self, constraint: Box<dyn Constraint>) -> Result<(), QQWingError> {
+        self.constraints.push(constraint);
+        self.store_constraint_peers();
+        self.reset_or_undo(|board| {
+            board.constraints.pop();
+            board.store_constraint_peers();
+        })
+    }
+
+    /**
+     * Remove every registered constraint, resetting the board to the givens.
+     */
+    pub fn clear_constraints(&mut self) -> Result<(), QQWingError> {
+        self.constraints.clear();
+        self.store_constraint_peers();
         self.reset()
     }
 
+    /**
+     * The registered constraints, oldest first.
+     */
+    pub fn get_constraints(&self) -> &[Box<dyn Constraint>] {
+        &self.constraints
+    }
+
+    fn store_constraint_peers(&mut self) {
+        self.rules_version += 1;
+        self.constraint_peers = constraint::constraint_peers(&self.layout, &self.constraints);
+    }
+
+    /**
+     * Work out the rules between pairs of cells from the layout, the dots
+     * and the markers, and which rules each cell is in.
+     */
+    fn store_pair_rules(&mut self) {
+        let layout = &*self.layout;
+        let mut rules = Vec::new();
+        if layout.has_non_consecutive() {
+            rules.extend(kropki::non_consecutive_rules(layout));
+        }
+        rules.extend(kropki::dot_rules(layout, &self.dots, self.negative_dots));
+        rules.extend(markers::marker_rules(layout, &self.markers));
+        self.cell_pair_rules = vec![Vec::new(); layout.get_board_size()];
+        for (index, rule) in rules.iter().enumerate() {
+            for position in rule.get_cells() {
+                self.cell_pair_rules[position].push(index);
+            }
+        }
+        self.pair_rules = rules;
+    }
+
+    /**
+     * Set the board to a game in progress. The givens become the puzzle, and
+     * the player's entries and pencil marks are applied on top of them so
+     * that hints and solving start from where the player is. Returns an error
+     * if the game does not fit the layout, or a given or entry is out of
+     * range or conflicts with another.
+     */
+    pub fn set_player_state(&mut self, state: &PlayerState) -> Result<(), QQWingError> {
+        self.check_values(state.get_givens())?;
+        self.check_values(state.get_entries())?;
+        self.puzzle = state.get_givens().to_vec();
+        self.entries = state.get_entries().to_vec();
+        self.candidate_marks = state.get_candidate_marks().to_vec();
+        self.reset()
+    }
+
+    /**
+     * Get the puzzle, an array with one integer per cell and 0 for unknown
+     * cells.
+     */
+    pub fn get_puzzle(&self) -> Vec<u8> {
+        self.puzzle.clone()
+    }
+
+    /**
+     * Get the solution as far as it has been worked out, an array with one
+     * integer per cell and 0 for cells that are not known yet.
+     */
+    pub fn get_solution(&self) -> Vec<u8> {
+        self.solution.clone()
+    }
+
     /**
      * Reset the board to its initial state with only the givens. This method
      * clears any solution, resets statistics, and clears any history messages.
+     * Returns an error if a given or entry conflicts with another.
      */
-    fn reset(&mut self) -> bool {
+    fn reset(&mut self) -> Result<(), QQWingError> {
         self.solution.fill(0);
         self.solution_round.fill(0);
-        self.possibilities.fill(0);
+        for position in 0..self.layout.get_board_size() {
+            self.candidates[position] = self.initial_values(position);
+        }
+        self.row_values.fill(0);
+        self.column_values.fill(0);
+        self.section_values.fill(0);
+        self.extra_values.fill(0);
+        self.trail.clear();
         self.solve_history.clear();
         self.solve_instructions.clear();
 
         let round = 1;
-        for position in 0..BOARD_SIZE {
-            if self.puzzle[position] > 0 {
-                let val_index = self.puzzle[position] - 1;
-                let val_pos = QQWing::get_possibility_index(val_index as usize, position);
-                let value = self.puzzle[position];
-                if self.possibilities[val_pos] != 0 {
-                    return false;
-                }
-                let _ = self.mark(position, round, value).unwrap();
-                if self.log_history || self.record_history {
-                    self.add_history_item(LogItem::new(
-                        round,
-                        LogType::Given,
-                        value as usize,
-                        position,
-                    ));
+        for position in 0..self.layout.get_board_size() {
+            let (value, log_type) = if self.puzzle[position] > 0 {
+                (self.puzzle[position], LogType::Given)
+            } else if self.entries[position] > 0 {
+                (self.entries[position], LogType::Entry)
+            } else {
+                continue;
+            };
+            self.mark(position, round, value)?;
+            if self.is_tracking() {
+                self.add_history_item(self.new_log_item(round, log_type, value as usize, position));
+            }
+        }
+
+        // Take out any possibilities the player has removed from their
+        // pencil marks.
+        for position in 0..self.layout.get_board_size() {
+            if let Some(marks) = self.candidate_marks[position] {
+                if self.solution[position] == 0 {
+                    self.eliminate_values(position, !marks, round);
                 }
             }
         }
 
-        true
+        Ok(())
     }
 
     /**
@@ -221,6 +975,9 @@ impl QQWing {
         if self.get_box_line_reduction_count() > 0 {
             return Difficulty::MEDIUM;
         }
+        if self.get_cage_reduction_count() > 0 {
+            return Difficulty::MEDIUM;
+        }
         if self.get_pointing_pair_triple_count() > 0 {
             return Difficulty::MEDIUM;
         }
@@ -233,10 +990,27 @@ impl QQWing {
         if self.get_hidden_single_count() > 0 {
             return Difficulty::EASY;
         }
+        if self.get_cage_combination_count() > 0 {
+            return Difficulty::EASY;
+        }
+        if self.get_pair_rule_count() > 0 {
+            return Difficulty::EASY;
+        }
+        if self.get_clue_count() > 0 {
+            return Difficulty::EASY;
+        }
         if self.get_single_count() > 0 {
             return Difficulty::SIMPLE;
         }
-        return Difficulty::UNKNOWN;
+        Difficulty::UNKNOWN
+    }
+
+    /**
+     * The symmetries of the pattern of givens of the puzzle, such as those it
+     * was generated with.
+     */
+    pub fn get_symmetries(&self) -> Symmetries {
+        symmetry::find_symmetries(&self.layout, &self.puzzle)
     }
 
     /**
@@ -249,13 +1023,15 @@ impl QQWing {
 
     /**
      * Get the number of cells for which the solution was determined because
-     * that cell had the only possibility for some value in the row, column, or
-     * section.
+     * that cell had the only possibility for some value in the row, column,
+     * section or extra unit.
      */
     fn get_hidden_single_count(&self) -> usize {
         QQWing::get_log_count(&self.solve_instructions, LogType::HiddenSingleRow)
             + QQWing::get_log_count(&self.solve_instructions, LogType::HiddenSingleColumn)
             + QQWing::get_log_count(&self.solve_instructions, LogType::HiddenSingleSection)
+            + QQWing::get_log_count(&self.solve_instructions, LogType::HiddenSingleDiagonal)
+            + QQWing::get_log_count(&self.solve_instructions, LogType::HiddenSingleExtraRegion)
     }
 
     /**
@@ -266,6 +1042,8 @@ impl QQWing {
         QQWing::get_log_count(&self.solve_instructions, LogType::NakedPairRow)
             + QQWing::get_log_count(&self.solve_instructions, LogType::NakedPairColumn)
             + QQWing::get_log_count(&self.solve_instructions, LogType::NakedPairSection)
+            + QQWing::get_log_count(&self.solve_instructions, LogType::NakedPairDiagonal)
+            + QQWing::get_log_count(&self.solve_instructions, LogType::NakedPairExtraRegion)
     }
 
     /**
@@ -276,6 +1054,8 @@ impl QQWing {
         QQWing::get_log_count(&self.solve_instructions, LogType::HiddenPairRow)
             + QQWing::get_log_count(&self.solve_instructions, LogType::HiddenPairColumn)
             + QQWing::get_log_count(&self.solve_instructions, LogType::HiddenPairSection)
+            + QQWing::get_log_count(&self.solve_instructions, LogType::HiddenPairDiagonal)
+            + QQWing::get_log_count(&self.solve_instructions, LogType::HiddenPairExtraRegion)
     }
 
     /**
@@ -296,6 +1076,44 @@ impl QQWing {
             + QQWing::get_log_count(&self.solve_instructions, LogType::ColumnBox)
     }
 
+    /**
+     * Get the number of times the possibilities of a killer cage were cut
+     * down to the ways its sum can be made.
+     */
+    fn get_cage_combination_count(&self) -> usize {
+        QQWing::get_log_count(&self.solve_instructions, LogType::CageCombination)
+    }
+
+    /**
+     * Get the number of rule of 45 and cage pointing reductions that were
+     * performed in solving this killer puzzle.
+     */
+    fn get_cage_reduction_count(&self) -> usize {
+        QQWing::get_log_count(&self.solve_instructions, LogType::CageRuleOf45)
+            + QQWing::get_log_count(&self.solve_instructions, LogType::CagePointing)
+    }
+
+    /**
+     * Get the number of times possibilities were removed because they fit
+     * no value left for the other cell of a rule between two cells.
+     */
+    fn get_pair_rule_count(&self) -> usize {
+        QQWing::get_log_count(&self.solve_instructions, LogType::NonConsecutive)
+            + QQWing::get_log_count(&self.solve_instructions, LogType::KropkiDot)
+            + QQWing::get_log_count(&self.solve_instructions, LogType::XvMarker)
+            + QQWing::get_log_count(&self.solve_instructions, LogType::GreaterThan)
+    }
+
+    /**
+     * Get the number of times possibilities were removed because they fit
+     * no way of keeping to a thermometer, arrow or sandwich sum.
+     */
+    fn get_clue_count(&self) -> usize {
+        QQWing::get_log_count(&self.solve_instructions, LogType::Thermometer)
+            + QQWing::get_log_count(&self.solve_instructions, LogType::Arrow)
+            + QQWing::get_log_count(&self.solve_instructions, LogType::Sandwich)
+    }
+
     /**
      * Get the number lucky guesses in solving this puzzle.
      */
@@ -317,21 +1135,190 @@ impl QQWing {
         self.random_possibility_array.shuffle(&mut rng);
     }
 
-    fn clear_puzzle(&mut self) {
+    fn clear_puzzle(&mut self) -> Result<(), QQWingError> {
         debug!("Clear any existing puzzle");
-        for i in 0..BOARD_SIZE {
-            self.puzzle[i] = 0;
+        self.puzzle.fill(0);
+        self.entries.fill(0);
+        self.candidate_marks.fill(None);
+        self.reset()
+    }
+
+    /// Generate a new sudoku puzzle, returning the number of attempts needed.
+    pub fn generate_puzzle(&mut self) -> Result<u32, QQWingError> {
+        self.generate_puzzle_with_options(&GenerateOptions::default())
+    }
+
+    /**
+     * Generate puzzles until one matches the difficulty and symmetry in the
+     * options, stopping with QQWingError::BudgetExhausted when the time or
+     * attempt limit is reached and QQWingError::Cancelled when the options'
+     * token is cancelled. On success the puzzle is left solved so that its
+     * difficulty and stats can be read, and the number of attempts is
+     * returned. Any cages, dots or markers the board had are replaced by new
+     * ones for killer, Kropki, XV or greater-than puzzles, and cleared
+     * otherwise.
+     */
+    pub fn generate_puzzle_with_options(
+        &mut self,
+        options: &GenerateOptions,
+    ) -> Result<u32, QQWingError> {
+        // Only report accepted and rejected candidates, not the solving
+        // done to produce and rate them.
+        let mut observer = self.observer.take();
+        let result = self.generate_puzzle_attempts(options, &mut observer);
+        self.observer = observer;
+        result
+    }
+
+    fn generate_puzzle_attempts(
+        &mut self,
+        options: &GenerateOptions,
+        observer: &mut Option<Box<dyn SolveObserver>>,
+    ) -> Result<u32, QQWingError> {
+        let mut budget = Budget::new(options);
+        loop {
+            if let Err(e) = budget.start_attempt() {
+                self.clear_puzzle()?;
+                return Err(e);
+            }
+            self.generate_puzzle_symmetry(options, &budget)?;
+            let accepted = options.difficulty == Difficulty::UNKNOWN || {
+                let rec_history = self.record_history;
+                self.set_record_history(true);
+                let solved = self.solve();
+                self.set_record_history(rec_history);
+                solved?;
+                self.get_difficulty() == options.difficulty
+            };
+            if accepted {
+                if let Some(observer) = observer.as_mut() {
+                    observer.on_candidate_accepted(&self.puzzle, budget.get_attempts());
+                }
+                return Ok(budget.get_attempts());
+            }
+            if let Some(observer) = observer.as_mut() {
+                observer.on_candidate_rejected(&self.puzzle, budget.get_attempts());
+            }
+            info!(
+                "get a puzzle with difficulty {:?} != {:?}, continue generate...",
+                self.get_difficulty(),
+                options.difficulty
+            );
         }
-        self.reset();
     }
 
-    /// Generate a new sudoku puzzle.
-    pub fn generate_puzzle(&mut self) -> bool {
-        self.generate_puzzle_symmetry(Symmetry::NONE)
+    /**
+     * Generate a puzzle whose givens are exactly the cells set in the mask.
+     * Each attempt fills a random grid with solve(), keeps the masked cells
+     * and checks with count_solutions_limited() that the puzzle is unique,
+     * and that it has the difficulty in the options. The options' time and
+     * attempt limits and token stop it as they do generate_puzzle_with_options(),
+     * and the puzzle is left the same way. Returns the number of attempts
+     * that were needed, or an error when the mask is malformed or the budget
+     * runs out first. The symmetry and variant options are not used.
+     */
+    pub fn generate_puzzle_from_mask(
+        &mut self,
+        mask: &[bool],
+        options: &GenerateOptions,
+    ) -> Result<u32, QQWingError> {
+        let board_size = self.layout.get_board_size();
+        if mask.len() != board_size {
+            return Err(QQWingError::InvalidMask {
+                expected: board_size,
+                found: mask.len(),
+            });
+        }
+        // Without all but one of the values among the givens, the missing
+        // ones could be swapped for each other. Only the classic 9x9 board
+        // is known to need more.
+        let size = self.layout.get_size();
+        let classic = !self.layout.is_jigsaw()
+            && self.layout.get_extra_unit_count() == 0
+            && !self.layout.has_anti_knight()
+            && !self.layout.has_anti_king();
+        let needed = if size == 9 && classic {
+            MIN_GIVENS
+        } else {
+            size - 1
+        };
+        let given_count = mask.iter().filter(|&&m| m).count();
+        if given_count < needed {
+            return Err(QQWingError::MaskTooSparse {
+                givens: given_count,
+                needed,
+            });
+        }
+        // Don't record history while generating.
+        let rec_history = self.record_history;
+        self.set_record_history(false);
+        let l_history = self.log_history;
+        self.set_log_history(false);
+        let mut observer = self.observer.take();
+
+        let mut result = self.generate_puzzle_from_mask_attempts(mask, options, &mut observer);
+        if result.is_err() {
+            self.puzzle.fill(0);
+            result = self.reset().and(result);
+        }
+
+        // Restore recording history.
+        self.set_record_history(rec_history);
+        self.set_log_history(l_history);
+        self.observer = observer;
+
+        result
     }
 
-    fn generate_puzzle_symmetry(&mut self, symmetry: Symmetry) -> bool {
-        let mut symmetry = symmetry;
+    fn generate_puzzle_from_mask_attempts(
+        &mut self,
+        mask: &[bool],
+        options: &GenerateOptions,
+        observer: &mut Option<Box<dyn SolveObserver>>,
+    ) -> Result<u32, QQWingError> {
+        let mut budget = Budget::new(options);
+        loop {
+            budget.start_attempt()?;
+            debug!("Mask attempt: {}", budget.get_attempts());
+            self.clear_puzzle()?;
+            self.shuffle_random_arrays();
+            if !self.fill_board(&budget)? {
+                return Err(QQWingError::UnsatisfiableLayout);
+            }
+            for (i, &given) in mask.iter().enumerate() {
+                self.puzzle[i] = if given { self.solution[i] } else { 0 };
+            }
+            budget.check()?;
+            if self.is_unique(&self.puzzle, &self.initial_allowed()) {
+                // Clear all solution info, leaving just the puzzle.
+                self.reset()?;
+                let accepted = options.difficulty == Difficulty::UNKNOWN || {
+                    let rec_history = self.record_history;
+                    self.set_record_history(true);
+                    let solved = self.solve();
+                    self.set_record_history(rec_history);
+                    solved?;
+                    self.get_difficulty() == options.difficulty
+                };
+                if accepted {
+                    if let Some(observer) = observer.as_mut() {
+                        observer.on_candidate_accepted(&self.puzzle, budget.get_attempts());
+                    }
+                    return Ok(budget.get_attempts());
+                }
+            }
+            if let Some(observer) = observer.as_mut() {
+                observer.on_candidate_rejected(&self.puzzle, budget.get_attempts());
+            }
+        }
+    }
+
+    fn generate_puzzle_symmetry(
+        &mut self,
+        options: &GenerateOptions,
+        budget: &Budget,
+    ) -> Result<(), QQWingError> {
+        let mut symmetry = options.symmetry.clone();
         if symmetry == Symmetry::RANDOM {
             symmetry = QQWing::get_random_symmetry();
         }
@@ -339,10 +1326,42 @@ impl QQWing {
         // Don't record history while generating.
         let rec_history = self.record_history;
         self.set_record_history(false);
-        let l_history = self.record_history;
+        let l_history = self.log_history;
         self.set_log_history(false);
 
-        self.clear_puzzle();
+        let result = self.remove_givens(symmetry, options, budget);
+        if result.is_err() {
+            self.puzzle.fill(0);
+        }
+
+        // Clear all solution info, leaving just the puzzle.
+        let reset = self.reset();
+
+        // Restore recording history.
+        self.set_record_history(rec_history);
+        self.set_log_history(l_history);
+
+        reset.and(result)
+    }
+
+    /**
+     * Fill a random grid and take givens out of it, in symmetric groups, for
+     * as long as the puzzle stays unique. For killer puzzles the grid is
+     * first carved into cages, which often leaves no givens at all.
+     */
+    fn remove_givens(
+        &mut self,
+        symmetry: Symmetry,
+        options: &GenerateOptions,
+        budget: &Budget,
+    ) -> Result<(), QQWingError> {
+        self.store_cages(Vec::new());
+        self.store_dots(Vec::new(), false);
+        self.store_markers(Vec::new());
+        if options.parity {
+            self.parity.fill(None);
+        }
+        self.clear_puzzle()?;
 
         // Start by getting the randomness in order so that
         // each puzzle will be different from the last.
@@ -351,10 +1370,43 @@ impl QQWing {
         // Now solve the puzzle the whole way. The solve
         // uses random algorithms, so we should have a
         // really randomly totally filled sudoku
-        // Even when starting from an empty grid
-        self.solve();
-
-        if symmetry == Symmetry::NONE {
+        // Even when starting from an empty grid. Rules that can't all be
+        // kept leave nothing to take givens from.
+        if !self.fill_board(budget)? {
+            return Err(QQWingError::UnsatisfiableLayout);
+        }
+
+        if options.killer {
+            // Cut the filled grid into cages. The grid is kept whole, as
+            // the givens are taken out below while the cages keep it unique.
+            self.store_cages(killer::carve_cages(&self.layout, &self.solution));
+        }
+        if options.kropki {
+            // Likewise every dot the filled grid has is put on the board,
+            // and the cells without one are known to have no dot.
+            self.store_dots(kropki::place_dots(&self.layout, &self.solution), true);
+        }
+        let markers = options.xv || options.greater_than;
+        if markers {
+            // And every marker, of which only those needed are kept below.
+            self.store_markers(markers::place_markers(
+                &self.layout,
+                &self.solution,
+                options.xv,
+                options.greater_than,
+            ));
+        }
+        if options.parity {
+            // And every cell is shaded, of which only those needed are kept.
+            self.parity = self.solution.iter().map(|&v| Some(Parity::of(v))).collect();
+        }
+        if !options.killer
+            && !options.kropki
+            && !markers
+            && !options.parity
+            && symmetry == Symmetry::NONE
+            && self.pair_rules.is_empty()
+        {
             // Rollback any square for which it is obvious that
             // the square doesn't contribute to a unique solution
             // (ie, squares that were filled by logic rather
@@ -364,9 +1416,7 @@ impl QQWing {
 
         // Record all marked squares as the puzzle so
         // that we can call countSolutions without losing it.
-        for i in 0..BOARD_SIZE {
-            self.puzzle[i] = self.solution[i];
-        }
+        self.puzzle.clone_from(&self.solution);
 
         // Rerandomize everything so that we test squares
         // in a different order than they were added.
@@ -376,40 +1426,47 @@ impl QQWing {
         // the puzzle still has only one solution.
         // If it does, leave it out the point because
         // it is not needed.
-        for i in 0..BOARD_SIZE {
+        let size = self.layout.get_size();
+        let allowed = self.initial_allowed();
+        for i in 0..self.layout.get_board_size() {
+            budget.check()?;
             // check all the positions, but in shuffled order
-            let position = self.random_board_array[i] as usize;
+            let position = self.random_board_array[i];
             if self.puzzle[position] > 0 {
                 let mut positionsym1 = UNSET_VALUE;
                 let mut positionsym2 = UNSET_VALUE;
                 let mut positionsym3 = UNSET_VALUE;
                 match symmetry {
                     Symmetry::ROTATE90 => {
-                        positionsym2 = QQWing::row_column_to_cell(
-                            ROW_COL_SEC_SIZE - 1 - QQWing::cell_to_column(position),
-                            QQWing::cell_to_row(position),
+                        positionsym1 = self.layout.row_column_to_cell(
+                            size - 1 - self.layout.cell_to_row(position),
+                            size - 1 - self.layout.cell_to_column(position),
                         );
-                        positionsym3 = QQWing::row_column_to_cell(
-                            QQWing::cell_to_column(position),
-                            ROW_COL_SEC_SIZE - 1 - QQWing::cell_to_row(position),
+                        positionsym2 = self.layout.row_column_to_cell(
+                            size - 1 - self.layout.cell_to_column(position),
+                            self.layout.cell_to_row(position),
+                        );
+                        positionsym3 = self.layout.row_column_to_cell(
+                            self.layout.cell_to_column(position),
+                            size - 1 - self.layout.cell_to_row(position),
                         );
                     }
                     Symmetry::ROTATE180 => {
-                        positionsym1 = QQWing::row_column_to_cell(
-                            ROW_COL_SEC_SIZE - 1 - QQWing::cell_to_row(position),
-                            ROW_COL_SEC_SIZE - 1 - QQWing::cell_to_column(position),
+                        positionsym1 = self.layout.row_column_to_cell(
+                            size - 1 - self.layout.cell_to_row(position),
+                            size - 1 - self.layout.cell_to_column(position),
                         )
                     }
                     Symmetry::MIRROR => {
-                        positionsym1 = QQWing::row_column_to_cell(
-                            QQWing::cell_to_row(position),
-                            ROW_COL_SEC_SIZE - 1 - QQWing::cell_to_column(position),
+                        positionsym1 = self.layout.row_column_to_cell(
+                            self.layout.cell_to_row(position),
+                            size - 1 - self.layout.cell_to_column(position),
                         )
                     }
                     Symmetry::FLIP => {
-                        positionsym1 = QQWing::row_column_to_cell(
-                            ROW_COL_SEC_SIZE - 1 - QQWing::cell_to_row(position),
-                            QQWing::cell_to_column(position),
+                        positionsym1 = self.layout.row_column_to_cell(
+                            size - 1 - self.layout.cell_to_row(position),
+                            self.layout.cell_to_column(position),
                         )
                     }
                     _ => {}
@@ -433,8 +1490,7 @@ impl QQWing {
                     saved_sym3 = self.puzzle[positionsym3];
                     self.puzzle[positionsym3] = 0;
                 }
-                self.reset();
-                if self.count_solutions_round(2, true) > 1 {
+                if !self.is_unique(&self.puzzle, &allowed) {
                     // Put it back in, it is needed
                     self.puzzle[position] = saved_value;
                     if positionsym1 != UNSET_VALUE && saved_sym1 != 0 {
@@ -450,14 +1506,74 @@ impl QQWing {
             }
         }
 
-        // Clear all solution info, leaving just the puzzle.
-        self.reset();
+        // Take out the markers the givens left don't need, in random order,
+        // so that only as many markers are left as the puzzle needs.
+        if markers {
+            let mut order: Vec<Marker> = self.markers.clone();
+            order.shuffle(&mut thread_rng());
+            for marker in order {
+                budget.check()?;
+                let kept = self.markers.clone();
+                self.store_markers(kept.iter().filter(|&m| *m != marker).cloned().collect());
+                if !self.is_unique(&self.puzzle, &allowed) {
+                    self.store_markers(kept);
+                }
+            }
+        }
 
-        // Restore recording history.
-        self.set_record_history(rec_history);
-        self.set_log_history(l_history);
+        // Likewise take the shading off the cells that don't need it.
+        if options.parity {
+            let mut order: Vec<usize> = (0..self.layout.get_board_size()).collect();
+            order.shuffle(&mut thread_rng());
+            for position in order {
+                budget.check()?;
+                let shading = self.parity[position].take();
+                if !self.is_unique(&self.puzzle, &self.initial_allowed()) {
+                    self.parity[position] = shading;
+                }
+            }
+        }
+        Ok(())
+    }
 
-        true
+    /**
+     * The values each cell may take before anything is placed, one bit per
+     * value.
+     */
+    fn initial_allowed(&self) -> Vec<u32> {
+        (0..self.layout.get_board_size())
+            .map(|position| self.initial_values(position))
+            .collect()
+    }
+
+    /**
+     * Fill the whole board in at random, returning false if its rules can't
+     * all be kept. The logic solver can guess for a long time on boards with
+     * rules between pairs of cells, so those are filled by the solution
+     * counter instead, starting over whenever a fill runs long. They are
+     * then not filled round by round, so have no rounds to roll back.
+     */
+    fn fill_board(&mut self, budget: &Budget) -> Result<bool, QQWingError> {
+        if self.pair_rules.is_empty() {
+            return self.solve();
+        }
+        let empty = vec![0; self.layout.get_board_size()];
+        let allowed = self.initial_allowed();
+        let node_limit = FILL_NODE_LIMIT * self.layout.get_board_size() as u64;
+        loop {
+            budget.check()?;
+            let mut counter = self.solution_counter(&empty, &allowed);
+            counter.set_random(true);
+            counter.set_node_limit(node_limit);
+            counter.count(1);
+            if let Some(solution) = counter.get_solution().map(<[u8]>::to_vec) {
+                self.solution = solution;
+                return Ok(true);
+            }
+            if !counter.is_exhausted() {
+                return Ok(false);
+            }
+        }
     }
 
     fn rollback_non_guesses(&mut self) {
@@ -479,11 +1595,55 @@ impl QQWing {
         self.record_history = rec_history;
     }
 
+    /**
+     * Add the columns for cage combinations, cage reductions, pair rule
+     * reductions, clue reductions and symmetries to the end of CSV stats.
+     * Without them CSV stats have the ten classic columns.
+     */
+    pub fn set_extra_stats(&mut self, extra_stats: bool) {
+        self.extra_stats = extra_stats;
+    }
+
     pub fn set_log_history(&mut self, log_hist: bool) {
         self.log_history = log_hist;
     }
 
+    pub fn set_observer(&mut self, observer: Box<dyn SolveObserver>) {
+        self.observer = Some(observer);
+    }
+
+    /**
+     * Remove the observer, returning it so that any state it gathered can be
+     * inspected.
+     */
+    pub fn take_observer(&mut self) -> Option<Box<dyn SolveObserver>> {
+        self.observer.take()
+    }
+
+    /**
+     * Whether solve steps need to be turned into log items, either for the
+     * history or for an observer.
+     */
+    fn is_tracking(&self) -> bool {
+        self.log_history || self.record_history || self.observer.is_some()
+    }
+
     fn add_history_item(&mut self, l: LogItem) {
+        if let Some(observer) = self.observer.as_mut() {
+            match l.log_type {
+                LogType::Guess => observer.on_guess(l.get_position(), l.get_value(), l.get_round()),
+                LogType::Rollback => observer.on_rollback(l.get_round()),
+                LogType::Given
+                | LogType::Entry
+                | LogType::Single
+                | LogType::HiddenSingleRow
+                | LogType::HiddenSingleColumn
+                | LogType::HiddenSingleSection
+                | LogType::HiddenSingleDiagonal
+                | LogType::HiddenSingleExtraRegion => observer.on_place(&l),
+                _ => {}
+            }
+        }
         if self.log_history {
             info!("{}", l);
         }
@@ -507,10 +1667,10 @@ impl QQWing {
                 sb.push_str(NL);
             }
         }
-        for i in 0..v.len() {
+        for (i, item) in v.iter().enumerate() {
             sb.push_str(&(i + 1).to_string());
             sb.push_str(". ");
-            sb.push_str(format!("{}", v[i]).as_str());
+            sb.push_str(format!("{}", item).as_str());
             if self.print_style == PrintStyle::CSV {
                 sb.push_str(" -- ");
             } else {
@@ -518,7 +1678,7 @@ impl QQWing {
             }
         }
         if self.print_style == PrintStyle::CSV {
-            sb.push_str(",");
+            sb.push(',');
         } else {
             sb.push_str(NL);
         }
@@ -532,9 +1692,9 @@ impl QQWing {
 
     fn get_solve_instructions_string(&self) -> String {
         if self.is_solved() {
-            return self.history_to_string(self.solve_instructions.clone());
+            self.history_to_string(self.solve_instructions.clone())
         } else {
-            return "No solve instructions - Puzzle is not possible to solve.".to_string();
+            "No solve instructions - Puzzle is not possible to solve.".to_string()
         }
     }
 
@@ -557,68 +1717,71 @@ impl QQWing {
         self.solve_history.clone()
     }
 
-    /// Solve the puzzle.
-    pub fn solve(&mut self) -> bool {
-        self.reset();
+    /// Solve the puzzle. Returns false if it has no solution, or an error if
+    /// the givens or entries conflict.
+    pub fn solve(&mut self) -> Result<bool, QQWingError> {
+        self.reset()?;
         self.shuffle_random_arrays();
         debug!("Solve round 2");
         self.solve_round(2)
     }
 
-    fn solve_round(&mut self, round: u8) -> bool {
+    fn solve_round(&mut self, round: u16) -> Result<bool, QQWingError> {
         self.last_solve_round = round;
+        if self.is_solved() {
+            return Ok(true);
+        }
 
-        while self.single_solve_move(round) {
+        while self.single_solve_move(round)? {
             if self.is_solved() {
-                return true;
+                return Ok(true);
             }
             if self.is_impossible() {
-                return false;
+                return Ok(false);
             }
         }
 
         let next_guess_round = round + 1;
         let next_round = round + 2;
         let mut guess_number = 0;
-        while self.guess(next_guess_round, guess_number) {
-            if self.is_impossible() || !self.solve_round(next_round) {
+        while self.guess(next_guess_round, guess_number)? {
+            if self.is_impossible() || !self.solve_round(next_round)? {
                 self.rollback_round(next_round);
                 self.rollback_round(next_guess_round);
             } else {
-                return true;
+                return Ok(true);
             }
             guess_number += 1;
         }
-        false
+        Ok(false)
     }
 
     /**
      * return true if the puzzle has no solutions at all
      */
-    pub fn has_no_solution(&mut self) -> bool {
-        self.count_solutions_limited() == 0
+    pub fn has_no_solution(&mut self) -> Result<bool, QQWingError> {
+        Ok(self.count_solutions_limited()? == 0)
     }
 
     /**
      * return true if the puzzle has a solution
      * and only a single solution
      */
-    pub fn has_unique_solution(&mut self) -> bool {
-        self.count_solutions_limited() == 1
+    pub fn has_unique_solution(&mut self) -> Result<bool, QQWingError> {
+        Ok(self.count_solutions_limited()? == 1)
     }
 
     /**
      * return true if the puzzle has more than one solution
      */
-
-    pub fn has_multiple_solutions(&mut self) -> bool {
-        self.count_solutions_limited() > 1
+    pub fn has_multiple_solutions(&mut self) -> Result<bool, QQWingError> {
+        Ok(self.count_solutions_limited()? > 1)
     }
 
     /**
      * Count the number of solutions to the puzzle
      */
-    pub fn count_total_solutions(&mut self) -> u32 {
+    pub fn count_total_solutions(&mut self) -> Result<u32, QQWingError> {
         self.count_solutions(false)
     }
 
@@ -631,58 +1794,63 @@ impl QQWing {
      * when you are interested in knowing if the
      * puzzle has zero, one, or multiple solutions.
      */
-    pub fn count_solutions_limited(&mut self) -> u32 {
+    pub fn count_solutions_limited(&mut self) -> Result<u32, QQWingError> {
         self.count_solutions(true)
     }
 
-    fn count_solutions(&mut self, limit_to_two: bool) -> u32 {
+    fn count_solutions(&mut self, limit_to_two: bool) -> Result<u32, QQWingError> {
         // Don't record history while generating.
         let rec_history = self.record_history;
         self.set_record_history(false);
         let l_history = self.log_history;
         self.set_log_history(false);
+        let observer = self.observer.take();
 
-        self.reset();
-        let solution_count = self.count_solutions_round(2, limit_to_two);
+        // Search from the givens, entries and pencil marks with the fast
+        // counter rather than the logical solver.
+        let limit = if limit_to_two { 2 } else { 0 };
+        let solution_count = self.reset().map(|()| {
+            self.solution_counter(&self.solution, &self.candidates)
+                .count(limit)
+        });
 
         // Restore recording history.
         self.set_record_history(rec_history);
         self.set_log_history(l_history);
+        self.observer = observer;
 
         solution_count
     }
 
-    fn count_solutions_round(&mut self, round: u8, limit_to_two: bool) -> u32 {
-        while self.single_solve_move(round) {
-            if self.is_solved() {
-                self.rollback_round(round);
-                return 1;
-            }
-            if self.is_impossible() {
-                self.rollback_round(round);
-                return 0;
-            }
-        }
-
-        let mut solutions = 0;
-        let next_round = round + 1;
-        let mut guess_number = 0;
-        while self.guess(next_round, guess_number) {
-            solutions += self.count_solutions_round(next_round, limit_to_two);
-            if limit_to_two && solutions >= 2 {
-                self.rollback_round(round);
-                return solutions;
-            }
-            guess_number += 1;
-        }
-        self.rollback_round(round);
+    /**
+     * A fast solution counter starting from a grid, with each empty cell
+     * limited to its allowed values, that keeps to the board's cages, the
+     * rules between pairs of cells, the clues and the constraints.
+     */
+    fn solution_counter(&self, grid: &[u8], allowed: &[u32]) -> SolutionCounter<'_> {
+        let mut counter = SolutionCounter::with_allowed(&self.layout, grid, allowed);
+        counter.set_cages(&self.cages);
+        counter.set_pair_rules(&self.pair_rules);
+        counter.set_clues(&self.clues);
+        counter.set_constraints(&self.constraints, &self.constraint_peers);
+        counter
+    }
 
-        solutions
+    /**
+     * Whether a grid has exactly one solution, as far as generation can
+     * afford to look. A search that fills in more than UNIQUE_NODE_LIMIT
+     * cells gives up and counts as not unique, so that one hard count can't
+     * stall generation on big boards.
+     */
+    fn is_unique(&self, grid: &[u8], allowed: &[u32]) -> bool {
+        let mut counter = self.solution_counter(grid, allowed);
+        counter.set_node_limit(UNIQUE_NODE_LIMIT);
+        counter.count(2) == 1 && !counter.is_exhausted()
     }
 
-    fn rollback_round(&mut self, round: u8) {
-        if self.log_history || self.record_history {
-            self.add_history_item(LogItem::new(
+    fn rollback_round(&mut self, round: u16) {
+        if self.is_tracking() {
+            self.add_history_item(self.new_log_item(
                 round,
                 LogType::Rollback,
                 4294967295,
@@ -690,731 +1858,880 @@ impl QQWing {
             ));
         }
 
-        for i in 0..BOARD_SIZE {
-            if self.solution_round[i] == round {
-                self.solution_round[i] = 0;
-                self.solution[i] = 0;
+        if self
+            .trail
+            .last()
+            .is_some_and(|change| change.round() > round)
+        {
+            // A round below the latest, as when rolling back the rounds
+            // that were not guesses, so pick its changes out of the trail.
+            let mut kept = Vec::with_capacity(self.trail.len());
+            for change in std::mem::take(&mut self.trail) {
+                if change.round() == round {
+                    self.undo_change(&change);
+                } else {
+                    kept.push(change);
+                }
             }
-        }
-        for i in 0..POSSIBILITY_SIZE {
-            if self.possibilities[i] == round {
-                self.possibilities[i] = 0;
+            self.trail = kept;
+        } else {
+            while let Some(&change) = self.trail.last().filter(|change| change.round() == round) {
+                self.undo_change(&change);
+                self.trail.pop();
             }
         }
-        while self.solve_instructions.len() > 0
-            && self.solve_instructions.last().unwrap().get_round() == round
-        {
-            let i = self.solve_instructions.len() - 1;
-            self.solve_instructions.remove(i);
+        let kept = self
+            .solve_instructions
+            .iter()
+            .rposition(|item| item.get_round() != round)
+            .map_or(0, |i| i + 1);
+        self.solve_instructions.truncate(kept);
+    }
+
+    /**
+     * Undo every change made since the trail was the given length.
+     */
+    fn undo_to(&mut self, trail_len: usize) {
+        let undone = self.trail.split_off(trail_len);
+        for change in undone.iter().rev() {
+            self.undo_change(change);
+        }
+    }
+
+    fn undo_change(&mut self, change: &Change) {
+        match *change {
+            Change::Place { position, .. } => {
+                let value_bit = !(1 << (self.solution[position] - 1));
+                self.row_values[self.layout.cell_to_row(position)] &= value_bit;
+                self.column_values[self.layout.cell_to_column(position)] &= value_bit;
+                self.section_values[self.layout.cell_to_section(position)] &= value_bit;
+                for &unit in self.layout.cell_to_extra_units(position) {
+                    self.extra_values[unit] &= value_bit;
+                }
+                self.solution_round[position] = 0;
+                self.solution[position] = 0;
+            }
+            Change::Eliminate {
+                position, values, ..
+            } => self.candidates[position] |= values,
         }
     }
 
     /// Check if the puzzle is solved.
     pub fn is_solved(&self) -> bool {
-        for i in 0..BOARD_SIZE {
-            if self.solution[i] == 0 {
-                return false;
-            }
-        }
-        true
+        self.solution.iter().all(|&value| value != 0)
+            && self.cages.iter().all(|cage| {
+                let sum: u32 = cage
+                    .get_cells()
+                    .iter()
+                    .map(|&p| self.solution[p] as u32)
+                    .sum();
+                sum == cage.get_sum()
+            })
+            && (0..self.clues.len()).all(|clue| self.clue_fit(clue).is_some())
     }
 
     fn is_impossible(&self) -> bool {
-        for position in 0..BOARD_SIZE {
-            if self.solution[position] == 0 {
-                let mut count = 0;
-                for val_index in 0..ROW_COL_SEC_SIZE {
-                    let val_pos = QQWing::get_possibility_index(val_index, position);
-                    if self.possibilities[val_pos] == 0 {
-                        count += 1;
-                    }
-                }
-                if count == 0 {
-                    return true;
-                }
+        for position in 0..self.solution.len() {
+            if self.solution[position] == 0 && self.candidates[position] == 0 {
+                return true;
             }
         }
-        false
+        // Every value must still fit somewhere in every row, column, section
+        // and extra unit.
+        let mut row_possible = self.row_values.clone();
+        let mut column_possible = self.column_values.clone();
+        let mut section_possible = self.section_values.clone();
+        let mut extra_possible = self.extra_values.clone();
+        for position in 0..self.solution.len() {
+            row_possible[self.layout.cell_to_row(position)] |= self.candidates[position];
+            column_possible[self.layout.cell_to_column(position)] |= self.candidates[position];
+            section_possible[self.layout.cell_to_section(position)] |= self.candidates[position];
+            for &unit in self.layout.cell_to_extra_units(position) {
+                extra_possible[unit] |= self.candidates[position];
+            }
+        }
+        row_possible
+            .iter()
+            .chain(column_possible.iter())
+            .chain(section_possible.iter())
+            .chain(extra_possible.iter())
+            .any(|&values| values != self.layout.all_values())
+            || (0..self.cages.len()).any(|cage| self.cage_fit(cage).is_none())
+            || (0..self.clues.len()).any(|clue| self.clue_fit(clue).is_none())
     }
 
     fn find_position_with_fewest_possibilities(&self) -> usize {
-        let mut min_possibilities = 10;
+        let mut min_possibilities = self.layout.get_size() as u32 + 1;
         let mut best_position = 0;
-        for i in 0..BOARD_SIZE {
+        for i in 0..self.layout.get_board_size() {
             let position = self.random_board_array[i];
-            if self.solution[position as usize] == 0 {
-                let mut count = 0;
-                for val_index in 0..ROW_COL_SEC_SIZE {
-                    let val_pos = QQWing::get_possibility_index(val_index, position as usize);
-                    if self.possibilities[val_pos] == 0 {
-                        count += 1;
-                    }
-                }
+            if self.solution[position] == 0 {
+                let count = self.count_possibilities(position);
                 if count < min_possibilities {
                     min_possibilities = count;
                     best_position = position;
                 }
             }
         }
-        return best_position as usize;
+        best_position
     }
 
-    fn guess(&mut self, round: u8, guess_number: u32) -> bool {
+    fn guess(&mut self, round: u16, guess_number: u32) -> Result<bool, QQWingError> {
         debug!("Guess round: {}, number: {}", round, guess_number);
         let mut local_guess_count = 0;
         let position = self.find_position_with_fewest_possibilities();
-        for i in 0..ROW_COL_SEC_SIZE {
+        for i in 0..self.layout.get_size() {
             let val_index = self.random_possibility_array[i];
-            let val_pos = QQWing::get_possibility_index(val_index as usize, position);
-            if self.possibilities[val_pos] == 0 {
+            if self.is_possible(val_index, position) {
                 if local_guess_count == guess_number {
                     let value = val_index + 1;
-                    if self.log_history || self.record_history {
-                        self.add_history_item(LogItem::new(
+                    if self.is_tracking() {
+                        self.add_history_item(self.new_log_item(
                             round,
                             LogType::Guess,
-                            value as usize,
+                            value,
                             position,
                         ));
                     }
-                    let _ = self.mark(position, round, value).unwrap();
-                    return true;
+                    self.mark(position, round, value as u8)?;
+                    return Ok(true);
                 }
                 local_guess_count += 1;
             }
         }
-        false
+        Ok(false)
     }
 
-    fn single_solve_move(&mut self, round: u8) -> bool {
+    fn single_solve_move(&mut self, round: u16) -> Result<bool, QQWingError> {
         debug!("Single Solve Move, round: {}", round);
-        if self.only_possibility_for_cell(round) {
+        if self.only_possibility_for_cell(round)? {
             debug!("only_possibility_for_cell round {} is ture", round);
-            return true;
+            return Ok(true);
         }
-        if self.only_value_in_section(round) {
+        if self.only_value_in_section(round)? {
             debug!("only_value_in_section round {} is ture", round);
-            return true;
+            return Ok(true);
         }
-        if self.only_value_in_row(round) {
+        if self.only_value_in_row(round)? {
             debug!("only_value_in_row round {} is ture", round);
-            return true;
+            return Ok(true);
         }
-        if self.only_value_in_column(round) {
+        if self.only_value_in_column(round)? {
             debug!("only_value_in_column round {} is ture", round);
-            return true;
+            return Ok(true);
+        }
+        if self.only_value_in_extra_unit(round)? {
+            debug!("only_value_in_extra_unit round {} is ture", round);
+            return Ok(true);
+        }
+        if self.pair_rule_reduction(round) {
+            debug!("pair_rule_reduction round {} is ture", round);
+            return Ok(true);
+        }
+        if self.clue_reduction(round) {
+            debug!("clue_reduction round {} is ture", round);
+            return Ok(true);
+        }
+        if self.cage_combination_reduction(round) {
+            debug!("cage_combination_reduction round {} is ture", round);
+            return Ok(true);
+        }
+        if self.cage_rule_of_45(round) {
+            debug!("cage_rule_of_45 round {} is ture", round);
+            return Ok(true);
+        }
+        if self.cage_pointing(round) {
+            debug!("cage_pointing round {} is ture", round);
+            return Ok(true);
         }
         if self.handle_naked_pairs(round) {
             debug!("handle_naked_pairs round {} is ture", round);
-            return true;
+            return Ok(true);
         }
         if self.pointing_row_reduction(round) {
             debug!("pointing_row_reduction round {} is ture", round);
-            return true;
+            return Ok(true);
         }
         if self.pointing_column_reduction(round) {
             debug!("pointing_column_reduction round {} is ture", round);
-            return true;
+            return Ok(true);
         }
         if self.row_box_reduction(round) {
             debug!("row_box_reduction round {} is ture", round);
-            return true;
+            return Ok(true);
         }
         if self.col_box_reduction(round) {
             debug!("col_box_reduction round {} is ture", round);
-            return true;
+            return Ok(true);
         }
         if self.hidden_pair_in_row(round) {
             debug!("hidden_pair_in_row round {} is ture", round);
-            return true;
+            return Ok(true);
         }
         if self.hidden_pair_in_column(round) {
             debug!("hidden_pair_in_column round {} is ture", round);
-            return true;
+            return Ok(true);
         }
         if self.hidden_pair_in_section(round) {
             debug!("hidden_pair_in_section round {} is ture", round);
-            return true;
+            return Ok(true);
+        }
+        if self.hidden_pair_in_extra_unit(round) {
+            debug!("hidden_pair_in_extra_unit round {} is ture", round);
+            return Ok(true);
         }
         debug!("single_solve_move round {} is false", round);
-        false
+        Ok(false)
     }
 
-    fn col_box_reduction(&mut self, round: u8) -> bool {
+    fn col_box_reduction(&mut self, round: u16) -> bool {
         debug!("col_box_reduction round: {}", round);
-        for val_index in 0..ROW_COL_SEC_SIZE {
-            for col in 0..ROW_COL_SEC_SIZE {
-                let col_start = col;
-                let mut in_one_box = true;
-                let mut col_box = UNSET_VALUE;
-                for i in 0..GRID_SIZE {
-                    for j in 0..GRID_SIZE {
-                        let row = i * GRID_SIZE + j;
-                        let position = QQWing::row_column_to_cell(row, col);
-                        let val_pos = QQWing::get_possibility_index(val_index, position);
-                        if self.possibilities[val_pos] == 0 {
-                            if col_box == UNSET_VALUE || col_box == i {
-                                col_box = i;
-                            } else {
-                                in_one_box = false;
-                            }
-                        }
+        let layout = Arc::clone(&self.layout);
+        for val_index in 0..layout.get_size() {
+            for col in 0..layout.get_size() {
+                let col_start = layout.row_column_to_cell(0, col);
+                let cells = layout.column_cells(col);
+                let Some(section) =
+                    self.unit_holding_value(val_index, cells, |p| layout.cell_to_section(p))
+                else {
+                    continue;
+                };
+                let mut done_something = false;
+                for &position in layout.section_cells(section) {
+                    if layout.cell_to_column(position) != col
+                        && self.eliminate(val_index, position, round)
+                    {
+                        done_something = true;
                     }
                 }
-                if in_one_box && col_box != UNSET_VALUE {
-                    let mut done_something = false;
-                    let row = GRID_SIZE * col_box;
-                    let sec_start =
-                        QQWing::cell_to_section_start_cell(QQWing::row_column_to_cell(row, col));
-                    let sec_start_row = QQWing::cell_to_row(sec_start);
-                    let sec_start_col = QQWing::cell_to_column(sec_start);
-                    for i in 0..GRID_SIZE {
-                        for j in 0..GRID_SIZE {
-                            let row2 = sec_start_row + i;
-                            let col2 = sec_start_col + j;
-                            let position = QQWing::row_column_to_cell(row2, col2);
-                            let val_pos = QQWing::get_possibility_index(val_index, position);
-                            if col != col2 && self.possibilities[val_pos] == 0 {
-                                self.possibilities[val_pos] = round;
-                                done_something = true;
-                            }
-                        }
-                    }
-                    if done_something {
-                        if self.log_history || self.record_history {
-                            self.add_history_item(LogItem::new(
-                                round,
-                                LogType::ColumnBox,
-                                val_index + 1,
-                                col_start,
-                            ));
-                        }
-                        return true;
+                if done_something {
+                    if self.is_tracking() {
+                        self.add_history_item(self.new_log_item(
+                            round,
+                            LogType::ColumnBox,
+                            val_index + 1,
+                            col_start,
+                        ));
                     }
+                    return true;
                 }
             }
         }
         false
     }
 
-    fn row_box_reduction(&mut self, round: u8) -> bool {
+    fn row_box_reduction(&mut self, round: u16) -> bool {
         debug!("row_box_reduction round: {}", round);
-        for val_index in 0..ROW_COL_SEC_SIZE {
-            for row in 0..ROW_COL_SEC_SIZE {
-                let row_start = row * 9;
-                let mut in_one_box = true;
-                let mut row_box = UNSET_VALUE;
-                for i in 0..GRID_SIZE {
-                    for j in 0..GRID_SIZE {
-                        let column = i * GRID_SIZE + j;
-                        let position = QQWing::row_column_to_cell(row, column);
-                        let val_pos = QQWing::get_possibility_index(val_index, position);
-                        if self.possibilities[val_pos] == 0 {
-                            if row_box == UNSET_VALUE || row_box == i {
-                                row_box = i;
-                            } else {
-                                in_one_box = false;
-                            }
-                        }
+        let layout = Arc::clone(&self.layout);
+        for val_index in 0..layout.get_size() {
+            for row in 0..layout.get_size() {
+                let row_start = layout.row_column_to_cell(row, 0);
+                let cells = layout.row_cells(row);
+                let Some(section) =
+                    self.unit_holding_value(val_index, cells, |p| layout.cell_to_section(p))
+                else {
+                    continue;
+                };
+                let mut done_something = false;
+                for &position in layout.section_cells(section) {
+                    if layout.cell_to_row(position) != row
+                        && self.eliminate(val_index, position, round)
+                    {
+                        done_something = true;
                     }
                 }
-                if in_one_box && row_box != UNSET_VALUE {
-                    let mut done_something = false;
-                    let column = GRID_SIZE * row_box;
-                    let sec_start =
-                        QQWing::cell_to_section_start_cell(QQWing::row_column_to_cell(row, column));
-                    let sec_start_row = QQWing::cell_to_row(sec_start);
-                    let sec_start_col = QQWing::cell_to_column(sec_start);
-                    for i in 0..GRID_SIZE {
-                        for j in 0..GRID_SIZE {
-                            let row2 = sec_start_row + i;
-                            let col2 = sec_start_col + j;
-                            let position = QQWing::row_column_to_cell(row2, col2);
-                            let val_pos = QQWing::get_possibility_index(val_index, position);
-                            if row != row2 && self.possibilities[val_pos] == 0 {
-                                self.possibilities[val_pos] = round;
-                                done_something = true;
-                            }
-                        }
-                    }
-                    if done_something {
-                        if self.log_history || self.record_history {
-                            self.add_history_item(LogItem::new(
-                                round,
-                                LogType::RowBox,
-                                val_index + 1,
-                                row_start,
-                            ));
-                        }
-                        return true;
+                if done_something {
+                    if self.is_tracking() {
+                        self.add_history_item(self.new_log_item(
+                            round,
+                            LogType::RowBox,
+                            val_index + 1,
+                            row_start,
+                        ));
                     }
+                    return true;
                 }
             }
         }
         false
     }
 
-    fn pointing_row_reduction(&mut self, round: u8) -> bool {
+    fn pointing_row_reduction(&mut self, round: u16) -> bool {
         debug!("pointing_row_reduction round: {}", round);
-        for val_index in 0..ROW_COL_SEC_SIZE {
-            for section in 0..ROW_COL_SEC_SIZE {
-                let sec_start = QQWing::section_to_first_cell(section);
-                let mut in_one_row = true;
-                let mut box_row = UNSET_VALUE;
-                for j in 0..GRID_SIZE {
-                    for i in 0..GRID_SIZE {
-                        let sec_val = sec_start + i + (ROW_COL_SEC_SIZE * j);
-                        let val_pos = QQWing::get_possibility_index(val_index, sec_val);
-                        if self.possibilities[val_pos] == 0 {
-                            if box_row == UNSET_VALUE || box_row == j {
-                                box_row = j;
-                            } else {
-                                in_one_row = false;
-                            }
-                        }
+        let layout = Arc::clone(&self.layout);
+        for val_index in 0..layout.get_size() {
+            for section in 0..layout.get_size() {
+                let cells = layout.section_cells(section);
+                let Some(row) =
+                    self.unit_holding_value(val_index, cells, |p| layout.cell_to_row(p))
+                else {
+                    continue;
+                };
+                let row_start = layout.row_column_to_cell(row, 0);
+                let mut done_something = false;
+                for &position in layout.row_cells(row) {
+                    if layout.cell_to_section(position) != section
+                        && self.eliminate(val_index, position, round)
+                    {
+                        done_something = true;
                     }
                 }
-                if in_one_row && box_row != UNSET_VALUE {
-                    let mut done_something = false;
-                    let row = QQWing::cell_to_row(sec_start) + box_row;
-                    let row_start = row * 9;
-
-                    for i in 0..ROW_COL_SEC_SIZE {
-                        let position = row_start + i;
-                        let section2 = QQWing::cell_to_section(position);
-                        let val_pos = QQWing::get_possibility_index(val_index, position);
-                        if section != section2 && self.possibilities[val_pos] == 0 {
-                            self.possibilities[val_pos] = round;
-                            done_something = true;
-                        }
-                    }
-                    if done_something {
-                        if self.log_history || self.record_history {
-                            self.add_history_item(LogItem::new(
-                                round,
-                                LogType::PointingPairTripleRow,
-                                val_index + 1,
-                                row_start,
-                            ));
-                        }
-                        return true;
+                if done_something {
+                    if self.is_tracking() {
+                        self.add_history_item(self.new_log_item(
+                            round,
+                            LogType::PointingPairTripleRow,
+                            val_index + 1,
+                            row_start,
+                        ));
                     }
+                    return true;
                 }
             }
         }
         false
     }
 
-    fn pointing_column_reduction(&mut self, round: u8) -> bool {
+    fn pointing_column_reduction(&mut self, round: u16) -> bool {
         debug!("pointing_column_reduction round: {}", round);
-        for val_index in 0..ROW_COL_SEC_SIZE {
-            for section in 0..ROW_COL_SEC_SIZE {
-                let sec_start = QQWing::section_to_first_cell(section);
-                let mut in_one_col = true;
-                let mut box_col = UNSET_VALUE;
-                for i in 0..GRID_SIZE {
-                    for j in 0..GRID_SIZE {
-                        let sec_val = sec_start + i + (ROW_COL_SEC_SIZE * j);
-                        let val_pos = QQWing::get_possibility_index(val_index, sec_val);
-                        if self.possibilities[val_pos] == 0 {
-                            if box_col == UNSET_VALUE || box_col == i {
-                                box_col = i;
-                            } else {
-                                in_one_col = false;
-                            }
-                        }
+        let layout = Arc::clone(&self.layout);
+        for val_index in 0..layout.get_size() {
+            for section in 0..layout.get_size() {
+                let cells = layout.section_cells(section);
+                let Some(col) =
+                    self.unit_holding_value(val_index, cells, |p| layout.cell_to_column(p))
+                else {
+                    continue;
+                };
+                let col_start = layout.row_column_to_cell(0, col);
+                let mut done_something = false;
+                for &position in layout.column_cells(col) {
+                    if layout.cell_to_section(position) != section
+                        && self.eliminate(val_index, position, round)
+                    {
+                        done_something = true;
                     }
                 }
-                if in_one_col && box_col != UNSET_VALUE {
-                    let mut done_something = false;
-                    let col = QQWing::cell_to_column(sec_start) + box_col;
-                    let col_start = col;
-
-                    for i in 0..ROW_COL_SEC_SIZE {
-                        let position = col_start + (ROW_COL_SEC_SIZE * i);
-                        let section2 = QQWing::cell_to_section(position);
-                        let val_pos = QQWing::get_possibility_index(val_index, position);
-                        if section != section2 && self.possibilities[val_pos] == 0 {
-                            self.possibilities[val_pos] = round;
-                            done_something = true;
-                        }
+                if done_something {
+                    if self.is_tracking() {
+                        self.add_history_item(self.new_log_item(
+                            round,
+                            LogType::PointingPairTripleColumn,
+                            val_index + 1,
+                            col_start,
+                        ));
                     }
-                    if done_something {
-                        if self.log_history || self.record_history {
-                            self.add_history_item(LogItem::new(
-                                round,
-                                LogType::PointingPairTripleColumn,
-                                val_index + 1,
-                                col_start,
-                            ));
-                        }
-                        return true;
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /**
+     * If every cell where a value (0 based) is still possible among the given
+     * cells lies in the same unit, as told by unit_of, return that unit.
+     */
+    fn unit_holding_value(
+        &self,
+        val_index: usize,
+        cells: &[usize],
+        unit_of: impl Fn(usize) -> usize,
+    ) -> Option<usize> {
+        let mut units = cells
+            .iter()
+            .filter(|&&position| self.is_possible(val_index, position))
+            .map(|&position| unit_of(position));
+        let unit = units.next()?;
+        units.all(|other| other == unit).then_some(unit)
+    }
+
+    fn count_possibilities(&self, position: usize) -> u32 {
+        self.candidates[position].count_ones()
+    }
+
+    fn are_possibilities_same(&self, position1: usize, position2: usize) -> bool {
+        self.candidates[position1] == self.candidates[position2]
+    }
+
+    fn remove_possibilities_in_one_from_two(
+        &mut self,
+        position1: usize,
+        position2: usize,
+        round: u16,
+    ) -> bool {
+        self.eliminate_values(position2, self.candidates[position1], round)
+    }
+
+    fn hidden_pair_in_column(&mut self, round: u16) -> bool {
+        debug!("hidden_pair_in_column round: {}", round);
+        let layout = Arc::clone(&self.layout);
+        (0..layout.get_size()).any(|column| {
+            self.hidden_pair_in_unit(
+                layout.column_cells(column),
+                LogType::HiddenPairColumn,
+                round,
+            )
+        })
+    }
+
+    fn hidden_pair_in_section(&mut self, round: u16) -> bool {
+        debug!("hidden_pair_in_section round: {}", round);
+        let layout = Arc::clone(&self.layout);
+        (0..layout.get_size()).any(|section| {
+            self.hidden_pair_in_unit(
+                layout.section_cells(section),
+                LogType::HiddenPairSection,
+                round,
+            )
+        })
+    }
+
+    fn hidden_pair_in_extra_unit(&mut self, round: u16) -> bool {
+        debug!("hidden_pair_in_extra_unit round: {}", round);
+        let layout = Arc::clone(&self.layout);
+        (0..layout.get_extra_unit_count()).any(|unit| {
+            let log_type = if layout.is_diagonal(unit) {
+                LogType::HiddenPairDiagonal
+            } else {
+                LogType::HiddenPairExtraRegion
+            };
+            self.hidden_pair_in_unit(layout.extra_unit_cells(unit), log_type, round)
+        })
+    }
+
+    fn hidden_pair_in_row(&mut self, round: u16) -> bool {
+        debug!("hidden_pair_in_row round: {}", round);
+        let layout = Arc::clone(&self.layout);
+        (0..layout.get_size()).any(|row| {
+            self.hidden_pair_in_unit(layout.row_cells(row), LogType::HiddenPairRow, round)
+        })
+    }
+
+    /**
+     * Look for two values that are each possible in the same two cells of a
+     * unit and nowhere else in it, and remove every other value from those
+     * two cells.
+     */
+    fn hidden_pair_in_unit(&mut self, cells: &[usize], log_type: LogType, round: u16) -> bool {
+        // For each value, the offsets into the unit where it is possible.
+        let places: Vec<u32> = (0..self.layout.get_size())
+            .map(|val_index| {
+                cells
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &position)| self.is_possible(val_index, position))
+                    .fold(0, |places, (offset, _)| places | (1 << offset))
+            })
+            .collect();
+        for val_index in 0..places.len() {
+            if places[val_index].count_ones() != 2 {
+                continue;
+            }
+            for val_index2 in (val_index + 1)..places.len() {
+                if places[val_index2] != places[val_index] {
+                    continue;
+                }
+                let offset1 = places[val_index].trailing_zeros() as usize;
+                let offset2 = (31 - places[val_index].leading_zeros()) as usize;
+                let others = self.layout.all_values() & !((1 << val_index) | (1 << val_index2));
+                let mut done_something = false;
+                if self.eliminate_values(cells[offset1], others, round) {
+                    done_something = true;
+                }
+                if self.eliminate_values(cells[offset2], others, round) {
+                    done_something = true;
+                }
+                if done_something {
+                    if self.is_tracking() {
+                        self.add_history_item(self.new_log_item(
+                            round,
+                            log_type,
+                            val_index + 1,
+                            cells[offset1],
+                        ));
                     }
+                    return true;
                 }
             }
         }
         false
     }
 
-    fn count_possibilities(&self, position: usize) -> u32 {
-        let mut count = 0;
-        for val_index in 0..ROW_COL_SEC_SIZE {
-            let val_pos = QQWing::get_possibility_index(val_index, position);
-            if self.possibilities[val_pos] == 0 {
-                count += 1;
+    /**
+     * The empty cells of a killer cage and the ways they can still be filled
+     * in, or None if they can't be.
+     */
+    fn cage_fit(&self, cage: usize) -> Option<(Vec<usize>, CageFit)> {
+        let cage = &self.cages[cage];
+        let mut empty = Vec::with_capacity(cage.get_cells().len());
+        let mut used = 0;
+        let mut placed_sum = 0;
+        for &position in cage.get_cells() {
+            match self.solution[position] {
+                0 => empty.push(position),
+                value => {
+                    used |= 1 << (value - 1);
+                    placed_sum += value as u32;
+                }
             }
         }
-        count
+        let candidates: Vec<u32> = empty.iter().map(|&p| self.candidates[p]).collect();
+        let left = cage.get_sum().checked_sub(placed_sum)?;
+        killer::fit_cage(&candidates, used, left).map(|fit| (empty, fit))
     }
 
-    fn are_possibilities_same(&self, position1: usize, position2: usize) -> bool {
-        for val_index in 0..ROW_COL_SEC_SIZE {
-            let val_pos1 = QQWing::get_possibility_index(val_index, position1);
-            let val_pos2 = QQWing::get_possibility_index(val_index, position2);
-            if (self.possibilities[val_pos1] == 0 || self.possibilities[val_pos2] == 0)
-                && (self.possibilities[val_pos1] != 0 || self.possibilities[val_pos2] != 0)
-            {
-                return false;
+    /**
+     * Remove the possibilities of a cell that fit no value left for the
+     * other cell of a rule between them, such as a Kropki dot.
+     */
+    fn pair_rule_reduction(&mut self, round: u16) -> bool {
+        debug!("pair_rule_reduction round: {}", round);
+        for rule in 0..self.pair_rules.len() {
+            let [first, second] = self.pair_rules[rule].get_cells();
+            let mut done_something = false;
+            for (position, partner) in [(first, second), (second, first)] {
+                if self.solution[position] != 0 {
+                    continue;
+                }
+                let left = match self.solution[partner] {
+                    0 => self.candidates[partner],
+                    value => 1 << (value - 1),
+                };
+                let fits = self.pair_rules[rule].support(partner, left);
+                if self.eliminate_values(position, !fits, round) {
+                    done_something = true;
+                }
+            }
+            if done_something {
+                if self.is_tracking() {
+                    let log_type = self.pair_rules[rule].get_log_type().clone();
+                    self.add_history_item(self.new_log_item(round, log_type, 0, first));
+                }
+                return true;
             }
         }
-        true
+        false
     }
 
-    fn remove_possibilities_in_one_from_two(
-        &mut self,
-        position1: usize,
-        position2: usize,
-        round: u8,
-    ) -> bool {
-        let mut done_something = false;
-        for val_index in 0..ROW_COL_SEC_SIZE {
-            let val_pos1 = QQWing::get_possibility_index(val_index, position1);
-            let val_pos2 = QQWing::get_possibility_index(val_index, position2);
-
-            if self.possibilities[val_pos1] == 0 && self.possibilities[val_pos2] == 0 {
-                self.possibilities[val_pos2] = round;
-                done_something = true;
+    /**
+     * Remove the possibilities of the cells of a thermometer, arrow or
+     * sandwich sum that fit no way of keeping to it.
+     */
+    fn clue_reduction(&mut self, round: u16) -> bool {
+        debug!("clue_reduction round: {}", round);
+        for clue in 0..self.clues.len() {
+            let Some(values) = self.clue_fit(clue) else {
+                continue;
+            };
+            let mut done_something = false;
+            for (i, &value) in values.iter().enumerate() {
+                let position = self.clue_cells[clue][i];
+                if self.solution[position] == 0 && self.eliminate_values(position, !value, round) {
+                    done_something = true;
+                }
+            }
+            if done_something {
+                if self.is_tracking() {
+                    let log_type = self.clues[clue].rule().log_type();
+                    let position = self.clue_cells[clue][0];
+                    self.add_history_item(self.new_log_item(round, log_type, 0, position));
+                }
+                return true;
             }
         }
-        done_something
+        false
     }
 
-    fn hidden_pair_in_column(&mut self, round: u8) -> bool {
-        debug!("hidden_pair_in_column round: {}", round);
-        for column in 0..ROW_COL_SEC_SIZE {
-            for val_index in 0..ROW_COL_SEC_SIZE {
-                let mut r1 = UNSET_VALUE;
-                let mut r2 = UNSET_VALUE;
-                let mut val_count = 0;
-                for row in 0..ROW_COL_SEC_SIZE {
-                    let position = QQWing::row_column_to_cell(row, column);
-                    let val_pos = QQWing::get_possibility_index(val_index, position);
-                    if self.possibilities[val_pos] == 0 {
-                        if r1 == UNSET_VALUE || r1 == row {
-                            r1 = row;
-                        } else if r2 == UNSET_VALUE || r2 == row {
-                            r2 = row;
-                        }
-                        val_count += 1;
-                    }
+    /**
+     * The values left for each cell of a clue that fit some way of keeping
+     * to it, in the order of clue_cells, with a placed cell having only its
+     * own value. None if there is no way left.
+     */
+    fn clue_fit(&self, clue: usize) -> Option<Vec<u32>> {
+        let mut values: Vec<u32> = self.clue_cells[clue]
+            .iter()
+            .map(|&position| match self.solution[position] {
+                0 => self.candidates[position],
+                value => 1 << (value - 1),
+            })
+            .collect();
+        self.clues[clue]
+            .rule()
+            .prune(&self.layout, &mut values)
+            .then_some(values)
+    }
+
+    /**
+     * Remove the possibilities from the cells of a killer cage that aren't
+     * part of any way of making its sum with different values.
+     */
+    fn cage_combination_reduction(&mut self, round: u16) -> bool {
+        debug!("cage_combination_reduction round: {}", round);
+        for cage in 0..self.cages.len() {
+            let Some((empty, fit)) = self.cage_fit(cage) else {
+                continue;
+            };
+            let mut done_something = false;
+            for (i, &position) in empty.iter().enumerate() {
+                if self.eliminate_values(position, !fit.values[i], round) {
+                    done_something = true;
                 }
-                if val_count == 2 {
-                    for val_index2 in (val_index + 1)..ROW_COL_SEC_SIZE {
-                        let mut r3 = UNSET_VALUE;
-                        let mut r4 = UNSET_VALUE;
-                        let mut val_count2 = 0;
-                        for row in 0..ROW_COL_SEC_SIZE {
-                            let position = QQWing::row_column_to_cell(row, column);
-                            let val_pos = QQWing::get_possibility_index(val_index2, position);
-                            if self.possibilities[val_pos] == 0 {
-                                if r3 == UNSET_VALUE || r3 == row {
-                                    r3 = row;
-                                } else if r4 == UNSET_VALUE || r4 == row {
-                                    r4 = row;
-                                }
-                                val_count2 += 1;
-                            }
-                        }
-                        if val_count2 == 2 && r1 == r3 && r2 == r4 {
-                            let mut done_something = false;
-                            for val_index3 in 0..ROW_COL_SEC_SIZE {
-                                if val_index3 != val_index && val_index3 != val_index2 {
-                                    let position1 = QQWing::row_column_to_cell(r1, column);
-                                    let position2 = QQWing::row_column_to_cell(r2, column);
-                                    let val_pos1 =
-                                        QQWing::get_possibility_index(val_index3, position1);
-                                    let val_pos2 =
-                                        QQWing::get_possibility_index(val_index3, position2);
-                                    if self.possibilities[val_pos1] == 0 {
-                                        self.possibilities[val_pos1] = round;
-                                        done_something = true;
-                                    }
-                                    if self.possibilities[val_pos2] == 0 {
-                                        self.possibilities[val_pos2] = round;
-                                        done_something = true;
-                                    }
-                                }
-                            }
-                            if done_something {
-                                if self.log_history || self.record_history {
-                                    self.add_history_item(LogItem::new(
-                                        round,
-                                        LogType::HiddenPairColumn,
-                                        val_index + 1,
-                                        QQWing::row_column_to_cell(r1, column),
-                                    ));
-                                }
-                                return true;
-                            }
-                        }
-                    }
+            }
+            if done_something {
+                if self.is_tracking() {
+                    self.add_history_item(self.new_log_item(
+                        round,
+                        LogType::CageCombination,
+                        0,
+                        self.cages[cage].get_cells()[0],
+                    ));
                 }
+                return true;
             }
         }
         false
     }
 
-    fn hidden_pair_in_section(&mut self, round: u8) -> bool {
-        debug!("hidden_pair_in_section round: {}", round);
-        for section in 0..ROW_COL_SEC_SIZE {
-            for val_index in 0..ROW_COL_SEC_SIZE {
-                let mut si1 = UNSET_VALUE;
-                let mut si2 = UNSET_VALUE;
-                let mut val_count = 0;
-                for sec_ind in 0..ROW_COL_SEC_SIZE {
-                    let position = QQWing::section_to_cell(section, sec_ind);
-                    let val_pos = QQWing::get_possibility_index(val_index, position);
-                    if self.possibilities[val_pos] == 0 {
-                        if si1 == UNSET_VALUE || si1 == sec_ind {
-                            si1 = sec_ind;
-                        } else if si2 == UNSET_VALUE || si2 == sec_ind {
-                            si2 = sec_ind;
+    /**
+     * Apply the rule of 45 to each row, column and section: the values in a
+     * unit always add up to the same total, so the cells of the unit left
+     * over by the cages inside it (the innies) must make up the difference,
+     * and when the cages touching a unit cover it, the cells they have
+     * outside it (the outies) must make up the excess. Innies are all in the
+     * unit, so they are treated as one more cage. Outies may repeat values,
+     * so only a single outie is used.
+     */
+    fn cage_rule_of_45(&mut self, round: u16) -> bool {
+        debug!("cage_rule_of_45 round: {}", round);
+        if self.cages.is_empty() {
+            return false;
+        }
+        let layout = Arc::clone(&self.layout);
+        let total = killer::mask_sum(layout.all_values());
+        let units = (0..layout.get_size()).flat_map(|i| {
+            [
+                layout.row_cells(i),
+                layout.column_cells(i),
+                layout.section_cells(i),
+            ]
+        });
+        for cells in units {
+            let mut touching: Vec<usize> =
+                cells.iter().filter_map(|&p| self.cell_cages[p]).collect();
+            touching.sort_unstable();
+            touching.dedup();
+            let mut inside_sum = 0;
+            let mut covering_sum = 0;
+            let mut outies = Vec::new();
+            let mut innies: Vec<usize> = cells.to_vec();
+            for &cage in &touching {
+                let cage_cells = self.cages[cage].get_cells();
+                covering_sum += self.cages[cage].get_sum();
+                if cage_cells.iter().all(|p| cells.contains(p)) {
+                    inside_sum += self.cages[cage].get_sum();
+                    innies.retain(|p| !cage_cells.contains(p));
+                } else {
+                    outies.extend(cage_cells.iter().filter(|p| !cells.contains(p)));
+                }
+            }
+
+            let mut done_something = false;
+            let mut position = cells[0];
+            if innies.len() < cells.len() && !innies.is_empty() {
+                let mut used = 0;
+                let mut left = total.checked_sub(inside_sum);
+                let mut empty = Vec::new();
+                for &p in &innies {
+                    match self.solution[p] {
+                        0 => empty.push(p),
+                        value => {
+                            used |= 1 << (value - 1);
+                            left = left.and_then(|left| left.checked_sub(value as u32));
                         }
-                        val_count += 1;
                     }
                 }
-                if val_count == 2 {
-                    for val_index2 in (val_index + 1)..ROW_COL_SEC_SIZE {
-                        let mut si3 = UNSET_VALUE;
-                        let mut si4 = UNSET_VALUE;
-                        let mut val_count2 = 0;
-                        for sec_ind in 0..ROW_COL_SEC_SIZE {
-                            let position = QQWing::section_to_cell(section, sec_ind);
-                            let val_pos = QQWing::get_possibility_index(val_index2, position);
-                            if self.possibilities[val_pos] == 0 {
-                                if si3 == UNSET_VALUE || si3 == sec_ind {
-                                    si3 = sec_ind;
-                                } else if si4 == UNSET_VALUE || si4 == sec_ind {
-                                    si4 = sec_ind;
-                                }
-                                val_count2 += 1;
-                            }
-                        }
-                        if val_count2 == 2 && si1 == si3 && si2 == si4 {
-                            let mut done_something = false;
-                            for val_index3 in 0..ROW_COL_SEC_SIZE {
-                                if val_index3 != val_index && val_index3 != val_index2 {
-                                    let position1 = QQWing::section_to_cell(section, si1);
-                                    let position2 = QQWing::section_to_cell(section, si2);
-                                    let val_pos1 =
-                                        QQWing::get_possibility_index(val_index3, position1);
-                                    let val_pos2 =
-                                        QQWing::get_possibility_index(val_index3, position2);
-                                    if self.possibilities[val_pos1] == 0 {
-                                        self.possibilities[val_pos1] = round;
-                                        done_something = true;
-                                    }
-                                    if self.possibilities[val_pos2] == 0 {
-                                        self.possibilities[val_pos2] = round;
-                                        done_something = true;
-                                    }
-                                }
-                            }
-                            if done_something {
-                                if self.log_history || self.record_history {
-                                    self.add_history_item(LogItem::new(
-                                        round,
-                                        LogType::HiddenPairSection,
-                                        val_index + 1,
-                                        QQWing::section_to_cell(section, si1),
-                                    ));
-                                }
-                                return true;
-                            }
+                let candidates: Vec<u32> = empty.iter().map(|&p| self.candidates[p]).collect();
+                if let Some(fit) = left.and_then(|left| killer::fit_cage(&candidates, used, left)) {
+                    for (i, &p) in empty.iter().enumerate() {
+                        if self.eliminate_values(p, !fit.values[i], round) {
+                            done_something = true;
+                            position = p;
                         }
                     }
                 }
             }
+            let covered = cells.iter().all(|&p| self.cell_cages[p].is_some());
+            if !done_something && covered && outies.len() == 1 && self.solution[outies[0]] == 0 {
+                if let Some(value) = covering_sum.checked_sub(total) {
+                    // A sum that isn't a value leaves the outie nothing.
+                    let keep = match (1..=layout.get_size() as u32).contains(&value) {
+                        true => 1 << (value - 1),
+                        false => 0,
+                    };
+                    if self.eliminate_values(outies[0], !keep, round) {
+                        done_something = true;
+                        position = outies[0];
+                    }
+                }
+            }
+            if done_something {
+                if self.is_tracking() {
+                    self.add_history_item(self.new_log_item(
+                        round,
+                        LogType::CageRuleOf45,
+                        0,
+                        position,
+                    ));
+                }
+                return true;
+            }
         }
         false
     }
 
-    fn hidden_pair_in_row(&mut self, round: u8) -> bool {
-        debug!("hidden_pair_in_row round: {}", round);
-        for row in 0..ROW_COL_SEC_SIZE {
-            for val_index in 0..ROW_COL_SEC_SIZE {
-                let mut c1 = UNSET_VALUE;
-                let mut c2 = UNSET_VALUE;
-                let mut val_count = 0;
-                for column in 0..ROW_COL_SEC_SIZE {
-                    let position = QQWing::row_column_to_cell(row, column);
-                    let val_pos = QQWing::get_possibility_index(val_index, position);
-                    if self.possibilities[val_pos] == 0 {
-                        if c1 == UNSET_VALUE || c1 == column {
-                            c1 = column;
-                        } else if c2 == UNSET_VALUE || c2 == column {
-                            c2 = column;
+    /**
+     * Look for a value that every way of filling a killer cage uses, and
+     * remove it from the cells outside the cage that see every cell of the
+     * cage it can go in.
+     */
+    fn cage_pointing(&mut self, round: u16) -> bool {
+        debug!("cage_pointing round: {}", round);
+        let layout = Arc::clone(&self.layout);
+        for cage in 0..self.cages.len() {
+            let Some((empty, fit)) = self.cage_fit(cage) else {
+                continue;
+            };
+            let mut required = fit.required;
+            while required != 0 {
+                let val_index = required.trailing_zeros() as usize;
+                required &= required - 1;
+                let places: Vec<usize> = empty
+                    .iter()
+                    .zip(&fit.values)
+                    .filter(|&(_, &values)| values & (1 << val_index) != 0)
+                    .map(|(&position, _)| position)
+                    .collect();
+                let Some(&first) = places.first() else {
+                    continue;
+                };
+                let row = layout.cell_to_row(first);
+                let column = layout.cell_to_column(first);
+                let section = layout.cell_to_section(first);
+                let units = [
+                    (
+                        places.iter().all(|&p| layout.cell_to_row(p) == row),
+                        layout.row_cells(row),
+                    ),
+                    (
+                        places.iter().all(|&p| layout.cell_to_column(p) == column),
+                        layout.column_cells(column),
+                    ),
+                    (
+                        places.iter().all(|&p| layout.cell_to_section(p) == section),
+                        layout.section_cells(section),
+                    ),
+                ];
+                let extra_units = layout.cell_to_extra_units(first).iter().map(|&unit| {
+                    (
+                        places
+                            .iter()
+                            .all(|&p| layout.cell_to_extra_units(p).contains(&unit)),
+                        layout.extra_unit_cells(unit),
+                    )
+                });
+                let mut done_something = false;
+                for (shared, cells) in units.into_iter().chain(extra_units) {
+                    if !shared {
+                        continue;
+                    }
+                    for &position in cells {
+                        if self.cell_cages[position] != Some(cage)
+                            && self.eliminate(val_index, position, round)
+                        {
+                            done_something = true;
                         }
-                        val_count += 1;
                     }
                 }
-                if val_count == 2 {
-                    for val_index2 in (val_index + 1)..ROW_COL_SEC_SIZE {
-                        let mut c3 = UNSET_VALUE;
-                        let mut c4 = UNSET_VALUE;
-                        let mut val_count2 = 0;
-                        for column in 0..ROW_COL_SEC_SIZE {
-                            let position = QQWing::row_column_to_cell(row, column);
-                            let val_pos = QQWing::get_possibility_index(val_index2, position);
-                            if self.possibilities[val_pos] == 0 {
-                                if c3 == UNSET_VALUE || c3 == column {
-                                    c3 = column;
-                                } else if c4 == UNSET_VALUE || c4 == column {
-                                    c4 = column;
-                                }
-                                val_count2 += 1;
-                            }
-                        }
-                        if val_count2 == 2 && c1 == c3 && c2 == c4 {
-                            let mut done_something = false;
-                            for val_index3 in 0..ROW_COL_SEC_SIZE {
-                                if val_index3 != val_index && val_index3 != val_index2 {
-                                    let position1 = QQWing::row_column_to_cell(row, c1);
-                                    let position2 = QQWing::row_column_to_cell(row, c2);
-                                    let val_pos1 =
-                                        QQWing::get_possibility_index(val_index3, position1);
-                                    let val_pos2 =
-                                        QQWing::get_possibility_index(val_index3, position2);
-                                    if self.possibilities[val_pos1] == 0 {
-                                        self.possibilities[val_pos1] = round;
-                                        done_something = true;
-                                    }
-                                    if self.possibilities[val_pos2] == 0 {
-                                        self.possibilities[val_pos2] = round;
-                                        done_something = true;
-                                    }
-                                }
-                            }
-                            if done_something {
-                                if self.log_history || self.record_history {
-                                    self.add_history_item(LogItem::new(
-                                        round,
-                                        LogType::HiddenPairRow,
-                                        val_index + 1,
-                                        QQWing::row_column_to_cell(row, c1),
-                                    ));
-                                }
-                                return true;
-                            }
-                        }
+                if done_something {
+                    if self.is_tracking() {
+                        self.add_history_item(self.new_log_item(
+                            round,
+                            LogType::CagePointing,
+                            val_index + 1,
+                            first,
+                        ));
                     }
+                    return true;
                 }
             }
         }
         false
     }
 
-    fn handle_naked_pairs(&mut self, round: u8) -> bool {
+    fn handle_naked_pairs(&mut self, round: u16) -> bool {
         debug!("handle_naked_pairs round: {}", round);
-        for position in 0..BOARD_SIZE {
-            let possibilities = self.count_possibilities(position);
-            if possibilities == 2 {
-                let row = QQWing::cell_to_row(position);
-                let column = QQWing::cell_to_column(position);
-                let section = QQWing::cell_to_section_start_cell(position);
-                for position2 in position..BOARD_SIZE {
-                    if position != position2 {
-                        let possibilities2 = self.count_possibilities(position2);
-                        if possibilities2 == 2 && self.are_possibilities_same(position, position2) {
-                            if row == QQWing::cell_to_row(position2) {
-                                let mut done_something = false;
-                                for column2 in 0..ROW_COL_SEC_SIZE {
-                                    let position3 = QQWing::row_column_to_cell(row, column2);
-                                    if position3 != position
-                                        && position3 != position2
-                                        && self.remove_possibilities_in_one_from_two(
-                                            position, position3, round,
-                                        )
-                                    {
-                                        done_something = true;
-                                    }
-                                }
-                                if done_something {
-                                    if self.log_history || self.record_history {
-                                        self.add_history_item(LogItem::new(
-                                            round,
-                                            LogType::NakedPairRow,
-                                            0,
-                                            position,
-                                        ));
-                                    }
-                                    return true;
-                                }
-                            }
-                            if column == QQWing::cell_to_column(position2) {
-                                let mut done_something = false;
-                                for row2 in 0..ROW_COL_SEC_SIZE {
-                                    let position3 = QQWing::row_column_to_cell(row2, column);
-                                    if position3 != position
-                                        && position3 != position2
-                                        && self.remove_possibilities_in_one_from_two(
-                                            position, position3, round,
-                                        )
-                                    {
-                                        done_something = true;
-                                    }
-                                }
-                                if done_something {
-                                    if self.log_history || self.record_history {
-                                        self.add_history_item(LogItem::new(
-                                            round,
-                                            LogType::NakedPairColumn,
-                                            0,
-                                            position,
-                                        ));
-                                    }
-                                    return true;
-                                }
-                            }
-                            if section == QQWing::cell_to_section_start_cell(position2) {
-                                let mut done_something = false;
-                                let sec_start = QQWing::cell_to_section_start_cell(position);
-                                for i in 0..GRID_SIZE {
-                                    for j in 0..GRID_SIZE {
-                                        let position3 = sec_start + i + (ROW_COL_SEC_SIZE * j);
-                                        if position3 != position
-                                            && position3 != position2
-                                            && self.remove_possibilities_in_one_from_two(
-                                                position, position3, round,
-                                            )
-                                        {
-                                            done_something = true;
-                                        }
-                                    }
-                                }
-                                if done_something {
-                                    if self.log_history || self.record_history {
-                                        self.add_history_item(LogItem::new(
-                                            round,
-                                            LogType::NakedPairSection,
-                                            0,
-                                            position,
-                                        ));
-                                    }
-                                    return true;
-                                }
-                            }
+        let layout = Arc::clone(&self.layout);
+        for position in 0..layout.get_board_size() {
+            if self.count_possibilities(position) != 2 {
+                continue;
+            }
+            let row = layout.cell_to_row(position);
+            let column = layout.cell_to_column(position);
+            let section = layout.cell_to_section(position);
+            for position2 in (position + 1)..layout.get_board_size() {
+                if self.count_possibilities(position2) != 2
+                    || !self.are_possibilities_same(position, position2)
+                {
+                    continue;
+                }
+                let units = [
+                    (
+                        row == layout.cell_to_row(position2),
+                        layout.row_cells(row),
+                        LogType::NakedPairRow,
+                    ),
+                    (
+                        column == layout.cell_to_column(position2),
+                        layout.column_cells(column),
+                        LogType::NakedPairColumn,
+                    ),
+                    (
+                        section == layout.cell_to_section(position2),
+                        layout.section_cells(section),
+                        LogType::NakedPairSection,
+                    ),
+                ];
+                let extra_units = layout.cell_to_extra_units(position).iter().map(|&unit| {
+                    (
+                        layout.cell_to_extra_units(position2).contains(&unit),
+                        layout.extra_unit_cells(unit),
+                        if layout.is_diagonal(unit) {
+                            LogType::NakedPairDiagonal
+                        } else {
+                            LogType::NakedPairExtraRegion
+                        },
+                    )
+                });
+                for (shared, cells, log_type) in units.into_iter().chain(extra_units) {
+                    if !shared {
+                        continue;
+                    }
+                    let mut done_something = false;
+                    for &position3 in cells {
+                        if position3 != position
+                            && position3 != position2
+                            && self.remove_possibilities_in_one_from_two(position, position3, round)
+                        {
+                            done_something = true;
+                        }
+                    }
+                    if done_something {
+                        if self.is_tracking() {
+                            self.add_history_item(self.new_log_item(round, log_type, 0, position));
                         }
+                        return true;
                     }
                 }
             }
@@ -1428,36 +2745,25 @@ impl QQWing {
      * is only listed for one cell. This type of cell is often called a
      * "hidden single"
      */
-    fn only_value_in_row(&mut self, round: u8) -> bool {
+    fn only_value_in_row(&mut self, round: u16) -> Result<bool, QQWingError> {
         debug!("only_value_in_row round: {}", round);
-        for row in 0..ROW_COL_SEC_SIZE {
-            for val_index in 0..ROW_COL_SEC_SIZE {
-                let mut count = 0;
-                let mut last_position = 0;
-                for col in 0..ROW_COL_SEC_SIZE {
-                    let position = (row * ROW_COL_SEC_SIZE) + col;
-                    let val_pos = QQWing::get_possibility_index(val_index, position);
-                    if self.possibilities[val_pos] == 0 {
-                        count += 1;
-                        last_position = position;
-                    }
-                }
-                if count == 1 {
-                    let value = val_index + 1;
-                    if self.log_history || self.record_history {
-                        self.add_history_item(LogItem::new(
-                            round,
-                            LogType::HiddenSingleRow,
-                            value,
-                            last_position,
-                        ));
-                    }
-                    let _ = self.mark(last_position, round, value as u8).unwrap();
-                    return true;
+        let layout = Arc::clone(&self.layout);
+        for row in 0..layout.get_size() {
+            if let Some((val_index, position)) = self.find_hidden_single(layout.row_cells(row)) {
+                let value = val_index + 1;
+                if self.is_tracking() {
+                    self.add_history_item(self.new_log_item(
+                        round,
+                        LogType::HiddenSingleRow,
+                        value,
+                        position,
+                    ));
                 }
+                self.mark(position, round, value as u8)?;
+                return Ok(true);
             }
         }
-        false
+        Ok(false)
     }
 
     /**
@@ -1466,36 +2772,25 @@ impl QQWing {
      * possibility that is only listed for one cell. This type of cell is often
      * called a "hidden single"
      */
-    fn only_value_in_column(&mut self, round: u8) -> bool {
+    fn only_value_in_column(&mut self, round: u16) -> Result<bool, QQWingError> {
         debug!("only_value_in_column round: {}", round);
-        for col in 0..ROW_COL_SEC_SIZE {
-            for val_index in 0..ROW_COL_SEC_SIZE {
-                let mut count = 0;
-                let mut last_position = 0;
-                for row in 0..ROW_COL_SEC_SIZE {
-                    let position = QQWing::row_column_to_cell(row, col);
-                    let val_pos = QQWing::get_possibility_index(val_index, position);
-                    if self.possibilities[val_pos] == 0 {
-                        count += 1;
-                        last_position = position;
-                    }
-                }
-                if count == 1 {
-                    let value = val_index + 1;
-                    if self.log_history || self.record_history {
-                        self.add_history_item(LogItem::new(
-                            round,
-                            LogType::HiddenSingleColumn,
-                            value,
-                            last_position,
-                        ));
-                    }
-                    let _ = self.mark(last_position, round, value as u8).unwrap();
-                    return true;
+        let layout = Arc::clone(&self.layout);
+        for col in 0..layout.get_size() {
+            if let Some((val_index, position)) = self.find_hidden_single(layout.column_cells(col)) {
+                let value = val_index + 1;
+                if self.is_tracking() {
+                    self.add_history_item(self.new_log_item(
+                        round,
+                        LogType::HiddenSingleColumn,
+                        value,
+                        position,
+                    ));
                 }
+                self.mark(position, round, value as u8)?;
+                return Ok(true);
             }
         }
-        false
+        Ok(false)
     }
 
     /**
@@ -1504,39 +2799,77 @@ impl QQWing {
      * possibility that is only listed for one cell. This type of cell is often
      * called a "hidden single"
      */
-    fn only_value_in_section(&mut self, round: u8) -> bool {
+    fn only_value_in_section(&mut self, round: u16) -> Result<bool, QQWingError> {
         debug!("only_value_in_section round: {}", round);
-        for sec in 0..ROW_COL_SEC_SIZE {
-            let sec_pos = QQWing::section_to_first_cell(sec);
-            for val_index in 0..ROW_COL_SEC_SIZE {
-                let mut count = 0;
-                let mut last_position = 0;
-                for i in 0..GRID_SIZE {
-                    for j in 0..GRID_SIZE {
-                        let position = sec_pos + i + ROW_COL_SEC_SIZE * j;
-                        let val_pos = QQWing::get_possibility_index(val_index, position);
-                        if self.possibilities[val_pos] == 0 {
-                            count += 1;
-                            last_position = position;
-                        }
-                    }
+        let layout = Arc::clone(&self.layout);
+        for sec in 0..layout.get_size() {
+            if let Some((val_index, position)) = self.find_hidden_single(layout.section_cells(sec))
+            {
+                let value = val_index + 1;
+                if self.is_tracking() {
+                    self.add_history_item(self.new_log_item(
+                        round,
+                        LogType::HiddenSingleSection,
+                        value,
+                        position,
+                    ));
                 }
-                if count == 1 {
-                    let value = val_index + 1;
-                    if self.log_history || self.record_history {
-                        self.add_history_item(LogItem::new(
-                            round,
-                            LogType::HiddenSingleSection,
-                            value,
-                            last_position,
-                        ));
-                    }
-                    let _ = self.mark(last_position, round, value as u8).unwrap();
-                    return true;
+                self.mark(position, round, value as u8)?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /**
+     * Mark exactly one cell which is the only possible value for some extra
+     * unit, a diagonal of a Sudoku-X board or an extra region, if such a
+     * cell exists. This type of cell is often called a "hidden single"
+     */
+    fn only_value_in_extra_unit(&mut self, round: u16) -> Result<bool, QQWingError> {
+        debug!("only_value_in_extra_unit round: {}", round);
+        let layout = Arc::clone(&self.layout);
+        for unit in 0..layout.get_extra_unit_count() {
+            if let Some((val_index, position)) =
+                self.find_hidden_single(layout.extra_unit_cells(unit))
+            {
+                let value = val_index + 1;
+                if self.is_tracking() {
+                    let log_type = if layout.is_diagonal(unit) {
+                        LogType::HiddenSingleDiagonal
+                    } else {
+                        LogType::HiddenSingleExtraRegion
+                    };
+                    self.add_history_item(self.new_log_item(round, log_type, value, position));
                 }
+                self.mark(position, round, value as u8)?;
+                return Ok(true);
             }
         }
-        false
+        Ok(false)
+    }
+
+    /**
+     * Find the lowest value that is possible in exactly one of the given cells
+     * of a row, column, section or extra unit. Returns the value (0 based)
+     * and the cell it is possible in.
+     */
+    fn find_hidden_single(&self, cells: &[usize]) -> Option<(usize, usize)> {
+        let mut once = 0;
+        let mut twice = 0;
+        for &position in cells {
+            twice |= once & self.candidates[position];
+            once |= self.candidates[position];
+        }
+        let singles = once & !twice;
+        if singles == 0 {
+            return None;
+        }
+        let val_index = singles.trailing_zeros() as usize;
+        cells
+            .iter()
+            .find(|&&position| self.is_possible(val_index, position))
+            .map(|&position| (val_index, position))
     }
 
     /**
@@ -1544,132 +2877,204 @@ impl QQWing {
      * exists. This method will look for a cell that has only one possibility.
      * This type of cell is often called a "single"
      */
-    fn only_possibility_for_cell(&mut self, round: u8) -> bool {
+    fn only_possibility_for_cell(&mut self, round: u16) -> Result<bool, QQWingError> {
         debug!("only_possibility_for_cell round: {}", round);
-        for position in 0..BOARD_SIZE {
-            if self.solution[position] == 0 {
-                let mut count = 0;
-                let mut last_value = 0;
-                for val_index in 0..ROW_COL_SEC_SIZE {
-                    let val_pos = QQWing::get_possibility_index(val_index, position);
-                    if self.possibilities[val_pos] == 0 {
-                        count += 1;
-                        last_value = val_index + 1;
-                    }
-                }
-                if count == 1 {
-                    let _ = self.mark(position, round, last_value as u8).unwrap();
-                    if self.log_history || self.record_history {
-                        self.add_history_item(LogItem::new(
-                            round,
-                            LogType::Single,
-                            last_value,
-                            position,
-                        ));
-                    }
-                    return true;
+        for position in 0..self.layout.get_board_size() {
+            if self.solution[position] == 0 && self.count_possibilities(position) == 1 {
+                let last_value = self.candidates[position].trailing_zeros() as usize + 1;
+                self.mark(position, round, last_value as u8)?;
+                if self.is_tracking() {
+                    self.add_history_item(self.new_log_item(
+                        round,
+                        LogType::Single,
+                        last_value,
+                        position,
+                    ));
                 }
+                return Ok(true);
             }
         }
-        false
+        Ok(false)
     }
 
     /**
      * Mark the given value at the given position. Go through the row, column,
-     * and section for the position and remove the value from the possibilities.
+     * section and any extra units and cage for the position and remove the
+     * value from the possibilities.
      *
-     * @param position Position into the board (0-80)
+     * @param position Position into the board
      * @param round Round to mark for rollback purposes
      * @param value The value to go in the square at the given position
      */
-    fn mark(&mut self, position: usize, round: u8, value: u8) -> Result<bool, QQWingError> {
+    fn mark(&mut self, position: usize, round: u16, value: u8) -> Result<(), QQWingError> {
         debug!(
             "Mark position: {}, round: {}, value: {}",
             position, round, value
         );
         if self.solution[position] != 0 {
-            return Err(QQWingError::PositionAlreadyMarked);
-        }
-        if self.solution_round[position] != 0 {
-            return Err(QQWingError::PositionAlreadyMarked);
+            let marked_round = self.solution_round[position];
+            if marked_round != round {
+                return Err(QQWingError::PositionMarkedAnotherRound {
+                    position,
+                    value,
+                    round,
+                    marked_round,
+                });
+            }
+            return Err(QQWingError::PositionAlreadyMarked {
+                position,
+                value,
+                round,
+            });
         }
 
         let val_index = value - 1;
+        if !self.is_possible(val_index as usize, position) {
+            return Err(QQWingError::PositionImpossible {
+                position,
+                value,
+                round,
+            });
+        }
         self.solution[position] = value;
+        self.solution_round[position] = round;
+        let layout = Arc::clone(&self.layout);
+        let row = layout.cell_to_row(position);
+        let column = layout.cell_to_column(position);
+        let section = layout.cell_to_section(position);
+        let value_bit = 1 << val_index;
+        self.row_values[row] |= value_bit;
+        self.column_values[column] |= value_bit;
+        self.section_values[section] |= value_bit;
+        for &unit in layout.cell_to_extra_units(position) {
+            self.extra_values[unit] |= value_bit;
+        }
+        self.trail.push(Change::Place { round, position });
+
+        // Take this value out of the possibilities for everything in the row,
+        // column, section, extra units and cage, and for the cells a ruled
+        // out knight's or king's move away
+        for &peer in layout
+            .row_cells(row)
+            .iter()
+            .chain(layout.column_cells(column))
+            .chain(layout.section_cells(section))
+            .chain(
+                layout
+                    .cell_to_extra_units(position)
+                    .iter()
+                    .flat_map(|&unit| layout.extra_unit_cells(unit)),
+            )
+            .chain(layout.move_peers(position))
+        {
+            self.eliminate(val_index as usize, peer, round);
+        }
+        for i in 0..self.constraint_peers[position].len() {
+            let peer = self.constraint_peers[position][i];
+            self.eliminate(val_index as usize, peer, round);
+        }
+        if let Some(cage) = self.cell_cages[position] {
+            for i in 0..self.cages[cage].get_cells().len() {
+                let peer = self.cages[cage].get_cells()[i];
+                self.eliminate(val_index as usize, peer, round);
+            }
+        }
 
-        let poss_ind = QQWing::get_possibility_index(val_index as usize, position);
-        if self.possibilities[poss_ind] != 0 {
-            return Err(QQWingError::PositionAlreadyMarked);
+        // Keep to the values the rules between pairs of cells leave for the
+        // other cell of each rule.
+        for i in 0..self.cell_pair_rules[position].len() {
+            let rule = &self.pair_rules[self.cell_pair_rules[position][i]];
+            let partner = rule.partner(position);
+            let fits = rule.support(position, value_bit);
+            self.eliminate_values(partner, !fits, round);
         }
 
-        // Take this value out of the possibilities for everything in the row
-        self.solution_round[position] = round;
-        let row_start = QQWing::cell_to_row(position) * ROW_COL_SEC_SIZE;
-        for col in 0..ROW_COL_SEC_SIZE {
-            let row_val = row_start + col;
-            let val_pos = QQWing::get_possibility_index(val_index as usize, row_val);
-            // System.out.println("Row Start: "+row_start+" Row Value: "+rowVal+" Value Position: "+val_pos);
-            if self.possibilities[val_pos] == 0 {
-                self.possibilities[val_pos] = round;
-            }
-        }
-
-        // Take this value out of the possibilities for everything in the column
-        let col_start = QQWing::cell_to_column(position);
-        for i in 0..ROW_COL_SEC_SIZE {
-            let col_val = col_start + (ROW_COL_SEC_SIZE * i);
-            let val_pos = QQWing::get_possibility_index(val_index as usize, col_val);
-            // System.out.println("Col Start: "+col_start+" Col Value: "+colVal+" Value Position: "+val_pos);
-            if self.possibilities[val_pos] == 0 {
-                self.possibilities[val_pos] = round;
-            }
-        }
-
-        // Take this value out of the possibilities for everything in section
-        let sec_start = QQWing::cell_to_section_start_cell(position);
-        for i in 0..GRID_SIZE {
-            for j in 0..GRID_SIZE {
-                let sec_val = sec_start + i + (ROW_COL_SEC_SIZE * j);
-                let val_pos = QQWing::get_possibility_index(val_index as usize, sec_val);
-                // System.out.println("Sec Start: "+sec_start+" Sec Value: "+sec_val+" Value Position: "+val_pos);
-                if self.possibilities[val_pos] == 0 {
-                    self.possibilities[val_pos] = round;
+        // This position itself is determined, it should have possibilities.
+        self.eliminate_values(position, layout.all_values(), round);
+
+        // Then let the constraints take out what the placement rules out.
+        for i in 0..self.constraints.len() {
+            let eliminations =
+                self.constraints[i].propagate(&layout, &self.solution, &self.candidates, position);
+            for (cell, values) in eliminations {
+                if cell < self.candidates.len() {
+                    self.eliminate_values(cell, values, round);
                 }
             }
         }
+        Ok(())
+    }
 
-        // This position itself is determined, it should have possibilities.
-        for val_index in 0..ROW_COL_SEC_SIZE {
-            let val_pos = QQWing::get_possibility_index(val_index as usize, position);
-            if self.possibilities[val_pos] == 0 {
-                self.possibilities[val_pos] = round;
+    /**
+     * Whether a value (0 based) is still a possibility for a position.
+     */
+    fn is_possible(&self, val_index: usize, position: usize) -> bool {
+        self.candidates[position] & (1 << val_index) != 0
+    }
+
+    /**
+     * Remove a value (0 based) from the possibilities of a cell, recording the
+     * round for rollback purposes. Returns false if the value had already
+     * been removed.
+     */
+    fn eliminate(&mut self, val_index: usize, position: usize, round: u16) -> bool {
+        self.eliminate_values(position, 1 << val_index, round)
+    }
+
+    /**
+     * Remove every value in a set of values (one bit per value) from the
+     * possibilities of a cell, recording the round for rollback purposes.
+     * Returns false if none of the values were still possible.
+     */
+    fn eliminate_values(&mut self, position: usize, values: u32, round: u16) -> bool {
+        let removed = self.candidates[position] & values;
+        if removed == 0 {
+            return false;
+        }
+        self.candidates[position] &= !removed;
+        self.trail.push(Change::Eliminate {
+            round,
+            position,
+            values: removed,
+        });
+        if let Some(observer) = self.observer.as_mut() {
+            let mut bits = removed;
+            while bits != 0 {
+                observer.on_eliminate(position, bits.trailing_zeros() as usize + 1, round);
+                bits &= bits - 1;
             }
         }
-        Ok(true)
+        true
     }
 
     /**
-     * print the given BOARD_SIZEd array of ints as a sudoku puzzle. Use print
+     * print the given board sized array of ints as a sudoku puzzle. Use print
      * options from member variables.
      */
-    fn print(&self, sudoku: [u8; 81]) {
+    fn print(&self, sudoku: &[u8]) {
         println!("{}", self.puzzle_to_string(sudoku));
     }
 
-    fn puzzle_to_string(&self, sudoku: [u8; 81]) -> String {
+    fn puzzle_to_string(&self, sudoku: &[u8]) -> String {
+        if self.layout.is_jigsaw() && self.print_style == PrintStyle::READABLE {
+            return self.jigsaw_to_string(sudoku);
+        }
+        if self.has_parity() && self.print_style == PrintStyle::READABLE {
+            return self.shaded_to_string(sudoku);
+        }
+        let size = self.layout.get_size();
+        let board_size = self.layout.get_board_size();
+        let box_rows = self.layout.get_box_rows();
+        let box_columns = self.layout.get_box_columns();
         let mut sb = String::new();
-        for i in 0..BOARD_SIZE {
+        for (i, &value) in sudoku.iter().enumerate().take(board_size) {
             if self.print_style == PrintStyle::READABLE {
-                sb.push_str(" ");
+                sb.push(' ');
             }
-            if sudoku[i] == 0 {
-                sb.push_str(".");
-            } else {
-                sb.push_str(sudoku[i].to_string().as_str());
-            }
-            if i == BOARD_SIZE - 1 {
+            sb.push(self.layout.value_to_symbol(value));
+            if i == board_size - 1 {
                 if self.print_style == PrintStyle::CSV {
-                    sb.push_str(",");
+                    sb.push(',');
                 } else {
                     sb.push_str(NL);
                 }
@@ -1678,24 +3083,103 @@ impl QQWing {
                 {
                     sb.push_str(NL);
                 }
-            } else if i % ROW_COL_SEC_SIZE == ROW_COL_SEC_SIZE - 1 {
+            } else if i % size == size - 1 {
                 if self.print_style == PrintStyle::READABLE
                     || self.print_style == PrintStyle::COMPACT
                 {
                     sb.push_str(NL);
                 }
-                if i % SEC_GROUP_SIZE == SEC_GROUP_SIZE - 1 {
-                    if self.print_style == PrintStyle::READABLE {
-                        sb.push_str("-------|-------|-------");
-                        sb.push_str(NL);
-                    }
+                if (i / size) % box_rows == box_rows - 1 && self.print_style == PrintStyle::READABLE
+                {
+                    let segment = "-".repeat(2 * box_columns + 1);
+                    sb.push_str(&vec![segment; size / box_columns].join("|"));
+                    sb.push_str(NL);
+                }
+            } else if i % box_columns == box_columns - 1 && self.print_style == PrintStyle::READABLE
+            {
+                sb.push_str(" |");
+            }
+        }
+        sb
+    }
+
+    /**
+     * Draw a jigsaw board with its region borders: a '|' between cells of
+     * different regions side by side, and a '-' under a cell whose region
+     * differs from the cell below it.
+     */
+    fn jigsaw_to_string(&self, sudoku: &[u8]) -> String {
+        let layout = &self.layout;
+        let size = layout.get_size();
+        let region = |row: usize, column: usize| {
+            layout.cell_to_section(layout.row_column_to_cell(row, column))
+        };
+        let mut sb = String::new();
+        for row in 0..size {
+            for column in 0..size {
+                if column > 0 && region(row, column - 1) != region(row, column) {
+                    sb.push('|');
+                } else {
+                    sb.push(' ');
                 }
-            } else if i % GRID_SIZE == GRID_SIZE - 1 {
-                if self.print_style == PrintStyle::READABLE {
-                    sb.push_str(" |");
+                sb.push(layout.value_to_symbol(sudoku[layout.row_column_to_cell(row, column)]));
+            }
+            sb.push_str(NL);
+            if row + 1 < size {
+                let border: Vec<bool> = (0..size)
+                    .map(|column| region(row, column) != region(row + 1, column))
+                    .collect();
+                for column in 0..size {
+                    sb.push(if border[column] && (column == 0 || border[column - 1]) {
+                        '-'
+                    } else {
+                        ' '
+                    });
+                    sb.push(if border[column] { '-' } else { ' ' });
+                }
+                sb.push_str(NL);
+            }
+        }
+        sb.push_str(NL);
+        sb
+    }
+
+    /**
+     * Draw the board of an even-odd puzzle with its shading: a cell shaded
+     * even in square brackets and one shaded odd in round brackets, such as
+     * [.] and (7).
+     */
+    fn shaded_to_string(&self, sudoku: &[u8]) -> String {
+        let layout = &self.layout;
+        let size = layout.get_size();
+        let box_rows = layout.get_box_rows();
+        let box_columns = layout.get_box_columns();
+        let mut sb = String::new();
+        for row in 0..size {
+            let mut line = String::new();
+            for column in 0..size {
+                let position = layout.row_column_to_cell(row, column);
+                let (open, close) = match self.parity[position] {
+                    Some(Parity::Even) => ('[', ']'),
+                    Some(Parity::Odd) => ('(', ')'),
+                    None => (' ', ' '),
+                };
+                line.push(open);
+                line.push(layout.value_to_symbol(sudoku[position]));
+                line.push(close);
+                if column % box_columns == box_columns - 1 && column + 1 < size {
+                    line.push('|');
                 }
             }
+            sb.push_str(line.trim_end());
+            sb.push_str(NL);
+            if row % box_rows == box_rows - 1 && row + 1 < size {
+                let segment = "-".repeat(3 * box_columns);
+                sb.push_str(&vec![segment; size / box_columns].join("|"));
+                sb.push_str(NL);
+            }
         }
+        sb.push_str(NL);
         sb
     }
 
@@ -1714,25 +3198,39 @@ impl QQWing {
         let difficulty_string = self.get_difficulty();
         if self.print_style == PrintStyle::CSV {
             sb.push_str(format!("{:?}", difficulty_string).as_str());
-            sb.push_str(",");
+            sb.push(',');
             sb.push_str(given_count.to_string().as_str());
-            sb.push_str(",");
+            sb.push(',');
             sb.push_str(single_count.to_string().as_str());
-            sb.push_str(",");
+            sb.push(',');
             sb.push_str(hidden_single_count.to_string().as_str());
-            sb.push_str(",");
+            sb.push(',');
             sb.push_str(naked_pair_count.to_string().as_str());
-            sb.push_str(",");
+            sb.push(',');
             sb.push_str(hidden_pair_count.to_string().as_str());
-            sb.push_str(",");
+            sb.push(',');
             sb.push_str(pointing_pair_triple_count.to_string().as_str());
-            sb.push_str(",");
+            sb.push(',');
             sb.push_str(box_reduction_count.to_string().as_str());
-            sb.push_str(",");
+            sb.push(',');
             sb.push_str(guess_count.to_string().as_str());
-            sb.push_str(",");
+            sb.push(',');
             sb.push_str(backtrack_count.to_string().as_str());
-            sb.push_str(",");
+            sb.push(',');
+            if self.extra_stats {
+                // The same columns whatever the rules, so rows of different
+                // puzzles line up. Counts for rules the puzzle lacks are 0.
+                sb.push_str(self.get_cage_combination_count().to_string().as_str());
+                sb.push(',');
+                sb.push_str(self.get_cage_reduction_count().to_string().as_str());
+                sb.push(',');
+                sb.push_str(self.get_pair_rule_count().to_string().as_str());
+                sb.push(',');
+                sb.push_str(self.get_clue_count().to_string().as_str());
+                sb.push(',');
+                sb.push_str(self.get_symmetries().to_string().as_str());
+                sb.push(',');
+            }
         } else {
             sb.push_str("Difficulty: ");
             sb.push_str(format!("{:?}", difficulty_string).as_str());
@@ -1764,29 +3262,91 @@ impl QQWing {
             sb.push_str("Number of Backtracks: ");
             sb.push_str(backtrack_count.to_string().as_str());
             sb.push_str(NL);
+            if !self.cages.is_empty() {
+                sb.push_str("Number of Cage Combinations: ");
+                sb.push_str(self.get_cage_combination_count().to_string().as_str());
+                sb.push_str(NL);
+                sb.push_str("Number of Rule of 45/Cage Pointing Reductions: ");
+                sb.push_str(self.get_cage_reduction_count().to_string().as_str());
+                sb.push_str(NL);
+            }
+            if !self.pair_rules.is_empty() {
+                sb.push_str("Number of Pair Rule Reductions: ");
+                sb.push_str(self.get_pair_rule_count().to_string().as_str());
+                sb.push_str(NL);
+            }
+            if !self.clues.is_empty() {
+                sb.push_str("Number of Clue Reductions: ");
+                sb.push_str(self.get_clue_count().to_string().as_str());
+                sb.push_str(NL);
+            }
+            sb.push_str("Symmetry: ");
+            sb.push_str(self.get_symmetries().to_string().as_str());
+            sb.push_str(NL);
         }
         sb
     }
 
+    /**
+     * The stats as a JSON object on one line, with every count whatever the
+     * rules and the symmetries of the pattern of givens as a list of names,
+     * empty when it has none.
+     */
+    pub fn get_stats_json(&self) -> String {
+        let counts = [
+            ("givens", self.get_given_count() as usize),
+            ("singles", self.get_single_count()),
+            ("hidden_singles", self.get_hidden_single_count()),
+            ("naked_pairs", self.get_naked_pair_count()),
+            ("hidden_pairs", self.get_hidden_pair_count()),
+            (
+                "pointing_pairs_triples",
+                self.get_pointing_pair_triple_count(),
+            ),
+            ("box_line_reductions", self.get_box_line_reduction_count()),
+            ("guesses", self.get_guess_count()),
+            ("backtracks", self.get_backtrack_count()),
+            ("cage_combinations", self.get_cage_combination_count()),
+            ("cage_reductions", self.get_cage_reduction_count()),
+            ("pair_rule_reductions", self.get_pair_rule_count()),
+            ("clue_reductions", self.get_clue_count()),
+        ];
+        let mut fields = vec![format!("\"difficulty\":\"{:?}\"", self.get_difficulty())];
+        fields.extend(
+            counts
+                .iter()
+                .map(|(name, count)| format!("\"{}\":{}", name, count)),
+        );
+        let symmetries: Vec<String> = self
+            .get_symmetries()
+            .names()
+            .iter()
+            .map(|name| format!("\"{}\"", name))
+            .collect();
+        fields.push(format!("\"symmetries\":[{}]", symmetries.join(",")));
+        format!("{{{}}}", fields.join(","))
+    }
+
     /**
      * Print the sudoku puzzle.
      */
     pub fn print_puzzle(&self) {
-        self.print(self.puzzle);
+        self.print(&self.puzzle);
+    }
+
+    /**
+     * Print the solution as far as it has been worked out.
+     */
+    pub fn print_solution(&self) {
+        self.print(&self.solution);
     }
 
     /**
      * Given a vector of LogItems, determine how many log items in the vector
      * are of the specified type.
      */
-    fn get_log_count(v: &Vec<LogItem>, logtype: LogType) -> usize {
-        let mut count = 0;
-        for i in 0..v.len() {
-            if v[i].log_type == logtype {
-                count += 1;
-            }
-        }
-        return count;
+    fn get_log_count(v: &[LogItem], logtype: LogType) -> usize {
+        v.iter().filter(|item| item.log_type == logtype).count()
     }
 
     fn get_random_symmetry() -> Symmetry {
@@ -1803,68 +3363,16 @@ impl QQWing {
     }
 
     /**
-     * Given a value for a cell (0-8) and a cell number (0-80) calculate the
-     * offset into the possibility array (0-728).
-     */
-    pub(crate) fn get_possibility_index(value_index: usize, cell: usize) -> usize {
-        value_index + (ROW_COL_SEC_SIZE * cell)
-    }
-
-    /**
-     * Given the index of a cell (0-80) calculate the row (0-8) in which it
-     * resides.
-     */
-    pub(crate) fn cell_to_row(cell: usize) -> usize {
-        cell / ROW_COL_SEC_SIZE
-    }
-
-    /**
-     * Given the index of a cell (0-80) calculate the column (0-8) in which that
-     * cell resides.
-     */
-    pub(crate) fn cell_to_column(cell: usize) -> usize {
-        cell % ROW_COL_SEC_SIZE
-    }
-
-    /**
-     * Given the index of a cell (0-80) calculate the section (0-8) in which it
-     * resides.
-     */
-    pub(crate) fn cell_to_section(cell: usize) -> usize {
-        (cell / SEC_GROUP_SIZE * GRID_SIZE) + (QQWing::cell_to_column(cell) / GRID_SIZE)
-    }
-
-    /**
-     * Given the index of a cell (0-80) calculate the cell (0-80) that is the
-     * upper left start cell of that section.
-     */
-    pub(crate) fn cell_to_section_start_cell(cell: usize) -> usize {
-        (cell / SEC_GROUP_SIZE * SEC_GROUP_SIZE)
-            + (QQWing::cell_to_column(cell) / GRID_SIZE * GRID_SIZE)
-    }
-
-    /**
-     * Given a row (0-8) and a column (0-8) calculate the cell (0-80).
-     */
-    pub(crate) fn row_column_to_cell(row: usize, column: usize) -> usize {
-        row * ROW_COL_SEC_SIZE + column
-    }
-
-    /**
-     * Given a section (0-8) calculate the first cell (0-80) of that section.
-     */
-    pub(crate) fn section_to_first_cell(section: usize) -> usize {
-        (section % GRID_SIZE * GRID_SIZE) + (section / GRID_SIZE * SEC_GROUP_SIZE)
-    }
-
-    /**
-     * Given a section (0-8) and an offset into that section (0-8) calculate the
-     * cell (0-80)
+     * A log item for the current layout.
      */
-    pub(crate) fn section_to_cell(section: usize, offset: usize) -> usize {
-        QQWing::section_to_first_cell(section)
-            + ((offset / GRID_SIZE) * ROW_COL_SEC_SIZE)
-            + (offset % GRID_SIZE)
+    fn new_log_item(
+        &self,
+        round: u16,
+        log_type: LogType,
+        value: usize,
+        position: usize,
+    ) -> LogItem {
+        LogItem::with_size(self.layout.get_size(), round, log_type, value, position)
     }
 }
 
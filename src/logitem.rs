@@ -1,19 +1,18 @@
 use std::fmt::Display;
 
 use crate::logtype::LogType;
-use crate::QQWing;
 ///
 /// While solving the puzzle, log steps taken in a log item. This is useful for
 /// later printing out the solve history or gathering statistics about how hard
 /// the puzzle was to solve.
-/// 
+///
 #[derive(Debug, Clone)]
 pub struct LogItem {
     /**
      * The recursion level at which this item was gathered. Used for backing out
      * log items solve branches that don't lead to a solution.
      */
-    round: u8,
+    round: u16,
 
     /**
      * The type of log message that will determine the message printed.
@@ -29,6 +28,11 @@ pub struct LogItem {
      * position on the board at which the value (if any) was set.
      */
     position: usize,
+
+    /**
+     * The number of cells in each row of the board the item is from.
+     */
+    size: usize,
 }
 
 impl Display for LogItem {
@@ -50,20 +54,41 @@ impl Display for LogItem {
 }
 
 impl LogItem {
-    pub fn new(r: u8, t: LogType, v: usize, p: usize) -> Self {
+    pub fn new(r: u16, t: LogType, v: usize, p: usize) -> Self {
         LogItem::init(r, t, v, p)
     }
 
-    pub fn init(r: u8, t: LogType, v: usize, p: usize) -> Self {
+    pub fn init(r: u16, t: LogType, v: usize, p: usize) -> Self {
+        LogItem::with_size(9, r, t, v, p)
+    }
+
+    /**
+     * A log item for a board with the given number of cells in each row.
+     */
+    pub(crate) fn with_size(size: usize, r: u16, t: LogType, v: usize, p: usize) -> Self {
         Self {
             round: r,
             log_type: t,
             value: v,
             position: p,
+            size,
+        }
+    }
+
+    /**
+     * The same item moved to another cell with another value, as when the
+     * board is transformed.
+     */
+    pub(crate) fn moved(&self, log_type: LogType, value: usize, position: usize) -> Self {
+        Self {
+            log_type,
+            value,
+            position,
+            ..self.clone()
         }
     }
 
-    pub fn get_round(&self) -> u8 {
+    pub fn get_round(&self) -> u16 {
         self.round
     }
 
@@ -71,20 +96,27 @@ impl LogItem {
      * Get the row (1 indexed), or -1 if no row
      */
     pub fn get_row(&self) -> u8 {
-        if self.position == 255 {
+        if self.position >= self.size * self.size {
             return 255;
         }
-        QQWing::cell_to_row(self.position) as u8 + 1
+        (self.position / self.size) as u8 + 1
     }
 
     /**
      * Get the column (1 indexed), or -1 if no column
      */
     pub fn get_column(&self) -> u8 {
-        if self.position == 255 {
+        if self.position >= self.size * self.size {
             return 255;
         }
-        QQWing::cell_to_column(self.position) as u8 + 1
+        (self.position % self.size) as u8 + 1
+    }
+
+    /**
+     * Get the position (0 to one less than the number of cells) on the board
+     */
+    pub fn get_position(&self) -> usize {
+        self.position
     }
 
     /**
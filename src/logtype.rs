@@ -1,20 +1,124 @@
 #[derive(Debug, PartialEq, Clone)]
 pub enum LogType {
-    Given,                       //("Mark given"),
-    Single,                      //("Mark only possibility for cell"),
-    HiddenSingleRow,           //("Mark single possibility for value in row"),
-    HiddenSingleColumn,        //("Mark single possibility for value in column"),
-    HiddenSingleSection,       //("Mark single possibility for value in section"),
-    Guess,                       //("Mark guess , //(start round)"),
-    Rollback,                    //("Roll back round"),
-    NakedPairRow,              //("Remove possibilities for naked pair in row"),
-    NakedPairColumn,           //("Remove possibilities for naked pair in column"),
-    NakedPairSection,          //("Remove possibilities for naked pair in section"),
+    Given,                    //("Mark given"),
+    Entry,                    //("Mark player entry"),
+    Single,                   //("Mark only possibility for cell"),
+    HiddenSingleRow,          //("Mark single possibility for value in row"),
+    HiddenSingleColumn,       //("Mark single possibility for value in column"),
+    HiddenSingleSection,      //("Mark single possibility for value in section"),
+    HiddenSingleDiagonal,     //("Mark single possibility for value in diagonal"),
+    HiddenSingleExtraRegion,  //("Mark single possibility for value in extra region"),
+    Guess,                    //("Mark guess , //(start round)"),
+    Rollback,                 //("Roll back round"),
+    NakedPairRow,             //("Remove possibilities for naked pair in row"),
+    NakedPairColumn,          //("Remove possibilities for naked pair in column"),
+    NakedPairSection,         //("Remove possibilities for naked pair in section"),
+    NakedPairDiagonal,        //("Remove possibilities for naked pair in diagonal"),
+    NakedPairExtraRegion,     //("Remove possibilities for naked pair in extra region"),
     PointingPairTripleRow, //("Remove possibilities for row because all values are in one section"),
     PointingPairTripleColumn, //("Remove possibilities for column because all values are in one section"),
-    RowBox,         //("Remove possibilities for section because all values are in one row"),
-    ColumnBox,      //("Remove possibilities for section because all values are in one column"),
-    HiddenPairRow, //("Remove possibilities from hidden pair in row"),
+    RowBox,           //("Remove possibilities for section because all values are in one row"),
+    ColumnBox,        //("Remove possibilities for section because all values are in one column"),
+    HiddenPairRow,    //("Remove possibilities from hidden pair in row"),
     HiddenPairColumn, //("Remove possibilities from hidden pair in column"),
-    HiddenPairSection, //("Remove possibilities from hidden pair in section");
+    HiddenPairSection, //("Remove possibilities from hidden pair in section"),
+    HiddenPairDiagonal, //("Remove possibilities from hidden pair in diagonal"),
+    HiddenPairExtraRegion, //("Remove possibilities from hidden pair in extra region"),
+    CageCombination,  //("Remove possibilities that fit no way of making the cage sum"),
+    CageRuleOf45,     //("Remove possibilities for cells whose sum the cages in a unit fix"),
+    CagePointing, //("Remove possibilities for cells that see every place for a value the cage needs"),
+    NonConsecutive, //("Remove possibilities consecutive to the only values left next to them"),
+    KropkiDot,    //("Remove possibilities that fit no value of the cell across a Kropki dot"),
+    XvMarker,     //("Remove possibilities that can't make the sum of an X or V marker"),
+    GreaterThan,  //("Remove possibilities that can't keep to a greater-than sign"),
+    Thermometer,  //("Remove possibilities that leave no increasing values along a thermometer"),
+    Arrow,        //("Remove possibilities that can't make the sum in the circle of an arrow"),
+    Sandwich, //("Remove possibilities that can't make the sum between the smallest and largest values");
+}
+
+impl LogType {
+    /**
+     * A human readable description of the step.
+     */
+    pub fn description(&self) -> &'static str {
+        match self {
+            LogType::Given => "Mark given",
+            LogType::Entry => "Mark player entry",
+            LogType::Single => "Mark only possibility for cell",
+            LogType::HiddenSingleRow => "Mark single possibility for value in row",
+            LogType::HiddenSingleColumn => "Mark single possibility for value in column",
+            LogType::HiddenSingleSection => "Mark single possibility for value in section",
+            LogType::HiddenSingleDiagonal => "Mark single possibility for value in diagonal",
+            LogType::HiddenSingleExtraRegion => "Mark single possibility for value in extra region",
+            LogType::Guess => "Mark guess (start round)",
+            LogType::Rollback => "Roll back round",
+            LogType::NakedPairRow => "Remove possibilities for naked pair in row",
+            LogType::NakedPairColumn => "Remove possibilities for naked pair in column",
+            LogType::NakedPairSection => "Remove possibilities for naked pair in section",
+            LogType::NakedPairDiagonal => "Remove possibilities for naked pair in diagonal",
+            LogType::NakedPairExtraRegion => "Remove possibilities for naked pair in extra region",
+            LogType::PointingPairTripleRow => {
+                "Remove possibilities for row because all values are in one section"
+            }
+            LogType::PointingPairTripleColumn => {
+                "Remove possibilities for column because all values are in one section"
+            }
+            LogType::RowBox => "Remove possibilities for section because all values are in one row",
+            LogType::ColumnBox => {
+                "Remove possibilities for section because all values are in one column"
+            }
+            LogType::HiddenPairRow => "Remove possibilities from hidden pair in row",
+            LogType::HiddenPairColumn => "Remove possibilities from hidden pair in column",
+            LogType::HiddenPairSection => "Remove possibilities from hidden pair in section",
+            LogType::HiddenPairDiagonal => "Remove possibilities from hidden pair in diagonal",
+            LogType::HiddenPairExtraRegion => {
+                "Remove possibilities from hidden pair in extra region"
+            }
+            LogType::CageCombination => {
+                "Remove possibilities that fit no way of making the cage sum"
+            }
+            LogType::CageRuleOf45 => {
+                "Remove possibilities for cells whose sum the cages in a unit fix"
+            }
+            LogType::CagePointing => {
+                "Remove possibilities for cells that see every place for a value the cage needs"
+            }
+            LogType::NonConsecutive => {
+                "Remove possibilities consecutive to the only values left next to them"
+            }
+            LogType::KropkiDot => {
+                "Remove possibilities that fit no value of the cell across a Kropki dot"
+            }
+            LogType::XvMarker => "Remove possibilities that can't make the sum of an X or V marker",
+            LogType::GreaterThan => "Remove possibilities that can't keep to a greater-than sign",
+            LogType::Thermometer => {
+                "Remove possibilities that leave no increasing values along a thermometer"
+            }
+            LogType::Arrow => {
+                "Remove possibilities that can't make the sum in the circle of an arrow"
+            }
+            LogType::Sandwich => {
+                "Remove possibilities that can't make the sum between the smallest and largest values"
+            }
+        }
+    }
+
+    /**
+     * The same step on the board with its rows and columns swapped.
+     */
+    pub(crate) fn transposed(&self) -> Self {
+        match self {
+            LogType::HiddenSingleRow => LogType::HiddenSingleColumn,
+            LogType::HiddenSingleColumn => LogType::HiddenSingleRow,
+            LogType::NakedPairRow => LogType::NakedPairColumn,
+            LogType::NakedPairColumn => LogType::NakedPairRow,
+            LogType::PointingPairTripleRow => LogType::PointingPairTripleColumn,
+            LogType::PointingPairTripleColumn => LogType::PointingPairTripleRow,
+            LogType::RowBox => LogType::ColumnBox,
+            LogType::ColumnBox => LogType::RowBox,
+            LogType::HiddenPairRow => LogType::HiddenPairColumn,
+            LogType::HiddenPairColumn => LogType::HiddenPairRow,
+            other => other.clone(),
+        }
+    }
 }
@@ -1,9 +1,22 @@
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
+use std::time::Duration;
 use tracing::{info, Level};
 use tracing_subscriber::FmtSubscriber;
 
-use qqwing::{difficulty::Difficulty, PrintStyle, QQWing};
+use qqwing::{
+    canon, clues,
+    composite::{Composite, CompositeKind, CompositeLayout},
+    difficulty::Difficulty,
+    killer, kropki,
+    layout::Layout,
+    markers,
+    options::GenerateOptions,
+    parity,
+    player::PlayerState,
+    transform::Transformation,
+    PrintStyle, QQWing, QQWingError,
+};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -25,6 +38,66 @@ struct Cli {
     )]
     ps: Option<PrintStyle>,
 
+    /// Number of values on the board, such as 4, 6, 9, 12, 16 or 25
+    #[arg(short, long, default_value = "9")]
+    size: usize,
+
+    /// Play jigsaw sudoku with the given region map, one character per cell where cells with the same character share a region, or "random" for new random regions
+    #[arg(short, long, value_name = "MAP|random")]
+    regions: Option<String>,
+
+    /// Play Sudoku-X, where both main diagonals must also hold every value
+    #[arg(short = 'x', long)]
+    diagonals: bool,
+
+    /// Play Windoku, where four windows offset one cell from the edges must also hold every value
+    #[arg(short, long)]
+    windows: bool,
+
+    /// Add extra regions that must also hold every value, one character per cell where cells with the same character share a region and '.' marks a cell in none
+    #[arg(short, long, value_name = "MAP")]
+    extra_regions: Option<String>,
+
+    /// No value may be a chess knight's move away from the same value
+    #[arg(long)]
+    anti_knight: bool,
+
+    /// No value may be a chess king's move away from the same value
+    #[arg(long)]
+    anti_king: bool,
+
+    /// Play killer sudoku with the given cages, one per line or separated by ';', each a sum and its cells such as "10: r1c1, r1c2"
+    #[arg(short, long)]
+    cages: Option<String>,
+
+    /// Cells next to each other in a row or column may not hold consecutive values
+    #[arg(long)]
+    non_consecutive: bool,
+
+    /// Play Kropki sudoku with the given dots, one per line or separated by ';', each w (consecutive) or b (double) and its two cells such as "w: r1c1, r1c2"
+    #[arg(long)]
+    dots: Option<String>,
+
+    /// Cells next to each other without a dot are neither consecutive nor one double the other
+    #[arg(long)]
+    negative_dots: bool,
+
+    /// Play XV or greater-than sudoku with the given markers, one per line or separated by ';', each x (sum 10), v (sum 5), > or < and its two cells such as "x: r1c1, r1c2"
+    #[arg(long)]
+    markers: Option<String>,
+
+    /// Play even-odd sudoku with the given shading, one character per cell where 'e' is shaded even, 'o' shaded odd and anything else not shaded
+    #[arg(long, value_name = "MAP")]
+    parity: Option<String>,
+
+    /// Play a puzzle of several 9x9 grids sharing boxes, generating and solving all of them at once
+    #[arg(long, value_name = "SAMURAI,TWOGRID,BUTTERFLY")]
+    composite: Option<CompositeKind>,
+
+    /// Add thermometers, arrows and sandwich sums, one per line or separated by ';', such as "thermo: r1c1, r1c2" (bulb first), "arrow: r1c1, r1c2, r1c3" (circle first) or "sandwich: r1=15"
+    #[arg(long)]
+    clues: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -45,16 +118,96 @@ enum Commands {
             default_value = "UNKNOWN"
         )]
         difficulty: Difficulty,
+
+        /// Pattern of givens, one character per cell where '.' or '0' is empty and anything else is a given
+        #[arg(short, long)]
+        mask: Option<String>,
+
+        /// Maximum number of attempts per puzzle [default: 100 with a mask, unlimited otherwise]
+        #[arg(short, long)]
+        attempts: Option<u32>,
+
+        /// Maximum number of seconds to spend on each puzzle
+        #[arg(short, long, value_name = "SECONDS")]
+        timeout: Option<u64>,
+
+        /// Generate killer puzzles, printing their cages after the grid
+        #[arg(short, long)]
+        killer: bool,
+
+        /// Generate Kropki puzzles with every dot given, printing the dots after the grid
+        #[arg(long)]
+        kropki: bool,
+
+        /// Generate XV puzzles, printing the markers they need after the grid
+        #[arg(long)]
+        xv: bool,
+
+        /// Generate greater-than puzzles, printing the signs they need after the grid
+        #[arg(long)]
+        greater_than: bool,
+
+        /// Generate even-odd puzzles, printing the shading they need after the grid
+        #[arg(long)]
+        even_odd: bool,
     },
     /// Solve a puzzle
     Solve {
         /// Print the puzzle stats
         #[arg(short, long)]
         stats: bool,
+        /// Add the counts for cages, pair rules and clues and the symmetries to CSV stats
+        #[arg(long)]
+        extra_stats: bool,
+        /// Print the puzzle stats as JSON, with every count and the symmetries
+        #[arg(long)]
+        json: bool,
         /// Print the puzzle
         #[arg(short, long)]
         puzzle: String,
     },
+    /// Show the next logical step for a puzzle
+    Hint {
+        /// The puzzle to take a step in
+        #[arg(short, long)]
+        puzzle: String,
+        /// Values the player has entered so far, in the same format as the puzzle
+        #[arg(short, long)]
+        entries: Option<String>,
+        /// Make the step and print the resulting grid
+        #[arg(short, long)]
+        apply: bool,
+    },
+    /// Check a player's grid against the rules and the solution
+    Check {
+        /// The puzzle the player is solving
+        #[arg(short, long)]
+        puzzle: String,
+        /// Values the player has entered so far, in the same format as the puzzle
+        #[arg(short, long)]
+        entries: Option<String>,
+    },
+    /// Print the canonical form of a puzzle and the transformation that gives it
+    Canon {
+        /// The puzzle to put in canonical form
+        #[arg(short, long)]
+        puzzle: String,
+        /// Another puzzle to compare with, printing whether the two are the same puzzle in disguise
+        #[arg(short, long)]
+        compare: Option<String>,
+    },
+    /// Print the same puzzle in disguise, with the transformation that gives it
+    Shuffle {
+        /// number of variants to print
+        #[arg(short, long, default_value = "1")]
+        nums: u32,
+        /// The puzzle to transform
+        #[arg(short, long)]
+        puzzle: String,
+        /// The transformation to apply, such as "t r213456789 c123456789 d987654321" [default: random]
+        #[arg(short, long)]
+        transformation: Option<String>,
+    },
 }
 
 fn main() {
@@ -76,74 +229,465 @@ fn main() {
         // completes the builder.
         .finish();
     tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
-    let mut ss = QQWing::new();
-
-    ss.set_print_style(cli.ps.unwrap());
 
     if let Some(file_path) = cli.file.as_deref() {
         println!("Value for file: {}", file_path.display());
     }
 
+    if let Some(kind) = &cli.composite {
+        run_composite(kind, &cli);
+        return;
+    }
+
+    let random_regions = cli.regions.as_deref() == Some("random");
+    let layout = or_exit(match cli.regions.as_deref() {
+        None | Some("random") => Layout::for_size(cli.size),
+        Some(map) => Layout::parse_regions(map),
+    });
+    let layout = match cli.diagonals {
+        true => layout.with_diagonals(),
+        false => layout,
+    };
+    let layout = match cli.windows {
+        true => layout.with_windows(),
+        false => layout,
+    };
+    let layout = match cli.extra_regions.as_deref() {
+        Some(map) => or_exit(layout.with_extra_region_map(map)),
+        None => layout,
+    };
+    let layout = match cli.anti_knight {
+        true => layout.with_anti_knight(),
+        false => layout,
+    };
+    let layout = match cli.anti_king {
+        true => layout.with_anti_king(),
+        false => layout,
+    };
+    let layout = match cli.non_consecutive {
+        true => layout.with_non_consecutive(),
+        false => layout,
+    };
+    let mut ss = QQWing::with_layout(match random_regions {
+        true => or_exit(layout.with_random_regions()),
+        false => layout.clone(),
+    });
+    if let Some(cages) = cli.cages.as_deref() {
+        or_exit(ss.set_cages(or_exit(killer::parse_cages(ss.get_layout(), cages))));
+    }
+    if cli.dots.is_some() || cli.negative_dots {
+        let dots = or_exit(kropki::parse_dots(
+            ss.get_layout(),
+            cli.dots.as_deref().unwrap_or(""),
+        ));
+        or_exit(ss.set_dots(dots, cli.negative_dots));
+    }
+    if let Some(markers) = cli.markers.as_deref() {
+        or_exit(ss.set_markers(or_exit(markers::parse_markers(ss.get_layout(), markers))));
+    }
+    if let Some(clues) = cli.clues.as_deref() {
+        or_exit(ss.set_clues(or_exit(clues::parse_clues(ss.get_layout(), clues))));
+    }
+    if let Some(map) = cli.parity.as_deref() {
+        or_exit(ss.set_parity(or_exit(parity::parse_parity(ss.get_layout(), map))));
+    }
+
+    let print_style = cli.ps.unwrap();
+    ss.set_print_style(print_style.clone());
+
     ss.set_log_history(true);
     ss.set_record_history(true);
 
     // You can check for the existence of subcommands, and if found use their
     // matches just as you would the top level cmd
     match &cli.command {
-        Commands::Generate { nums, difficulty } => {
+        Commands::Generate {
+            nums,
+            difficulty,
+            mask,
+            attempts,
+            timeout,
+            killer,
+            kropki,
+            xv,
+            greater_than,
+            even_odd,
+        } => {
             info!("Set puzzle difficulty level {:?} to generate", difficulty);
-            let num = *nums;
+            let mask = mask
+                .as_deref()
+                .map(|mask| or_exit(read_mask(ss.get_layout(), mask)));
+            let options = GenerateOptions {
+                difficulty: difficulty.clone(),
+                max_attempts: *attempts,
+                max_duration: timeout.map(Duration::from_secs),
+                killer: *killer,
+                kropki: *kropki,
+                xv: *xv,
+                greater_than: *greater_than,
+                parity: *even_odd,
+                ..Default::default()
+            };
+            // Masks that can't be filled would otherwise be tried forever.
+            let mask_options = GenerateOptions {
+                max_attempts: attempts.or(Some(100)),
+                ..options.clone()
+            };
             info!("Start generate puzzle");
-            let mut n = 0;
-            while n < num {
-                ss.generate_puzzle();
-                ss.set_record_history(true);
-                ss.solve();
-                if *difficulty == Difficulty::UNKNOWN || ss.get_difficulty() == *difficulty {
-                    info!(
-                        "get a puzzle with difficulty {:?}, print it:",
-                        ss.get_difficulty()
-                    );
-                    ss.print_puzzle();
-                    n += 1;
-                } else {
-                    info!(
-                        "get a puzzle with difficulty {:?} != {:?}, continue generate...",
-                        ss.get_difficulty(),
-                        *difficulty
-                    );
+            for n in 0..*nums {
+                if random_regions && n > 0 {
+                    ss.set_layout(or_exit(layout.with_random_regions()));
                 }
+                or_exit(match &mask {
+                    Some(mask) => ss.generate_puzzle_from_mask(mask, &mask_options),
+                    None => ss.generate_puzzle_with_options(&options),
+                });
+                info!(
+                    "get a puzzle with difficulty {:?}, print it:",
+                    ss.get_difficulty()
+                );
+                print_regions(&ss, &print_style);
+                ss.print_puzzle();
+                print_cages(&ss, &print_style);
+                print_dots(&ss, &print_style);
+                print_markers(&ss, &print_style);
+                print_clues(&ss, &print_style);
+                print_parity(&ss, &print_style);
             }
         }
-        Commands::Solve { stats, puzzle } => {
-            if puzzle.len() == qqwing::BOARD_SIZE {
-                info!("Set the puzzle");
-                let init_puzzle = read_puzzle(puzzle);
-                ss.set_puzzle(init_puzzle);
-            }
+        Commands::Solve {
+            stats,
+            extra_stats,
+            json,
+            puzzle,
+        } => {
+            ss.set_extra_stats(*extra_stats);
+            info!("Set the puzzle");
+            let init_puzzle = or_exit(ss.get_layout().parse(puzzle));
+            or_exit(ss.set_puzzle(init_puzzle));
             info!("Start solve puzzle");
-            if ss.solve() {
+            if or_exit(ss.solve()) {
                 ss.print_solve_instructions();
             }
-            if *stats {
+            if *json {
+                println!("{}", ss.get_stats_json());
+            } else if *stats {
                 println!("{}", ss.get_stats());
             }
         }
+        Commands::Hint {
+            puzzle,
+            entries,
+            apply,
+        } => {
+            let state = read_player_state(ss.get_layout(), puzzle, entries.as_deref());
+            or_exit(ss.set_player_state(&state));
+            match or_exit(ss.next_step(*apply)) {
+                Some(step) => {
+                    println!("{}", step);
+                    if *apply {
+                        ss.print_solution();
+                    }
+                }
+                None => println!("No logical step available."),
+            }
+        }
+        Commands::Check { puzzle, entries } => {
+            let state = read_player_state(ss.get_layout(), puzzle, entries.as_deref());
+            let size = ss.get_layout().get_size();
+            let validation = ss.validate(&state);
+            for conflict in &validation.conflicts {
+                let cells: Vec<String> = conflict
+                    .cells
+                    .iter()
+                    .map(|&p| format!("r{}c{}", p / size + 1, p % size + 1))
+                    .collect();
+                println!(
+                    "Conflict: {} repeated in {} at {}",
+                    conflict.value,
+                    conflict.unit,
+                    cells.join(", ")
+                );
+            }
+            for broken in &validation.broken {
+                let cells: Vec<String> = broken
+                    .cells
+                    .iter()
+                    .map(|&p| format!("r{}c{}", p / size + 1, p % size + 1))
+                    .collect();
+                println!("Broken: {} at {}", broken.rule, cells.join(", "));
+            }
+            if !validation.unique_solution {
+                println!("The puzzle does not have a unique solution.");
+            }
+            for &p in &validation.mismatches {
+                println!("Wrong: r{}c{}", p / size + 1, p % size + 1);
+            }
+            println!("Solved: {}", validation.solved);
+        }
+        Commands::Canon { puzzle, compare } => {
+            let init_puzzle = or_exit(ss.get_layout().parse(puzzle));
+            let (canonical, transformation) =
+                or_exit(canon::canonical_form(ss.get_layout(), &init_puzzle));
+            or_exit(ss.set_puzzle(canonical));
+            ss.print_puzzle();
+            println!("Transformation: {}", transformation);
+            if let Some(other) = compare {
+                let other = or_exit(ss.get_layout().parse(other));
+                let same = or_exit(canon::equivalent(ss.get_layout(), &init_puzzle, &other));
+                println!("Equivalent: {}", same);
+            }
+        }
+        Commands::Shuffle {
+            nums,
+            puzzle,
+            transformation,
+        } => {
+            let init_puzzle = or_exit(ss.get_layout().parse(puzzle));
+            let transformation = transformation
+                .as_deref()
+                .map(|text| or_exit(text.parse::<Transformation>()));
+            for _ in 0..*nums {
+                or_exit(ss.set_puzzle(init_puzzle.clone()));
+                let used = match &transformation {
+                    Some(transformation) => {
+                        or_exit(ss.transform(transformation));
+                        transformation.clone()
+                    }
+                    None => or_exit(ss.shuffle()),
+                };
+                ss.print_puzzle();
+                println!("Transformation: {}", used);
+            }
+        }
     }
 }
 
 /**
- * Read a sudoku puzzle from a String input. Any digit is
- * used to fill the sudoku, any other character is ignored.
+ * Generate or solve composite puzzles, which are searched as a whole rather
+ * than solved step by step, so they have no hints or difficulty. Options
+ * that don't apply to them are refused rather than ignored.
  */
-fn read_puzzle(puzzle_str: &str) -> Vec<u8> {
-    let mut puzzle = Vec::new();
-    for c in puzzle_str.chars() {
-        let n = c.to_digit(10);
-        match n {
-            Some(n) => puzzle.push(n as u8),
-            None => puzzle.push(0),
+fn run_composite(kind: &CompositeKind, cli: &Cli) {
+    if let Some(option) = composite_conflict(cli) {
+        eprintln!("{} is not available for composite puzzles.", option);
+        std::process::exit(1);
+    }
+    let mut composite = Composite::new(CompositeLayout::new(kind.clone()));
+    composite.set_print_style(cli.ps.clone().unwrap());
+    match &cli.command {
+        Commands::Generate { nums, timeout, .. } => {
+            let options = GenerateOptions {
+                max_duration: timeout.map(Duration::from_secs),
+                ..Default::default()
+            };
+            for _ in 0..*nums {
+                or_exit(composite.generate_puzzle_with_options(&options));
+                composite.print_puzzle();
+            }
         }
+        Commands::Solve { stats, puzzle, .. } => {
+            let init_puzzle = or_exit(composite.get_layout().parse(puzzle));
+            or_exit(composite.set_puzzle(init_puzzle));
+            if composite.solve() {
+                composite.print_solution();
+            } else {
+                println!("The puzzle has no solution.");
+            }
+            if *stats {
+                let givens = composite.get_puzzle().iter().filter(|&&v| v != 0).count();
+                println!("Number of Givens: {}", givens);
+                println!("Unique: {}", composite.count_solutions_limited() == 1);
+            }
+        }
+        Commands::Hint { .. }
+        | Commands::Check { .. }
+        | Commands::Canon { .. }
+        | Commands::Shuffle { .. } => {
+            eprintln!("Hints, checking, canonical forms and transformations are not available for composite puzzles.");
+            std::process::exit(1);
+        }
+    }
+}
+
+/**
+ * The first option given that composite puzzles don't support, which are
+ * those for other boards and rules, and those for generating by difficulty,
+ * mask or number of attempts, and extra CSV or JSON stats.
+ */
+fn composite_conflict(cli: &Cli) -> Option<&'static str> {
+    let mut used = vec![
+        (cli.size != 9, "--size"),
+        (cli.regions.is_some(), "--regions"),
+        (cli.diagonals, "--diagonals"),
+        (cli.windows, "--windows"),
+        (cli.extra_regions.is_some(), "--extra-regions"),
+        (cli.anti_knight, "--anti-knight"),
+        (cli.anti_king, "--anti-king"),
+        (cli.cages.is_some(), "--cages"),
+        (cli.non_consecutive, "--non-consecutive"),
+        (cli.dots.is_some(), "--dots"),
+        (cli.negative_dots, "--negative-dots"),
+        (cli.markers.is_some(), "--markers"),
+        (cli.parity.is_some(), "--parity"),
+        (cli.clues.is_some(), "--clues"),
+    ];
+    if let Commands::Generate {
+        difficulty,
+        mask,
+        attempts,
+        killer,
+        kropki,
+        xv,
+        greater_than,
+        even_odd,
+        ..
+    } = &cli.command
+    {
+        used.extend([
+            (*difficulty != Difficulty::UNKNOWN, "--difficulty"),
+            (mask.is_some(), "--mask"),
+            (attempts.is_some(), "--attempts"),
+            (*killer, "--killer"),
+            (*kropki, "--kropki"),
+            (*xv, "--xv"),
+            (*greater_than, "--greater-than"),
+            (*even_odd, "--even-odd"),
+        ]);
+    }
+    if let Commands::Solve {
+        extra_stats, json, ..
+    } = &cli.command
+    {
+        used.push((*extra_stats, "--extra-stats"));
+        used.push((*json, "--json"));
+    }
+    used.into_iter()
+        .find(|&(given, _)| given)
+        .map(|(_, option)| option)
+}
+
+/**
+ * Print the region map of a jigsaw board on its own line, unless the print
+ * style already draws the regions.
+ */
+fn print_regions(ss: &QQWing, print_style: &PrintStyle) {
+    if ss.get_layout().is_jigsaw() && *print_style != PrintStyle::READABLE {
+        println!("{}", ss.get_layout().regions_to_string());
+    }
+}
+
+/**
+ * Print the cages of a killer puzzle, one per line, or all on one line for
+ * the one line print styles.
+ */
+fn print_cages(ss: &QQWing, print_style: &PrintStyle) {
+    if ss.get_cages().is_empty() {
+        return;
+    }
+    let cages = killer::cages_to_string(ss.get_layout(), ss.get_cages());
+    match print_style {
+        PrintStyle::ONELINE | PrintStyle::CSV => println!("{}", cages.replace('\n', "; ")),
+        _ => println!("{}\n", cages),
+    }
+}
+
+/**
+ * Print the dots of a Kropki puzzle, one per line, or all on one line for
+ * the one line styles. Nothing is printed for other puzzles.
+ */
+fn print_dots(ss: &QQWing, print_style: &PrintStyle) {
+    if ss.get_dots().is_empty() {
+        return;
+    }
+    let dots = kropki::dots_to_string(ss.get_layout(), ss.get_dots());
+    match print_style {
+        PrintStyle::ONELINE | PrintStyle::CSV => println!("{}", dots.replace('\n', "; ")),
+        _ => println!("{}\n", dots),
+    }
+}
+
+/**
+ * Print the markers of an XV or greater-than puzzle, one per line, or all on
+ * one line for the one line styles. Nothing is printed for other puzzles.
+ */
+fn print_markers(ss: &QQWing, print_style: &PrintStyle) {
+    if ss.get_markers().is_empty() {
+        return;
+    }
+    let markers = markers::markers_to_string(ss.get_layout(), ss.get_markers());
+    match print_style {
+        PrintStyle::ONELINE | PrintStyle::CSV => println!("{}", markers.replace('\n', "; ")),
+        _ => println!("{}\n", markers),
+    }
+}
+
+/**
+ * Print the thermometers, arrows and sandwich sums of a puzzle, one per
+ * line, or all on one line for the one line styles. Nothing is printed for
+ * other puzzles.
+ */
+fn print_clues(ss: &QQWing, print_style: &PrintStyle) {
+    if ss.get_clues().is_empty() {
+        return;
+    }
+    let clues = clues::clues_to_string(ss.get_layout(), ss.get_clues());
+    match print_style {
+        PrintStyle::ONELINE | PrintStyle::CSV => println!("{}", clues.replace('\n', "; ")),
+        _ => println!("{}\n", clues),
+    }
+}
+
+/**
+ * Print the shading of an even-odd puzzle as a parity map on its own line,
+ * unless the print style already draws the shading.
+ */
+fn print_parity(ss: &QQWing, print_style: &PrintStyle) {
+    let drawn = *print_style == PrintStyle::READABLE && !ss.get_layout().is_jigsaw();
+    if ss.has_parity() && !drawn {
+        println!("{}", parity::parity_to_string(ss.get_parity()));
+    }
+}
+
+/**
+ * Unwrap a result, printing the error and exiting if there is one.
+ */
+fn or_exit<T>(result: Result<T, QQWingError>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+/**
+ * Read a game in progress from the puzzle and, optionally, the values the
+ * player has entered, exiting if either can't be read for the layout.
+ */
+fn read_player_state(layout: &Layout, puzzle_str: &str, entries_str: Option<&str>) -> PlayerState {
+    let mut state = PlayerState::new(&or_exit(layout.parse(puzzle_str)));
+    if let Some(entries_str) = entries_str {
+        state.set_entries(&or_exit(layout.parse(entries_str)));
+    }
+    state
+}
+
+/**
+ * Read a pattern of givens from a String input. A '.' or '0' marks an
+ * empty cell, any other character marks a given, and whitespace is skipped
+ * so the mask can be wrapped. Returns an error if the number of cells is
+ * not the board size.
+ */
+fn read_mask(layout: &Layout, mask_str: &str) -> Result<Vec<bool>, QQWingError> {
+    let mask: Vec<bool> = mask_str
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c != '.' && c != '0')
+        .collect();
+    if mask.len() != layout.get_board_size() {
+        return Err(QQWingError::InvalidMask {
+            expected: layout.get_board_size(),
+            found: mask.len(),
+        });
     }
-    puzzle
+    Ok(mask)
 }
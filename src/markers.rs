@@ -0,0 +1,186 @@
+use crate::killer::parse_cell;
+use crate::layout::Layout;
+use crate::logtype::LogType;
+use crate::pairs::{adjacent_pairs, PairRule};
+use crate::QQWingError;
+
+/// The kind of a marker between two cells.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MarkerKind {
+    /// The two values add up to 10.
+    X,
+    /// The two values add up to 5.
+    V,
+    /// The first value is greater than the second.
+    Greater,
+}
+
+///
+/// An XV or greater-than marker on the edge between two cells next to each
+/// other.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marker {
+    /**
+     * The two cells. X and V markers keep them in reading order, a
+     * greater-than marker puts the greater cell first.
+     */
+    cells: [usize; 2],
+
+    kind: MarkerKind,
+}
+
+impl Marker {
+    pub fn new(first: usize, second: usize, kind: MarkerKind) -> Self {
+        let cells = match kind {
+            MarkerKind::Greater => [first, second],
+            _ => [first.min(second), first.max(second)],
+        };
+        Self { cells, kind }
+    }
+
+    pub fn get_cells(&self) -> [usize; 2] {
+        self.cells
+    }
+
+    pub fn get_kind(&self) -> MarkerKind {
+        self.kind
+    }
+
+    /**
+     * Whether two values, the first in the first cell, fit the marker.
+     */
+    fn fits(&self, a: u32, b: u32) -> bool {
+        match self.kind {
+            MarkerKind::X => a + b == 10,
+            MarkerKind::V => a + b == 5,
+            MarkerKind::Greater => a > b,
+        }
+    }
+}
+
+/**
+ * Read markers from text, one marker per line or separated by ';'. Each
+ * marker is x, v, > or <, a ':' and its two cells written as r1c1 and
+ * separated by spaces or commas, such as "x: r1c1, r1c2" or "<: r1c1, r1c2"
+ * for a first cell less than the second. Blank lines are skipped. Returns
+ * an error if a marker can't be read or doesn't fit the board, see
+ * check_markers().
+ */
+pub fn parse_markers(layout: &Layout, text: &str) -> Result<Vec<Marker>, QQWingError> {
+    let mut markers = Vec::new();
+    for definition in text.split(['\n', ';']).map(str::trim) {
+        if definition.is_empty() {
+            continue;
+        }
+        let syntax = || QQWingError::MarkerSyntax(definition.to_string());
+        let (kind, cells) = definition.split_once(':').ok_or_else(syntax)?;
+        let cells = cells
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|word| !word.is_empty())
+            .map(|word| parse_cell(layout, word).ok_or_else(syntax))
+            .collect::<Result<Vec<usize>, QQWingError>>()?;
+        let [first, second] = cells[..] else {
+            return Err(syntax());
+        };
+        markers.push(match kind.trim().to_ascii_lowercase().as_str() {
+            "x" => Marker::new(first, second, MarkerKind::X),
+            "v" => Marker::new(first, second, MarkerKind::V),
+            ">" => Marker::new(first, second, MarkerKind::Greater),
+            "<" => Marker::new(second, first, MarkerKind::Greater),
+            _ => return Err(syntax()),
+        });
+    }
+    check_markers(layout, &markers)?;
+    Ok(markers)
+}
+
+/**
+ * Write markers one per line in the form read by parse_markers().
+ */
+pub fn markers_to_string(layout: &Layout, markers: &[Marker]) -> String {
+    let lines: Vec<String> = markers
+        .iter()
+        .map(|marker| {
+            let [first, second] = marker.cells;
+            format!(
+                "{}: r{}c{},r{}c{}",
+                match marker.kind {
+                    MarkerKind::X => "x",
+                    MarkerKind::V => "v",
+                    MarkerKind::Greater => ">",
+                },
+                layout.cell_to_row(first) + 1,
+                layout.cell_to_column(first) + 1,
+                layout.cell_to_row(second) + 1,
+                layout.cell_to_column(second) + 1
+            )
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/**
+ * Check that every marker is between two cells next to each other, and
+ * that no two markers are between the same cells.
+ */
+pub(crate) fn check_markers(layout: &Layout, markers: &[Marker]) -> Result<(), QQWingError> {
+    let sorted = |marker: &Marker| {
+        let [first, second] = marker.cells;
+        [first.min(second), first.max(second)]
+    };
+    for (index, marker) in markers.iter().enumerate() {
+        let [first, second] = marker.cells;
+        let fits = first.max(second) < layout.get_board_size()
+            && layout.neighbors(first).contains(&second)
+            && !markers[..index]
+                .iter()
+                .any(|other| sorted(other) == sorted(marker));
+        if !fits {
+            return Err(QQWingError::InvalidMarker(index + 1));
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Every marker a filled grid could have: an X or V between every two cells
+ * next to each other that add up to 10 or 5 and, with comparisons set, a
+ * greater-than sign between every other two.
+ */
+pub(crate) fn place_markers(
+    layout: &Layout,
+    grid: &[u8],
+    xv: bool,
+    comparisons: bool,
+) -> Vec<Marker> {
+    adjacent_pairs(layout)
+        .filter_map(|(first, second)| {
+            let (a, b) = (grid[first], grid[second]);
+            match a + b {
+                10 if xv => Some(Marker::new(first, second, MarkerKind::X)),
+                5 if xv => Some(Marker::new(first, second, MarkerKind::V)),
+                _ if comparisons && a > b => Some(Marker::new(first, second, MarkerKind::Greater)),
+                _ if comparisons => Some(Marker::new(second, first, MarkerKind::Greater)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/**
+ * The rules the markers set.
+ */
+pub(crate) fn marker_rules(layout: &Layout, markers: &[Marker]) -> Vec<PairRule> {
+    markers
+        .iter()
+        .map(|marker| {
+            let [first, second] = marker.cells;
+            let log_type = match marker.kind {
+                MarkerKind::X | MarkerKind::V => LogType::XvMarker,
+                MarkerKind::Greater => LogType::GreaterThan,
+            };
+            PairRule::new(layout, first, second, log_type, |a, b| marker.fits(a, b))
+        })
+        .collect()
+}
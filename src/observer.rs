@@ -0,0 +1,50 @@
+use std::fmt;
+
+use crate::logitem::LogItem;
+
+///
+/// Receives events from the solver and the generator as they happen. Every
+/// method has an empty default so an observer only needs to implement the
+/// events it cares about. Observers are called whether or not history is
+/// being recorded, but not for the internal solving done while counting
+/// solutions or generating puzzles.
+///
+pub trait SolveObserver: Send {
+    /**
+     * A value was placed by logic, or as a given. The log item holds the
+     * technique used, the position, the value and the round.
+     */
+    fn on_place(&mut self, _item: &LogItem) {}
+
+    /**
+     * A value was removed from the possibilities of a position.
+     */
+    fn on_eliminate(&mut self, _position: usize, _value: usize, _round: u16) {}
+
+    /**
+     * A value was guessed for a position, starting a new round.
+     */
+    fn on_guess(&mut self, _position: usize, _value: usize, _round: u16) {}
+
+    /**
+     * Everything done in the round was undone.
+     */
+    fn on_rollback(&mut self, _round: u16) {}
+
+    /**
+     * A candidate puzzle was accepted by the generator on the given attempt.
+     */
+    fn on_candidate_accepted(&mut self, _puzzle: &[u8], _attempt: u32) {}
+
+    /**
+     * A candidate puzzle was rejected by the generator on the given attempt,
+     * because it did not have the wanted difficulty or was not unique.
+     */
+    fn on_candidate_rejected(&mut self, _puzzle: &[u8], _attempt: u32) {}
+}
+
+impl fmt::Debug for dyn SolveObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SolveObserver")
+    }
+}
@@ -0,0 +1,164 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::difficulty::Difficulty;
+use crate::symmetry::Symmetry;
+use crate::QQWingError;
+
+/// A cooperative cancellation flag. Clones share the same flag, so a caller
+/// can keep one clone and hand the other to a running generator.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every holder of this token to stop as soon as possible.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Options controlling puzzle generation and the limits put on it.
+#[derive(Debug, Clone)]
+pub struct GenerateOptions {
+    /**
+     * Difficulty the generated puzzle must have, Difficulty::UNKNOWN accepts
+     * any puzzle.
+     */
+    pub difficulty: Difficulty,
+
+    /**
+     * Symmetry of the clue pattern.
+     */
+    pub symmetry: Symmetry,
+
+    /**
+     * Carve the filled grid into killer cages and take out as many givens
+     * as the cages allow, often all of them.
+     */
+    pub killer: bool,
+
+    /**
+     * Put every Kropki dot the filled grid has on the board, with no dot
+     * meaning neither consecutive nor double, and take out as many givens as
+     * the dots allow.
+     */
+    pub kropki: bool,
+
+    /**
+     * Put X and V markers between cells that add up to 10 and 5, and
+     * greater-than signs between cells, then take out as many givens and
+     * markers as the puzzle allows, keeping only the markers it needs.
+     */
+    pub xv: bool,
+    pub greater_than: bool,
+
+    /**
+     * Shade every cell even or odd by its value, then take out as many
+     * givens and shaded cells as the puzzle allows, keeping only the shading
+     * it needs.
+     */
+    pub parity: bool,
+
+    /**
+     * Maximum wall-clock time to spend generating, or None for no limit.
+     */
+    pub max_duration: Option<Duration>,
+
+    /**
+     * Maximum number of candidate puzzles to generate, or None for no limit.
+     */
+    pub max_attempts: Option<u32>,
+
+    /**
+     * Token checked between steps of generation so a caller can stop it.
+     */
+    pub cancel: Option<CancellationToken>,
+}
+
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        Self {
+            difficulty: Difficulty::UNKNOWN,
+            symmetry: Symmetry::NONE,
+            killer: false,
+            kropki: false,
+            xv: false,
+            greater_than: false,
+            parity: false,
+            max_duration: None,
+            max_attempts: None,
+            cancel: None,
+        }
+    }
+}
+
+/// Tracks how much of the budget in some GenerateOptions has been used.
+pub(crate) struct Budget<'a> {
+    options: &'a GenerateOptions,
+    start: Instant,
+    attempts: u32,
+}
+
+impl<'a> Budget<'a> {
+    pub(crate) fn new(options: &'a GenerateOptions) -> Self {
+        Self {
+            options,
+            start: Instant::now(),
+            attempts: 0,
+        }
+    }
+
+    pub(crate) fn get_attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /**
+     * Count the start of a new attempt, failing if the attempt limit has been
+     * reached or the budget is otherwise used up.
+     */
+    pub(crate) fn start_attempt(&mut self) -> Result<(), QQWingError> {
+        self.check()?;
+        if let Some(max_attempts) = self.options.max_attempts {
+            if self.attempts >= max_attempts {
+                return Err(self.exhausted());
+            }
+        }
+        self.attempts += 1;
+        Ok(())
+    }
+
+    /**
+     * Fail if generation has been cancelled or has run out of time.
+     */
+    pub(crate) fn check(&self) -> Result<(), QQWingError> {
+        if let Some(cancel) = &self.options.cancel {
+            if cancel.is_cancelled() {
+                return Err(QQWingError::Cancelled);
+            }
+        }
+        if let Some(max_duration) = self.options.max_duration {
+            if self.start.elapsed() >= max_duration {
+                return Err(self.exhausted());
+            }
+        }
+        Ok(())
+    }
+
+    fn exhausted(&self) -> QQWingError {
+        QQWingError::BudgetExhausted {
+            attempts: self.attempts,
+            elapsed: self.start.elapsed(),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::layout::Layout;
+use crate::logtype::LogType;
+
+///
+/// A rule between the values of two cells, such as a Kropki dot or the
+/// non-consecutive rule between cells next to each other. The values that
+/// fit together are worked out once, so that the values one cell leaves for
+/// the other are found with a table lookup.
+///
+#[derive(Debug, Clone)]
+pub(crate) struct PairRule {
+    /**
+     * The two cells the rule is between.
+     */
+    cells: [usize; 2],
+
+    /**
+     * For each value of the first cell, the values the second cell may take
+     * with it, and the other way round, one bit per value.
+     */
+    forward: Vec<u32>,
+    backward: Vec<u32>,
+
+    /**
+     * The technique logged when the rule removes possibilities.
+     */
+    log_type: LogType,
+}
+
+impl PairRule {
+    /**
+     * A rule between two cells that lets the first hold a and the second
+     * hold b when fits(a, b) is true.
+     */
+    pub(crate) fn new(
+        layout: &Layout,
+        first: usize,
+        second: usize,
+        log_type: LogType,
+        fits: impl Fn(u32, u32) -> bool,
+    ) -> Self {
+        let values = 1..=layout.get_size() as u32;
+        let table = |swap: bool| -> Vec<u32> {
+            values
+                .clone()
+                .map(|a| {
+                    values
+                        .clone()
+                        .filter(|&b| if swap { fits(b, a) } else { fits(a, b) })
+                        .fold(0, |mask, b| mask | 1 << (b - 1))
+                })
+                .collect()
+        };
+        Self {
+            cells: [first, second],
+            forward: table(false),
+            backward: table(true),
+            log_type,
+        }
+    }
+
+    pub(crate) fn get_cells(&self) -> [usize; 2] {
+        self.cells
+    }
+
+    pub(crate) fn get_log_type(&self) -> &LogType {
+        &self.log_type
+    }
+
+    /**
+     * The other cell of the rule.
+     */
+    pub(crate) fn partner(&self, position: usize) -> usize {
+        match self.cells[0] == position {
+            true => self.cells[1],
+            false => self.cells[0],
+        }
+    }
+
+    /**
+     * The values the partner of a cell may take when the cell holds any of
+     * the given values, one bit per value.
+     */
+    pub(crate) fn support(&self, position: usize, values: u32) -> u32 {
+        let table = match self.cells[0] == position {
+            true => &self.forward,
+            false => &self.backward,
+        };
+        let mut support = 0;
+        let mut bits = values;
+        while bits != 0 {
+            support |= table[bits.trailing_zeros() as usize];
+            bits &= bits - 1;
+        }
+        support
+    }
+}
+
+/**
+ * Every pair of cells next to each other in a row or column, the cell above
+ * or to the left first.
+ */
+pub(crate) fn adjacent_pairs(layout: &Layout) -> impl Iterator<Item = (usize, usize)> + '_ {
+    (0..layout.get_board_size()).flat_map(move |cell| {
+        layout
+            .neighbors(cell)
+            .into_iter()
+            .filter(move |&other| other > cell)
+            .map(move |other| (cell, other))
+    })
+}
@@ -0,0 +1,75 @@
+use crate::layout::Layout;
+use crate::QQWingError;
+
+/// The shading of a cell in an even-odd puzzle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Parity {
+    /// The cell holds an even value.
+    Even,
+    /// The cell holds an odd value.
+    Odd,
+}
+
+impl Parity {
+    /**
+     * The parity of a value.
+     */
+    pub fn of(value: u8) -> Self {
+        match value % 2 {
+            0 => Parity::Even,
+            _ => Parity::Odd,
+        }
+    }
+
+    /**
+     * The values of this parity on a board, one bit per value.
+     */
+    pub(crate) fn values(self, layout: &Layout) -> u32 {
+        // Bit 0 is the value 1, so odd values are the even bits.
+        let odd = 0x5555_5555 & layout.all_values();
+        match self {
+            Parity::Even => layout.all_values() & !odd,
+            Parity::Odd => odd,
+        }
+    }
+}
+
+/**
+ * Read a parity map, one character per cell: 'e' for a cell shaded even,
+ * 'o' for one shaded odd, either case, and anything else for a cell that
+ * isn't shaded. Whitespace is skipped. Returns an error if the number of
+ * cells is not the board size.
+ */
+pub fn parse_parity(layout: &Layout, text: &str) -> Result<Vec<Option<Parity>>, QQWingError> {
+    let shading: Vec<Option<Parity>> = text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| match c.to_ascii_lowercase() {
+            'e' => Some(Parity::Even),
+            'o' => Some(Parity::Odd),
+            _ => None,
+        })
+        .collect();
+    if shading.len() != layout.get_board_size() {
+        return Err(QQWingError::InvalidParityMap {
+            expected: layout.get_board_size(),
+            found: shading.len(),
+        });
+    }
+    Ok(shading)
+}
+
+/**
+ * Write a parity map in the form read by parse_parity(), with '.' for the
+ * cells that aren't shaded.
+ */
+pub fn parity_to_string(shading: &[Option<Parity>]) -> String {
+    shading
+        .iter()
+        .map(|parity| match parity {
+            Some(Parity::Even) => 'e',
+            Some(Parity::Odd) => 'o',
+            None => '.',
+        })
+        .collect()
+}
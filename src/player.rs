@@ -0,0 +1,125 @@
+use crate::QQWingError;
+
+///
+/// A game in progress: the givens of the puzzle, the values the player has
+/// entered and the candidates the player has pencilled in. Entries are kept
+/// apart from the givens so that a wrong entry can be told from a given.
+///
+#[derive(Debug, Clone)]
+pub struct PlayerState {
+    /**
+     * The number of values, and of cells in each row, column and section.
+     */
+    size: usize,
+
+    /**
+     * The givens of the puzzle, 1 to the size or 0 for unknown.
+     */
+    givens: Vec<u8>,
+
+    /**
+     * The values entered by the player, 0 where nothing is entered.
+     */
+    entries: Vec<u8>,
+
+    /**
+     * Pencil marks for each cell, bit 0 for 1, bit 1 for 2 and so on, or
+     * None where the cell has no pencil marks.
+     */
+    candidate_marks: Vec<Option<u32>>,
+}
+
+impl PlayerState {
+    /**
+     * Start a game from the givens of a puzzle, one integer per cell. The
+     * size of the board is taken from the number of givens, 81 for a 9x9
+     * board.
+     */
+    pub fn new(givens: &[u8]) -> Self {
+        let size = (1..).find(|size| size * size >= givens.len()).unwrap_or(1);
+        Self {
+            size,
+            givens: givens.to_vec(),
+            entries: vec![0; givens.len()],
+            candidate_marks: vec![None; givens.len()],
+        }
+    }
+
+    /**
+     * Enter a value at a position, or clear the entry with 0.
+     * Entries on givens are ignored.
+     */
+    pub fn set_entry(&mut self, position: usize, value: u8) {
+        if self.givens[position] == 0 {
+            self.entries[position] = value;
+        }
+    }
+
+    /**
+     * Set every entry at once from one integer per cell, 0 where the player
+     * has not entered anything.
+     */
+    pub fn set_entries(&mut self, entries: &[u8]) {
+        for (position, &value) in entries.iter().enumerate().take(self.givens.len()) {
+            self.set_entry(position, value);
+        }
+    }
+
+    /**
+     * Replace the pencil marks for a position with the given values.
+     */
+    pub fn set_candidates(&mut self, position: usize, values: &[u8]) {
+        self.candidate_marks[position] = Some(
+            values
+                .iter()
+                .filter(|&&v| v >= 1 && v as usize <= self.size)
+                .fold(0, |marks, &v| marks | (1 << (v - 1))),
+        );
+    }
+
+    /**
+     * Cross a value out of the pencil marks of a position. A cell
+     * without pencil marks starts out with every value marked. Returns an
+     * error if the value is not 1 to the size of the board.
+     */
+    pub fn remove_candidate(&mut self, position: usize, value: u8) -> Result<(), QQWingError> {
+        if value == 0 || value as usize > self.size {
+            return Err(QQWingError::InvalidValue { position, value });
+        }
+        let marks = self.candidate_marks[position].unwrap_or((1 << self.size) - 1);
+        self.candidate_marks[position] = Some(marks & !(1 << (value - 1)));
+        Ok(())
+    }
+
+    /**
+     * Remove all pencil marks from a position.
+     */
+    pub fn clear_candidates(&mut self, position: usize) {
+        self.candidate_marks[position] = None;
+    }
+
+    /**
+     * Get the pencilled in values for a position, or None if the
+     * cell has no pencil marks.
+     */
+    pub fn get_candidates(&self, position: usize) -> Option<Vec<u8>> {
+        self.candidate_marks[position].map(|marks| {
+            (0..self.size as u8)
+                .filter(|v| marks & (1 << v) != 0)
+                .map(|v| v + 1)
+                .collect()
+        })
+    }
+
+    pub fn get_givens(&self) -> &[u8] {
+        &self.givens
+    }
+
+    pub fn get_entries(&self) -> &[u8] {
+        &self.entries
+    }
+
+    pub fn get_candidate_marks(&self) -> &[Option<u32>] {
+        &self.candidate_marks
+    }
+}
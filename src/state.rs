@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use crate::layout::Layout;
+use crate::{QQWing, QQWingError};
+
+///
+/// A snapshot of everything on the board: the puzzle, the player's entries
+/// and pencil marks, the values placed so far and the possibilities left.
+/// Games can keep a list of these for undo and redo, taking one with
+/// save_state() before each move and going back with restore_state().
+/// The layout and rules are not copied into the snapshot, which can only
+/// be restored on the board it was taken of while that board keeps them.
+/// The solve history and instructions are not part of the snapshot either.
+///
+#[derive(Debug, Clone)]
+pub struct BoardState {
+    layout: Arc<Layout>,
+    puzzle: Vec<u8>,
+    entries: Vec<u8>,
+    candidate_marks: Vec<Option<u32>>,
+    solution: Vec<u8>,
+    solution_round: Vec<u16>,
+    candidates: Vec<u32>,
+    row_values: Vec<u32>,
+    column_values: Vec<u32>,
+    section_values: Vec<u32>,
+    extra_values: Vec<u32>,
+    last_solve_round: u16,
+    rules_version: u64,
+}
+
+impl BoardState {
+    /**
+     * The givens of the puzzle, 0 for unknown.
+     */
+    pub fn get_puzzle(&self) -> &[u8] {
+        &self.puzzle
+    }
+
+    /**
+     * The values entered by the player, 0 where nothing is entered.
+     */
+    pub fn get_entries(&self) -> &[u8] {
+        &self.entries
+    }
+
+    /**
+     * The values on the board, givens and entries as well as anything the
+     * solver has placed, 0 for unknown.
+     */
+    pub fn get_values(&self) -> &[u8] {
+        &self.solution
+    }
+}
+
+impl QQWing {
+    /**
+     * Take a snapshot of the board that restore_state() can go back to.
+     */
+    pub fn save_state(&self) -> BoardState {
+        BoardState {
+            layout: Arc::clone(&self.layout),
+            puzzle: self.puzzle.clone(),
+            entries: self.entries.clone(),
+            candidate_marks: self.candidate_marks.clone(),
+            solution: self.solution.clone(),
+            solution_round: self.solution_round.clone(),
+            candidates: self.candidates.clone(),
+            row_values: self.row_values.clone(),
+            column_values: self.column_values.clone(),
+            section_values: self.section_values.clone(),
+            extra_values: self.extra_values.clone(),
+            last_solve_round: self.last_solve_round,
+            rules_version: self.rules_version,
+        }
+    }
+
+    /**
+     * Put the board back the way it was when the snapshot was taken. The
+     * changes made since can no longer be rolled back round by round, which
+     * only a solve in progress needs. Returns an error if the snapshot was
+     * taken of another board, or before the layout or rules were changed.
+     */
+    pub fn restore_state(&mut self, state: &BoardState) -> Result<(), QQWingError> {
+        if !Arc::ptr_eq(&self.layout, &state.layout) || self.rules_version != state.rules_version {
+            return Err(QQWingError::StateMismatch);
+        }
+        self.puzzle.clone_from(&state.puzzle);
+        self.entries.clone_from(&state.entries);
+        self.candidate_marks.clone_from(&state.candidate_marks);
+        self.solution.clone_from(&state.solution);
+        self.solution_round.clone_from(&state.solution_round);
+        self.candidates.clone_from(&state.candidates);
+        self.row_values.clone_from(&state.row_values);
+        self.column_values.clone_from(&state.column_values);
+        self.section_values.clone_from(&state.section_values);
+        self.extra_values.clone_from(&state.extra_values);
+        self.last_solve_round = state.last_solve_round;
+        self.trail.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,511 @@
+use std::fmt::{self, Display};
+
+use crate::clues::{Clue, Line};
+use crate::kropki::DotColor;
+use crate::layout::Layout;
+use crate::logitem::LogItem;
+use crate::logtype::LogType;
+use crate::markers::MarkerKind;
+use crate::{Change, QQWing, QQWingError};
+
+/// Round at which steps taken one at a time are marked.
+const STEP_ROUND: u16 = 2;
+
+///
+/// A single logical step, as single_solve_move() would take it from the
+/// current state of the board.
+///
+#[derive(Debug, Clone)]
+pub struct SolveStep {
+    /**
+     * The technique used for the step.
+     */
+    pub technique: LogType,
+
+    /**
+     * The cells that make up the pattern the technique found.
+     */
+    pub cells: Vec<usize>,
+
+    /**
+     * The position and value placed, for steps that place a value.
+     */
+    pub placement: Option<(usize, usize)>,
+
+    /**
+     * The positions and values removed from the possibilities,
+     * for steps that only remove possibilities.
+     */
+    pub eliminations: Vec<(usize, usize)>,
+
+    /**
+     * A human readable explanation of the step.
+     */
+    pub explanation: String,
+}
+
+impl Display for SolveStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.technique, self.explanation)
+    }
+}
+
+impl QQWing {
+    /**
+     * Find the next logical step from the current state of the board. When
+     * apply is true the step is made, so that calling this again gives the
+     * step after it, otherwise the board is left untouched. Returns None if
+     * the puzzle is solved, impossible, or can't be advanced without
+     * guessing.
+     */
+    pub fn next_step(&mut self, apply: bool) -> Result<Option<SolveStep>, QQWingError> {
+        if self.is_solved() || self.is_impossible() {
+            return Ok(None);
+        }
+        let trail_len = self.trail.len();
+        let history_len = self.solve_history.len();
+        let instructions_len = self.solve_instructions.len();
+
+        // Record the step so that its technique can be read back, but don't
+        // let anybody else see it unless it is being applied.
+        let rec_history = self.record_history;
+        let l_history = self.log_history;
+        self.set_record_history(true);
+        let observer = if apply {
+            None
+        } else {
+            self.set_log_history(false);
+            self.observer.take()
+        };
+
+        let moved = self.single_solve_move(STEP_ROUND);
+        let step = if let Ok(true) = moved {
+            self.solve_instructions
+                .last()
+                .cloned()
+                .map(|item| self.describe_step(&item, &self.trail[trail_len..]))
+        } else {
+            None
+        };
+
+        self.set_record_history(rec_history);
+        self.set_log_history(l_history);
+        if observer.is_some() {
+            self.observer = observer;
+        }
+        if !apply || !rec_history || moved.is_err() {
+            self.solve_history.truncate(history_len);
+            self.solve_instructions.truncate(instructions_len);
+        }
+        if !apply || moved.is_err() {
+            self.undo_to(trail_len);
+        }
+        moved?;
+        Ok(step)
+    }
+
+    fn describe_step(&self, item: &LogItem, changes: &[Change]) -> SolveStep {
+        let technique = item.log_type.clone();
+        let position = item.get_position();
+        let value = item.get_value();
+        let layout = &*self.layout;
+        let size = layout.get_size();
+
+        let placement = changes.iter().find_map(|change| match *change {
+            Change::Place { position, .. } => Some((position, self.solution[position] as usize)),
+            _ => None,
+        });
+        let mut eliminations = Vec::new();
+        if placement.is_none() {
+            for change in changes {
+                if let Change::Eliminate {
+                    position, values, ..
+                } = *change
+                {
+                    for val_index in 0..size {
+                        if values & (1 << val_index) != 0 {
+                            eliminations.push((position, val_index + 1));
+                        }
+                    }
+                }
+            }
+            eliminations.sort_unstable();
+        }
+
+        let unit = match technique {
+            LogType::HiddenSingleRow
+            | LogType::NakedPairRow
+            | LogType::HiddenPairRow
+            | LogType::PointingPairTripleRow
+            | LogType::RowBox => layout.row_cells(layout.cell_to_row(position)),
+            LogType::HiddenSingleColumn
+            | LogType::NakedPairColumn
+            | LogType::HiddenPairColumn
+            | LogType::PointingPairTripleColumn
+            | LogType::ColumnBox => layout.column_cells(layout.cell_to_column(position)),
+            LogType::HiddenSingleSection
+            | LogType::NakedPairSection
+            | LogType::HiddenPairSection => layout.section_cells(layout.cell_to_section(position)),
+            LogType::HiddenSingleDiagonal
+            | LogType::NakedPairDiagonal
+            | LogType::HiddenPairDiagonal
+            | LogType::HiddenSingleExtraRegion
+            | LogType::NakedPairExtraRegion
+            | LogType::HiddenPairExtraRegion => {
+                layout.extra_unit_cells(self.step_extra_unit(&technique, position, value))
+            }
+            LogType::CageCombination | LogType::CagePointing => self.cell_cages[position]
+                .map_or(std::slice::from_ref(&position), |cage| {
+                    self.cages[cage].get_cells()
+                }),
+            _ => std::slice::from_ref(&position),
+        };
+        let cells: Vec<usize> = match technique {
+            LogType::NakedPairRow
+            | LogType::NakedPairColumn
+            | LogType::NakedPairSection
+            | LogType::NakedPairDiagonal
+            | LogType::NakedPairExtraRegion => unit
+                .iter()
+                .copied()
+                .filter(|&p| self.is_naked_pair_with(position, p))
+                .collect(),
+            LogType::HiddenPairRow
+            | LogType::HiddenPairColumn
+            | LogType::HiddenPairSection
+            | LogType::HiddenPairDiagonal
+            | LogType::HiddenPairExtraRegion
+            | LogType::PointingPairTripleRow
+            | LogType::PointingPairTripleColumn
+            | LogType::RowBox
+            | LogType::ColumnBox
+            | LogType::CagePointing => unit
+                .iter()
+                .copied()
+                .filter(|&p| self.is_possible(value - 1, p))
+                .collect(),
+            LogType::CageCombination => unit.to_vec(),
+            LogType::NonConsecutive
+            | LogType::KropkiDot
+            | LogType::XvMarker
+            | LogType::GreaterThan => self
+                .step_pair_rule(&technique, position, &eliminations)
+                .to_vec(),
+            LogType::Thermometer | LogType::Arrow | LogType::Sandwich => self
+                .step_clue(&technique, position, &eliminations)
+                .map_or(vec![position], |clue| self.clue_cells[clue].clone()),
+            _ => vec![placement.map_or(position, |(p, _)| p)],
+        };
+
+        let explanation = match technique {
+            LogType::Single => {
+                let (p, v) = placement.unwrap_or((position, value));
+                format!("{} can only be {}.", cell_name(layout, p), v)
+            }
+            LogType::HiddenSingleRow
+            | LogType::HiddenSingleColumn
+            | LogType::HiddenSingleSection
+            | LogType::HiddenSingleDiagonal
+            | LogType::HiddenSingleExtraRegion => format!(
+                "{} can only go in one place in {}: {}.",
+                value,
+                unit_name(layout, &technique, unit),
+                cell_name(layout, position)
+            ),
+            LogType::NakedPairRow
+            | LogType::NakedPairColumn
+            | LogType::NakedPairSection
+            | LogType::NakedPairDiagonal
+            | LogType::NakedPairExtraRegion => {
+                let values: Vec<String> = (0..size)
+                    .filter(|&v| self.is_possible(v, position))
+                    .map(|v| (v + 1).to_string())
+                    .collect();
+                format!(
+                    "{} can only be {}, so those values are removed from the rest of {}.",
+                    cells_name(layout, &cells),
+                    values.join(" or "),
+                    unit_name(layout, &technique, unit)
+                )
+            }
+            LogType::HiddenPairRow
+            | LogType::HiddenPairColumn
+            | LogType::HiddenPairSection
+            | LogType::HiddenPairDiagonal
+            | LogType::HiddenPairExtraRegion => {
+                let values: Vec<String> = (0..size)
+                    .filter(|&v| {
+                        unit.iter()
+                            .filter(|&&p| self.is_possible(v, p))
+                            .eq(cells.iter())
+                    })
+                    .map(|v| (v + 1).to_string())
+                    .collect();
+                format!(
+                    "In {}, {} can only go in {}, so other values are removed from those cells.",
+                    unit_name(layout, &technique, unit),
+                    values.join(" and "),
+                    cells_name(layout, &cells)
+                )
+            }
+            LogType::PointingPairTripleRow | LogType::PointingPairTripleColumn => {
+                let section = layout.cell_to_section(cells.first().copied().unwrap_or(position));
+                format!(
+                    "In section {}, {} can only go in {}, so it is removed from the rest of {}.",
+                    section + 1,
+                    value,
+                    cells_name(layout, &cells),
+                    unit_name(layout, &technique, unit)
+                )
+            }
+            LogType::RowBox | LogType::ColumnBox => {
+                let section = layout.cell_to_section(cells.first().copied().unwrap_or(position));
+                format!(
+                    "In {}, {} can only go in {}, so it is removed from the rest of section {}.",
+                    unit_name(layout, &technique, unit),
+                    value,
+                    cells_name(layout, &cells),
+                    section + 1
+                )
+            }
+            LogType::CageCombination => format!(
+                "The values of {} must add up to {}, so values that don't fit any way of making that sum are removed.",
+                cells_name(layout, &cells),
+                self.cell_cages[position].map_or(0, |cage| self.cages[cage].get_sum())
+            ),
+            LogType::CagePointing => format!(
+                "The cage of {} must hold {}, which can only go in {}, so it is removed from the cells that see all of them.",
+                cells_name(layout, unit),
+                value,
+                cells_name(layout, &cells)
+            ),
+            LogType::NonConsecutive => format!(
+                "{} are next to each other, so values with no value left in the other cell that isn't consecutive to them are removed.",
+                cells_name(layout, &cells)
+            ),
+            LogType::KropkiDot => {
+                let rule = match self.dots.iter().find(|dot| dot.get_cells()[..] == cells[..]) {
+                    Some(dot) if dot.get_color() == DotColor::White => {
+                        "have a white dot between them, so they are consecutive"
+                    }
+                    Some(_) => "have a black dot between them, so one is double the other",
+                    None => "have no dot between them, so they are neither consecutive nor one double the other",
+                };
+                format!(
+                    "{} {}, and values that fit no value left in the other cell are removed.",
+                    cells_name(layout, &cells),
+                    rule
+                )
+            }
+            LogType::XvMarker => {
+                let sum = match self.markers.iter().find(|m| m.get_cells()[..] == cells[..]) {
+                    Some(marker) if marker.get_kind() == MarkerKind::V => "a V between them, so they add up to 5",
+                    _ => "an X between them, so they add up to 10",
+                };
+                format!(
+                    "{} have {}, and values that fit no value left in the other cell are removed.",
+                    cells_name(layout, &cells),
+                    sum
+                )
+            }
+            LogType::GreaterThan => format!(
+                "{} is greater than {}, so values that leave no smaller or greater value in the other cell are removed.",
+                cell_name(layout, cells[0]),
+                cell_name(layout, cells[cells.len() - 1])
+            ),
+            LogType::Thermometer => format!(
+                "The values along the thermometer {} increase from the bulb, so values that leave no smaller value before them or larger value after them are removed.",
+                cells_name(layout, &cells)
+            ),
+            LogType::Arrow => format!(
+                "The values along the arrow from {} add up to the value in its circle, so values that can't make that sum are removed.",
+                cells_name(layout, &cells)
+            ),
+            LogType::Sandwich => {
+                let clue = self.step_clue(&technique, position, &eliminations);
+                let (line, sum) = match clue.map(|clue| &self.clues[clue]) {
+                    Some(Clue::Sandwich(sandwich)) => match sandwich.get_line() {
+                        Line::Row(row) => (format!("row {}", row + 1), sandwich.get_sum()),
+                        Line::Column(column) => {
+                            (format!("column {}", column + 1), sandwich.get_sum())
+                        }
+                    },
+                    _ => (cells_name(layout, &cells), 0),
+                };
+                format!(
+                    "The values between 1 and {} in {} add up to {}, so values that fit no place for 1 and {} making that sum are removed.",
+                    size, line, sum, size
+                )
+            }
+            _ => technique.description().to_string(),
+        };
+
+        SolveStep {
+            technique,
+            cells,
+            placement,
+            eliminations,
+            explanation,
+        }
+    }
+
+    /**
+     * Whether a cell holds the same two possibilities as the first cell of a
+     * naked pair, or is that cell.
+     */
+    fn is_naked_pair_with(&self, position: usize, other: usize) -> bool {
+        other == position
+            || (self.solution[other] == 0
+                && self.count_possibilities(other) == 2
+                && self.are_possibilities_same(position, other))
+    }
+
+    /**
+     * The cells of the rule between two cells a step was taken on: one that
+     * starts at the logged position and rules out every value the step
+     * removed, given the values its other cell had before the step.
+     */
+    fn step_pair_rule(
+        &self,
+        technique: &LogType,
+        position: usize,
+        eliminations: &[(usize, usize)],
+    ) -> [usize; 2] {
+        let mut rules = self.cell_pair_rules[position]
+            .iter()
+            .map(|&rule| &self.pair_rules[rule])
+            .filter(|rule| rule.get_cells()[0] == position && rule.get_log_type() == technique);
+        let first = rules.clone().next();
+        rules
+            .find(|rule| {
+                eliminations.iter().all(|&(p, v)| {
+                    if !rule.get_cells().contains(&p) {
+                        return false;
+                    }
+                    let partner = rule.partner(p);
+                    let mut left = match self.solution[partner] {
+                        0 => self.candidates[partner],
+                        value => 1 << (value - 1),
+                    };
+                    for &(q, w) in eliminations {
+                        if q == partner {
+                            left |= 1 << (w - 1);
+                        }
+                    }
+                    rule.support(partner, left) & (1 << (v - 1)) == 0
+                })
+            })
+            .or(first)
+            .map_or([position, position], |rule| rule.get_cells())
+    }
+
+    /**
+     * The clue a step was taken on: one of the technique's kind that starts
+     * at the logged position and has every cell the step removed values
+     * from.
+     */
+    fn step_clue(
+        &self,
+        technique: &LogType,
+        position: usize,
+        eliminations: &[(usize, usize)],
+    ) -> Option<usize> {
+        let mut clues = (0..self.clues.len()).filter(|&clue| {
+            self.clue_cells[clue][0] == position && self.clues[clue].rule().log_type() == *technique
+        });
+        let first = clues.clone().next();
+        clues
+            .find(|&clue| {
+                eliminations
+                    .iter()
+                    .all(|(p, _)| self.clue_cells[clue].contains(p))
+            })
+            .or(first)
+    }
+
+    /**
+     * The diagonal or extra region a step was taken in. A cell can be in
+     * more than one, such as the centre cell of an odd sized board which is
+     * on both diagonals, so pick the one the rest of the pattern is in.
+     */
+    fn step_extra_unit(&self, technique: &LogType, position: usize, value: usize) -> usize {
+        let layout = &*self.layout;
+        let diagonal = matches!(
+            technique,
+            LogType::HiddenSingleDiagonal
+                | LogType::NakedPairDiagonal
+                | LogType::HiddenPairDiagonal
+        );
+        let units: Vec<usize> = layout
+            .cell_to_extra_units(position)
+            .iter()
+            .copied()
+            .filter(|&unit| layout.is_diagonal(unit) == diagonal)
+            .collect();
+        units
+            .iter()
+            .copied()
+            .find(|&unit| {
+                let cells = layout.extra_unit_cells(unit);
+                match technique {
+                    LogType::NakedPairDiagonal | LogType::NakedPairExtraRegion => cells
+                        .iter()
+                        .any(|&p| p != position && self.is_naked_pair_with(position, p)),
+                    LogType::HiddenPairDiagonal | LogType::HiddenPairExtraRegion => cells
+                        .iter()
+                        .any(|&p| p != position && self.is_possible(value - 1, p)),
+                    _ => true,
+                }
+            })
+            .unwrap_or(units[0])
+    }
+}
+
+fn unit_name(layout: &Layout, technique: &LogType, unit: &[usize]) -> String {
+    let position = unit[0];
+    match technique {
+        LogType::HiddenSingleRow
+        | LogType::NakedPairRow
+        | LogType::HiddenPairRow
+        | LogType::PointingPairTripleRow
+        | LogType::RowBox => format!("row {}", layout.cell_to_row(position) + 1),
+        LogType::HiddenSingleColumn
+        | LogType::NakedPairColumn
+        | LogType::HiddenPairColumn
+        | LogType::PointingPairTripleColumn
+        | LogType::ColumnBox => format!("column {}", layout.cell_to_column(position) + 1),
+        LogType::HiddenSingleDiagonal
+        | LogType::NakedPairDiagonal
+        | LogType::HiddenPairDiagonal => {
+            // Diagonals start in the top left or the top right corner.
+            match position {
+                0 => "diagonal 1".to_string(),
+                _ => "diagonal 2".to_string(),
+            }
+        }
+        LogType::HiddenSingleExtraRegion
+        | LogType::NakedPairExtraRegion
+        | LogType::HiddenPairExtraRegion => {
+            let region = layout
+                .get_extra_regions()
+                .iter()
+                .position(|cells| cells.as_slice() == unit)
+                .unwrap_or(0);
+            format!("extra region {}", region + 1)
+        }
+        _ => format!("section {}", layout.cell_to_section(position) + 1),
+    }
+}
+
+fn cell_name(layout: &Layout, position: usize) -> String {
+    format!(
+        "r{}c{}",
+        layout.cell_to_row(position) + 1,
+        layout.cell_to_column(position) + 1
+    )
+}
+
+fn cells_name(layout: &Layout, cells: &[usize]) -> String {
+    let names: Vec<String> = cells.iter().map(|&p| cell_name(layout, p)).collect();
+    names.join(", ")
+}
@@ -1,3 +1,7 @@
+use std::fmt;
+
+use crate::layout::Layout;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Symmetry {
     NONE,
@@ -6,4 +10,113 @@ pub enum Symmetry {
     MIRROR,
     FLIP,
     RANDOM,
-}
\ No newline at end of file
+}
+
+///
+/// The symmetries the pattern of givens of a puzzle has, as found by
+/// find_symmetries(). Only where the givens are counts, not their values.
+/// A pattern with every symmetry has the full symmetry of the square,
+/// written as DIHEDRAL.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Symmetries {
+    /**
+     * The pattern is the same turned a quarter or a half turn.
+     */
+    pub rotate90: bool,
+    pub rotate180: bool,
+
+    /**
+     * The pattern is the same reflected left to right, or top to bottom.
+     */
+    pub mirror: bool,
+    pub flip: bool,
+
+    /**
+     * The pattern is the same reflected in the main diagonal, top left to
+     * bottom right, or in the anti-diagonal, top right to bottom left.
+     */
+    pub diagonal: bool,
+    pub anti_diagonal: bool,
+}
+
+impl Symmetries {
+    /**
+     * Whether the pattern has every symmetry of the square.
+     */
+    pub fn is_dihedral(&self) -> bool {
+        self.rotate90 && self.mirror
+    }
+
+    /**
+     * Whether the pattern has the symmetry puzzles are generated with.
+     * Every pattern has NONE, and RANDOM is whether it has any other.
+     */
+    pub fn has(&self, symmetry: &Symmetry) -> bool {
+        match symmetry {
+            Symmetry::NONE => true,
+            Symmetry::ROTATE90 => self.rotate90,
+            Symmetry::ROTATE180 => self.rotate180,
+            Symmetry::MIRROR => self.mirror,
+            Symmetry::FLIP => self.flip,
+            Symmetry::RANDOM => *self != Symmetries::default(),
+        }
+    }
+
+    /**
+     * The names of the symmetries, only DIHEDRAL for all of them, or none.
+     */
+    pub fn names(&self) -> Vec<&'static str> {
+        if self.is_dihedral() {
+            return vec!["DIHEDRAL"];
+        }
+        [
+            (self.rotate90, "ROTATE90"),
+            (self.rotate180, "ROTATE180"),
+            (self.mirror, "MIRROR"),
+            (self.flip, "FLIP"),
+            (self.diagonal, "DIAGONAL"),
+            (self.anti_diagonal, "ANTIDIAGONAL"),
+        ]
+        .into_iter()
+        .filter_map(|(found, name)| found.then_some(name))
+        .collect()
+    }
+}
+
+impl fmt::Display for Symmetries {
+    /**
+     * The names of the symmetries separated by spaces, DIHEDRAL for all of
+     * them, or NONE.
+     */
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = self.names();
+        match names.is_empty() {
+            true => write!(f, "NONE"),
+            false => write!(f, "{}", names.join(" ")),
+        }
+    }
+}
+
+/**
+ * Find the symmetries of the pattern of givens of a puzzle, with 0 for empty
+ * cells.
+ */
+pub fn find_symmetries(layout: &Layout, puzzle: &[u8]) -> Symmetries {
+    let size = layout.get_size();
+    let last = size - 1;
+    let symmetric = |image: &dyn Fn(usize, usize) -> (usize, usize)| {
+        (0..puzzle.len()).all(|cell| {
+            let (row, column) = image(cell / size, cell % size);
+            (puzzle[cell] == 0) == (puzzle[row * size + column] == 0)
+        })
+    };
+    Symmetries {
+        rotate90: symmetric(&|row, column| (column, last - row)),
+        rotate180: symmetric(&|row, column| (last - row, last - column)),
+        mirror: symmetric(&|row, column| (row, last - column)),
+        flip: symmetric(&|row, column| (last - row, column)),
+        diagonal: symmetric(&|row, column| (column, row)),
+        anti_diagonal: symmetric(&|row, column| (last - column, last - row)),
+    }
+}
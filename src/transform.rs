@@ -0,0 +1,424 @@
+use rand::{random, seq::SliceRandom, thread_rng};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::layout::Layout;
+use crate::QQWingError;
+
+///
+/// A relabelling of a board that keeps every row, column and box a unit:
+/// the board may be transposed, then its rows and columns put in a new
+/// order and its values renamed. Row (column) r of the result is row
+/// (column) rows\[r\] (columns\[r\]) of the board, counting from 0, and the
+/// value v becomes digits\[v - 1\]. Written as text, a transformation is the
+/// three orders counting from 1, with values above 9 as letters like the
+/// cells of a puzzle, after a 't' when the board is transposed, such as
+/// "t r213456789 c123456789 d987654321". Any transformation that fits the
+/// board turns a puzzle into the same puzzle in disguise, with the same
+/// solution transformed alike and the same difficulty.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transformation {
+    /**
+     * Whether rows and columns are swapped before anything else.
+     */
+    pub transpose: bool,
+
+    /**
+     * The row and column of the board each row and column comes from.
+     */
+    pub rows: Vec<usize>,
+    pub columns: Vec<usize>,
+
+    /**
+     * The value each value becomes, the new value for 1 first.
+     */
+    pub digits: Vec<u8>,
+}
+
+impl Transformation {
+    /**
+     * The transformation that leaves a board of the given size as it is.
+     */
+    pub fn identity(size: usize) -> Self {
+        Transformation {
+            transpose: false,
+            rows: (0..size).collect(),
+            columns: (0..size).collect(),
+            digits: (1..=size as u8).collect(),
+        }
+    }
+
+    /**
+     * Rename the values, the value v becoming digits\[v - 1\].
+     */
+    pub fn relabel(digits: Vec<u8>) -> Self {
+        Transformation {
+            digits: digits.clone(),
+            ..Self::identity(digits.len())
+        }
+    }
+
+    /**
+     * Swap two rows, which fits the board when they are in the same band.
+     */
+    pub fn swap_rows(size: usize, first: usize, second: usize) -> Self {
+        let mut transformation = Self::identity(size);
+        transformation.rows.swap(first, second);
+        transformation
+    }
+
+    /**
+     * Swap two columns, which fits the board when they are in the same stack.
+     */
+    pub fn swap_columns(size: usize, first: usize, second: usize) -> Self {
+        let mut transformation = Self::identity(size);
+        transformation.columns.swap(first, second);
+        transformation
+    }
+
+    /**
+     * Swap two bands, the rows of a row of boxes, counting from 0.
+     */
+    pub fn swap_bands(layout: &Layout, first: usize, second: usize) -> Self {
+        let mut transformation = Self::identity(layout.get_size());
+        swap_groups(
+            &mut transformation.rows,
+            layout.get_box_rows(),
+            first,
+            second,
+        );
+        transformation
+    }
+
+    /**
+     * Swap two stacks, the columns of a column of boxes, counting from 0.
+     */
+    pub fn swap_stacks(layout: &Layout, first: usize, second: usize) -> Self {
+        let mut transformation = Self::identity(layout.get_size());
+        swap_groups(
+            &mut transformation.columns,
+            layout.get_box_columns(),
+            first,
+            second,
+        );
+        transformation
+    }
+
+    /**
+     * Swap rows and columns, reflecting the board in its main diagonal. This
+     * fits boards with square boxes.
+     */
+    pub fn transposition(size: usize) -> Self {
+        Transformation {
+            transpose: true,
+            ..Self::identity(size)
+        }
+    }
+
+    /**
+     * Turn the board clockwise by the given number of quarter turns. Odd
+     * numbers of quarter turns fit boards with square boxes.
+     */
+    pub fn rotation(size: usize, quarter_turns: usize) -> Self {
+        let quarter = Self::transposition(size).then(&Self::mirror(size));
+        (0..quarter_turns % 4).fold(Self::identity(size), |turned, _| turned.then(&quarter))
+    }
+
+    /**
+     * Reflect the board left to right.
+     */
+    pub fn mirror(size: usize) -> Self {
+        Transformation {
+            columns: (0..size).rev().collect(),
+            ..Self::identity(size)
+        }
+    }
+
+    /**
+     * Reflect the board top to bottom.
+     */
+    pub fn flip(size: usize) -> Self {
+        Transformation {
+            rows: (0..size).rev().collect(),
+            ..Self::identity(size)
+        }
+    }
+
+    /**
+     * A transformation picked at random from all those that fit the board:
+     * the bands, stacks, and rows and columns within them in any order, the
+     * values renamed at random and, on boards with square boxes, transposed
+     * half the time.
+     */
+    pub fn random(layout: &Layout) -> Self {
+        let size = layout.get_size();
+        let mut rng = thread_rng();
+        let mut digits: Vec<u8> = (1..=size as u8).collect();
+        digits.shuffle(&mut rng);
+        Transformation {
+            transpose: layout.get_box_rows() == layout.get_box_columns() && random(),
+            rows: random_order(size, layout.get_box_rows()),
+            columns: random_order(size, layout.get_box_columns()),
+            digits,
+        }
+    }
+
+    /**
+     * This transformation followed by another, as one transformation.
+     */
+    pub fn then(&self, next: &Transformation) -> Self {
+        // Transposing after reordering is reordering the other way round
+        // after transposing.
+        let (rows, columns) = match next.transpose {
+            true => (&self.columns, &self.rows),
+            false => (&self.rows, &self.columns),
+        };
+        Transformation {
+            transpose: self.transpose != next.transpose,
+            rows: next.rows.iter().map(|&row| rows[row]).collect(),
+            columns: next.columns.iter().map(|&column| columns[column]).collect(),
+            digits: self
+                .digits
+                .iter()
+                .map(|&digit| next.digits[digit as usize - 1])
+                .collect(),
+        }
+    }
+
+    /**
+     * Whether the transformation fits a board: the rows, columns and values
+     * each in some order for the size of the board, the rows of each band
+     * and columns of each stack kept together, and transposed only if the
+     * boxes are square. The board must also be plain for the rules to carry
+     * over.
+     */
+    pub fn fits(&self, layout: &Layout) -> bool {
+        let size = layout.get_size();
+        let digits: Vec<usize> = self.digits.iter().map(|&digit| digit as usize).collect();
+        let (box_rows, box_columns) = (layout.get_box_rows(), layout.get_box_columns());
+        is_order(&self.rows, size, 0)
+            && is_order(&self.columns, size, 0)
+            && is_order(&digits, size, 1)
+            && keeps_groups(&self.rows, box_rows)
+            && keeps_groups(&self.columns, box_columns)
+            && (!self.transpose || box_rows == box_columns)
+    }
+
+    /**
+     * Apply the transformation to the values of a board, with 0 for empty
+     * cells, such as a puzzle or its solution.
+     */
+    pub fn apply(&self, layout: &Layout, values: &[u8]) -> Vec<u8> {
+        self.sources(layout)
+            .into_iter()
+            .map(|from| self.relabel_value(values[from]))
+            .collect()
+    }
+
+    /**
+     * The cell of the board each cell of the result comes from.
+     */
+    pub(crate) fn sources(&self, layout: &Layout) -> Vec<usize> {
+        let size = layout.get_size();
+        let mut sources = Vec::with_capacity(layout.get_board_size());
+        for &from_row in &self.rows {
+            for &from_column in &self.columns {
+                sources.push(match self.transpose {
+                    true => from_column * size + from_row,
+                    false => from_row * size + from_column,
+                });
+            }
+        }
+        sources
+    }
+
+    /**
+     * The value a value becomes, 0 staying 0.
+     */
+    pub(crate) fn relabel_value(&self, value: u8) -> u8 {
+        match value {
+            0 => 0,
+            value => self.digits[value as usize - 1],
+        }
+    }
+
+    /**
+     * The values a set of values becomes, one bit per value.
+     */
+    pub(crate) fn relabel_values(&self, values: u32) -> u32 {
+        self.digits
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| values & (1 << index) != 0)
+            .fold(0, |result, (_, &digit)| result | 1 << (digit - 1))
+    }
+}
+
+impl FromStr for Transformation {
+    type Err = QQWingError;
+
+    /**
+     * Read a transformation written as by Display, such as
+     * "t r213456789 c123456789 d987654321". Whether it fits a board is
+     * checked with fits().
+     */
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let error = || QQWingError::TransformationSyntax(text.to_string());
+        let mut transformation = Transformation::identity(0);
+        let mut seen = String::new();
+        for word in text.split_whitespace() {
+            let mut symbols = word.chars();
+            let kind = symbols.next().map(|kind| kind.to_ascii_lowercase());
+            if kind.is_some_and(|kind| seen.contains(kind)) {
+                return Err(error());
+            }
+            let numbers: Option<Vec<usize>> = symbols
+                .map(|symbol| symbol.to_digit(36).map(|number| number as usize))
+                .collect();
+            let numbers = numbers.ok_or_else(error)?;
+            match kind {
+                Some('t') if numbers.is_empty() => transformation.transpose = true,
+                Some('r') => {
+                    transformation.rows = numbers.iter().map(|&n| n.wrapping_sub(1)).collect()
+                }
+                Some('c') => {
+                    transformation.columns = numbers.iter().map(|&n| n.wrapping_sub(1)).collect()
+                }
+                Some('d') => transformation.digits = numbers.iter().map(|&n| n as u8).collect(),
+                _ => return Err(error()),
+            }
+            seen.extend(kind);
+        }
+        if !seen.contains('r') || !seen.contains('c') || !seen.contains('d') {
+            return Err(error());
+        }
+        Ok(transformation)
+    }
+}
+
+impl fmt::Display for Transformation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.transpose {
+            write!(f, "t ")?;
+        }
+        write!(
+            f,
+            "r{} c{} d{}",
+            symbols(self.rows.iter().map(|&row| row + 1)),
+            symbols(self.columns.iter().map(|&column| column + 1)),
+            symbols(self.digits.iter().map(|&digit| digit as usize))
+        )
+    }
+}
+
+/**
+ * Numbers from 1 written one symbol each, as values are in puzzles.
+ */
+fn symbols(numbers: impl Iterator<Item = usize>) -> String {
+    numbers
+        .map(|number| {
+            char::from_digit(number as u32, 36).map_or('?', |symbol| symbol.to_ascii_uppercase())
+        })
+        .collect()
+}
+
+/**
+ * Swap two groups of lines, such as the rows of two bands, in an order of
+ * lines.
+ */
+fn swap_groups(order: &mut [usize], group_size: usize, first: usize, second: usize) {
+    for line in 0..group_size {
+        order.swap(first * group_size + line, second * group_size + line);
+    }
+}
+
+/**
+ * Whether the numbers are each number from the first for the given count,
+ * in some order.
+ */
+fn is_order(numbers: &[usize], count: usize, first: usize) -> bool {
+    let mut seen = vec![false; count];
+    numbers.len() == count
+        && numbers.iter().all(|&number| {
+            number >= first
+                && number - first < count
+                && !std::mem::replace(&mut seen[number - first], true)
+        })
+}
+
+/**
+ * Whether an order of lines keeps each group of lines, such as the rows of
+ * a band, together.
+ */
+fn keeps_groups(order: &[usize], group_size: usize) -> bool {
+    order.chunks(group_size).all(|group| {
+        group
+            .iter()
+            .all(|&line| line / group_size == group[0] / group_size)
+    })
+}
+
+/**
+ * An order of the rows (or columns) of a board picked at random from those
+ * that keep the rows of each band together.
+ */
+fn random_order(size: usize, band_size: usize) -> Vec<usize> {
+    let mut rng = thread_rng();
+    let mut bands: Vec<usize> = (0..size / band_size).collect();
+    bands.shuffle(&mut rng);
+    bands
+        .into_iter()
+        .flat_map(|band| {
+            let mut lines: Vec<usize> = (band * band_size..(band + 1) * band_size).collect();
+            lines.shuffle(&mut rng);
+            lines
+        })
+        .collect()
+}
+
+/**
+ * Every order of the rows (or columns) of a board that keeps the rows of each
+ * band together: the bands in any order, and the rows in each band in any
+ * order.
+ */
+pub(crate) fn line_orders(size: usize, band_size: usize) -> Vec<Vec<usize>> {
+    let band_orders = permutations(size / band_size);
+    let in_band_orders = permutations(band_size);
+    let mut orders = Vec::new();
+    for bands in &band_orders {
+        let mut partial: Vec<Vec<usize>> = vec![Vec::new()];
+        for &band in bands {
+            partial = partial
+                .iter()
+                .flat_map(|order| {
+                    in_band_orders.iter().map(move |lines| {
+                        let mut order = order.clone();
+                        order.extend(lines.iter().map(|&line| band * band_size + line));
+                        order
+                    })
+                })
+                .collect();
+        }
+        orders.extend(partial);
+    }
+    orders
+}
+
+/**
+ * Every order of the numbers below n, in lexicographic order.
+ */
+fn permutations(n: usize) -> Vec<Vec<usize>> {
+    if n == 0 {
+        return vec![Vec::new()];
+    }
+    let mut result = Vec::new();
+    for first in 0..n {
+        for rest in permutations(n - 1) {
+            let mut order = vec![first];
+            order.extend(rest.into_iter().map(|i| if i >= first { i + 1 } else { i }));
+            result.push(order);
+        }
+    }
+    result
+}
@@ -0,0 +1,372 @@
+use std::fmt::{self, Display};
+
+use crate::logtype::LogType;
+use crate::parity::Parity;
+use crate::player::PlayerState;
+use std::sync::Arc;
+
+use crate::QQWing;
+
+/// A row, column, section, diagonal, extra region or killer cage of the board, numbered
+/// from 0, or a pair of cells a ruled out knight's or king's move apart.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unit {
+    Row(usize),
+    Column(usize),
+    Section(usize),
+    Diagonal(usize),
+    ExtraRegion(usize),
+    Cage(usize),
+    KnightMove,
+    KingMove,
+}
+
+impl Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unit::Row(i) => write!(f, "row {}", i + 1),
+            Unit::Column(i) => write!(f, "column {}", i + 1),
+            Unit::Section(i) => write!(f, "section {}", i + 1),
+            Unit::Diagonal(i) => write!(f, "diagonal {}", i + 1),
+            Unit::ExtraRegion(i) => write!(f, "extra region {}", i + 1),
+            Unit::Cage(i) => write!(f, "cage {}", i + 1),
+            Unit::KnightMove => write!(f, "cells a knight's move apart"),
+            Unit::KingMove => write!(f, "cells a king's move apart"),
+        }
+    }
+}
+
+/// A value that appears more than once in a unit.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub unit: Unit,
+
+    /**
+     * The repeated value.
+     */
+    pub value: u8,
+
+    /**
+     * The positions holding the value in the unit.
+     */
+    pub cells: Vec<usize>,
+}
+
+/// A rule, other than a unit holding each value once, that some cells can
+/// break. Cages, dots, markers, clues and constraints are numbered from 0 in
+/// the order the puzzle has them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    CageSum(usize),
+    Dot(usize),
+    NegativeDot,
+    Marker(usize),
+    NonConsecutive,
+    Clue(usize),
+    Parity,
+    Constraint(usize),
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::CageSum(i) => write!(f, "the sum of cage {}", i + 1),
+            Rule::Dot(i) => write!(f, "dot {}", i + 1),
+            Rule::NegativeDot => write!(f, "cells without a dot"),
+            Rule::Marker(i) => write!(f, "marker {}", i + 1),
+            Rule::NonConsecutive => write!(f, "the non-consecutive rule"),
+            Rule::Clue(i) => write!(f, "clue {}", i + 1),
+            Rule::Parity => write!(f, "the shading"),
+            Rule::Constraint(i) => write!(f, "constraint {}", i + 1),
+        }
+    }
+}
+
+/// Cells whose values break a rule between them.
+#[derive(Debug, Clone)]
+pub struct Broken {
+    pub rule: Rule,
+
+    /**
+     * The filled positions that break the rule.
+     */
+    pub cells: Vec<usize>,
+}
+
+/// The result of checking a player's grid.
+#[derive(Debug, Clone)]
+pub struct Validation {
+    /**
+     * Values repeated within a row, column, section, diagonal, extra region
+     * or cage.
+     */
+    pub conflicts: Vec<Conflict>,
+
+    /**
+     * Cage sums, dots, markers, clues, shading and constraints the filled
+     * cells already break.
+     */
+    pub broken: Vec<Broken>,
+
+    /**
+     * Positions where the player's entry differs from the puzzle's
+     * solution. Always empty when the puzzle does not have a unique solution.
+     */
+    pub mismatches: Vec<usize>,
+
+    /**
+     * Whether the givens have exactly one solution to compare against.
+     */
+    pub unique_solution: bool,
+
+    /**
+     * Whether every cell is filled and the grid breaks no rules and matches
+     * the solution.
+     */
+    pub solved: bool,
+}
+
+impl QQWing {
+    /**
+     * Check a game in progress against every rule of the puzzle and against
+     * the unique solution of its givens. Pencil marks are not checked. Afterwards the
+     * board holds the game in progress, as with set_player_state(), as far
+     * as the entries allow.
+     */
+    pub fn validate(&mut self, state: &PlayerState) -> Validation {
+        let layout = Arc::clone(&self.layout);
+        let size = layout.get_size();
+        let givens = state.get_givens();
+        let mut grid = vec![0; layout.get_board_size()];
+        for (position, cell) in grid.iter_mut().enumerate() {
+            let given = givens.get(position).copied().unwrap_or(0);
+            *cell = match given {
+                0 => state.get_entries().get(position).copied().unwrap_or(0),
+                given => given,
+            };
+        }
+
+        let mut conflicts = Vec::new();
+        let units = (0..size)
+            .flat_map(|i| {
+                [
+                    (Unit::Row(i), layout.row_cells(i)),
+                    (Unit::Column(i), layout.column_cells(i)),
+                    (Unit::Section(i), layout.section_cells(i)),
+                ]
+            })
+            .chain((0..layout.get_extra_unit_count()).map(|i| {
+                let unit = if layout.is_diagonal(i) {
+                    Unit::Diagonal(i)
+                } else {
+                    Unit::ExtraRegion(i - layout.get_diagonal_count())
+                };
+                (unit, layout.extra_unit_cells(i))
+            }))
+            .chain(
+                self.cages
+                    .iter()
+                    .enumerate()
+                    .map(|(i, cage)| (Unit::Cage(i), cage.get_cells())),
+            );
+        for (unit, cells) in units {
+            for value in 1..=size as u8 {
+                let with_value: Vec<usize> = cells
+                    .iter()
+                    .copied()
+                    .filter(|&p| grid[p] == value)
+                    .collect();
+                if with_value.len() > 1 {
+                    conflicts.push(Conflict {
+                        unit: unit.clone(),
+                        value,
+                        cells: with_value,
+                    });
+                }
+            }
+        }
+
+        // Cells a ruled out move apart that aren't already in a unit together.
+        for position in 0..grid.len() {
+            for &peer in layout.move_peers(position) {
+                let shares_unit = layout.cell_to_row(peer) == layout.cell_to_row(position)
+                    || layout.cell_to_column(peer) == layout.cell_to_column(position)
+                    || layout.cell_to_section(peer) == layout.cell_to_section(position);
+                if peer < position || shares_unit || grid[position] == 0 {
+                    continue;
+                }
+                if grid[peer] == grid[position] {
+                    let row_apart = layout
+                        .cell_to_row(peer)
+                        .abs_diff(layout.cell_to_row(position));
+                    let column_apart = layout
+                        .cell_to_column(peer)
+                        .abs_diff(layout.cell_to_column(position));
+                    conflicts.push(Conflict {
+                        unit: match row_apart.max(column_apart) {
+                            1 => Unit::KingMove,
+                            _ => Unit::KnightMove,
+                        },
+                        value: grid[position],
+                        cells: vec![position, peer],
+                    });
+                }
+            }
+        }
+
+        let broken = self.broken_rules(&grid);
+
+        // Givens that conflict with each other have no solution to compare
+        // against, the conflicts above already say what is wrong.
+        let unique_solution = self.set_puzzle(givens.to_vec()).is_ok()
+            && self.count_solutions_limited().is_ok_and(|count| count == 1);
+        let mut mismatches = Vec::new();
+        if unique_solution && self.solve().is_ok_and(|solved| solved) {
+            for (position, &value) in grid.iter().enumerate() {
+                if value != 0 && value != self.solution[position] {
+                    mismatches.push(position);
+                }
+            }
+        }
+        let complete = grid.iter().all(|&v| v != 0);
+        let solved = complete && conflicts.is_empty() && broken.is_empty() && mismatches.is_empty();
+
+        // The entries may conflict, in which case the board holds as much of
+        // the game as could be placed.
+        let _ = self.set_player_state(state);
+        Validation {
+            conflicts,
+            broken,
+            mismatches,
+            unique_solution,
+            solved,
+        }
+    }
+
+    /**
+     * The rules besides repeated values that the filled cells of a grid
+     * break, looking only at the cells filled so far.
+     */
+    fn broken_rules(&self, grid: &[u8]) -> Vec<Broken> {
+        let layout = &*self.layout;
+        let bit = |value: u8| 1u32 << (value - 1);
+        let filled = |cells: &[usize]| -> Vec<usize> {
+            cells.iter().copied().filter(|&p| grid[p] != 0).collect()
+        };
+        let mut broken = Vec::new();
+
+        for (i, cage) in self.cages.iter().enumerate() {
+            let cells = filled(cage.get_cells());
+            let sum: u32 = cells.iter().map(|&p| grid[p] as u32).sum();
+            let complete = cells.len() == cage.get_cells().len();
+            if sum > cage.get_sum() || (complete && sum != cage.get_sum()) {
+                broken.push(Broken {
+                    rule: Rule::CageSum(i),
+                    cells,
+                });
+            }
+        }
+
+        for rule in &self.pair_rules {
+            let [first, second] = rule.get_cells();
+            if grid[first] == 0
+                || grid[second] == 0
+                || rule.support(first, bit(grid[first])) & bit(grid[second]) != 0
+            {
+                continue;
+            }
+            let same_cells = |cells: [usize; 2]| cells == [first, second];
+            let rule = match rule.get_log_type() {
+                LogType::NonConsecutive => Rule::NonConsecutive,
+                LogType::KropkiDot => {
+                    match self.dots.iter().position(|d| same_cells(d.get_cells())) {
+                        Some(dot) => Rule::Dot(dot),
+                        None => Rule::NegativeDot,
+                    }
+                }
+                _ => Rule::Marker(
+                    self.markers
+                        .iter()
+                        .position(|m| same_cells(m.get_cells()))
+                        .unwrap_or_default(),
+                ),
+            };
+            broken.push(Broken {
+                rule,
+                cells: vec![first, second],
+            });
+        }
+
+        for (i, clue) in self.clues.iter().enumerate() {
+            let cells = clue.rule().cells(layout);
+            let mut values: Vec<u32> = cells
+                .iter()
+                .map(|&p| match grid[p] {
+                    0 => layout.all_values(),
+                    value => bit(value),
+                })
+                .collect();
+            if !clue.rule().prune(layout, &mut values) {
+                broken.push(Broken {
+                    rule: Rule::Clue(i),
+                    cells: filled(&cells),
+                });
+            }
+        }
+
+        for (position, parity) in self.parity.iter().enumerate() {
+            if let Some(parity) = parity {
+                if grid[position] != 0 && Parity::of(grid[position]) != *parity {
+                    broken.push(Broken {
+                        rule: Rule::Parity,
+                        cells: vec![position],
+                    });
+                }
+            }
+        }
+
+        // The values each empty cell could still take, as constraints are
+        // asked with, and 0 for the filled cells.
+        let possibilities: Vec<u32> = grid
+            .iter()
+            .map(|&value| match value {
+                0 => layout.all_values(),
+                _ => 0,
+            })
+            .collect();
+        for (i, constraint) in self.constraints.iter().enumerate() {
+            for position in (0..grid.len()).filter(|&p| grid[p] != 0) {
+                let value = grid[position];
+                let mut cells: Vec<usize> = constraint
+                    .peers(layout, position)
+                    .into_iter()
+                    .filter(|&peer| grid.get(peer) == Some(&value))
+                    // Name each pair once, however the constraint names it.
+                    .filter(|&peer| {
+                        peer > position || !constraint.peers(layout, peer).contains(&position)
+                    })
+                    .collect();
+                cells.extend(
+                    constraint
+                        .propagate(layout, grid, &possibilities, position)
+                        .into_iter()
+                        .filter(|&(cell, values)| {
+                            cell != position
+                                && grid
+                                    .get(cell)
+                                    .is_some_and(|&v| v != 0 && values & bit(v) != 0)
+                        })
+                        .map(|(cell, _)| cell),
+                );
+                if constraint.restrict(layout, position) & bit(value) == 0 || !cells.is_empty() {
+                    cells.insert(0, position);
+                    broken.push(Broken {
+                        rule: Rule::Constraint(i),
+                        cells,
+                    });
+                }
+            }
+        }
+        broken
+    }
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::{assert_ambiguous, assert_latin, assert_solves_to, values, BOXES};
+use qqwing::layout::Layout;
+use qqwing::QQWing;
+
+const KNIGHT_PUZZLE: &str =
+    ".......8...5...4..9..............2........5......8...6.5...17..........3...27....";
+const KNIGHT_SOLUTION: &str =
+    "143527689875396412962418375698145237431762598527983146259831764714659823386274951";
+const KING_PUZZLE: &str =
+    "......5..8..3.1....1..5......5.....92..9..38.......7....36.....9.45..63...8.9...2";
+const KING_SOLUTION: &str =
+    "739268514856341297412759863685437129247916385391825746123674958974582631568193472";
+
+/// Check that no value is the given moves away from the same value.
+fn assert_no_repeat_a_move_away(grid: &str, moves: &[(i32, i32)]) {
+    let grid = values(grid);
+    for cell in 0..81 {
+        let (row, column) = ((cell / 9) as i32, (cell % 9) as i32);
+        for &(down, right) in moves {
+            let (other_row, other_column) = (row + down, column + right);
+            if (0..9).contains(&other_row) && (0..9).contains(&other_column) {
+                let other = (other_row * 9 + other_column) as usize;
+                assert_ne!(grid[cell], grid[other], "cells {} and {}", cell, other);
+            }
+        }
+    }
+}
+
+#[test]
+fn solves_known_anti_knight_puzzle() {
+    assert_latin(KNIGHT_SOLUTION, BOXES);
+    let knight = [(1, 2), (2, 1), (2, -1), (1, -2)];
+    assert_no_repeat_a_move_away(KNIGHT_SOLUTION, &knight);
+    let mut qqwing = QQWing::with_layout(Layout::default().with_anti_knight());
+    assert_solves_to(&mut qqwing, KNIGHT_PUZZLE, KNIGHT_SOLUTION);
+}
+
+#[test]
+fn counts_ambiguous_anti_knight_puzzle() {
+    let mut qqwing = QQWing::with_layout(Layout::default().with_anti_knight());
+    assert_ambiguous(
+        &mut qqwing,
+        "...........5...4..9..............2........5......8...6.5...17..........3...27....",
+    );
+}
+
+#[test]
+fn solves_known_anti_king_puzzle() {
+    assert_latin(KING_SOLUTION, BOXES);
+    assert_no_repeat_a_move_away(KING_SOLUTION, &[(1, 1), (1, -1)]);
+    let mut qqwing = QQWing::with_layout(Layout::default().with_anti_king());
+    assert_solves_to(&mut qqwing, KING_PUZZLE, KING_SOLUTION);
+}
+
+#[test]
+fn counts_ambiguous_anti_king_puzzle() {
+    let mut qqwing = QQWing::with_layout(Layout::default().with_anti_king());
+    assert_ambiguous(
+        &mut qqwing,
+        ".........8..3.1....1..5......5.....92..9..38.......7....36.....9.45..63...8.9...2",
+    );
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::values;
+use qqwing::canon::{canonical_form, equivalent};
+use qqwing::layout::Layout;
+use qqwing::transform::Transformation;
+use qqwing::QQWing;
+
+const PUZZLE: &str =
+    ".2....1.4..173.....6.....8.7.5.12....1...8.6....5..7..2.....53.5.8...6.1........8";
+const DISGUISED: &str =
+    "8.4.9.......3..85..9.5...2..3...5....7.9........82....9....354...2.4.7..6......92";
+
+#[test]
+fn disguises_have_the_same_canonical_form() {
+    let layout = Layout::default();
+    let (canonical, transformation) = canonical_form(&layout, &values(PUZZLE)).unwrap();
+    assert_eq!(transformation.apply(&layout, &values(PUZZLE)), canonical);
+    assert_eq!(
+        canonical_form(&layout, &values(DISGUISED)).unwrap().0,
+        canonical
+    );
+    assert!(equivalent(&layout, &values(PUZZLE), &values(DISGUISED)).unwrap());
+
+    let transformation: Transformation = "t r213456789 c123456789 d987654321".parse().unwrap();
+    assert_eq!(
+        transformation.apply(&layout, &values(PUZZLE)),
+        values(DISGUISED)
+    );
+}
+
+#[test]
+fn disguises_solve_to_the_solution_disguised_alike() {
+    let layout = Layout::default();
+    let transformation: Transformation = "t r213456789 c123456789 d987654321".parse().unwrap();
+    let mut qqwing = QQWing::new();
+    qqwing.set_puzzle(values(PUZZLE)).unwrap();
+    assert!(qqwing.solve().unwrap());
+    let solution = qqwing.get_solution();
+    qqwing.set_puzzle(values(DISGUISED)).unwrap();
+    assert!(qqwing.solve().unwrap());
+    assert_eq!(
+        qqwing.get_solution(),
+        transformation.apply(&layout, &solution)
+    );
+}
+
+#[test]
+fn canonical_form_keeps_the_solution_count() {
+    let layout = Layout::default();
+    let (canonical, _) = canonical_form(&layout, &values(PUZZLE)).unwrap();
+    let mut qqwing = QQWing::new();
+    qqwing.set_puzzle(canonical).unwrap();
+    assert_eq!(qqwing.count_solutions_limited().unwrap(), 1);
+}
+
+#[test]
+fn other_puzzles_are_not_equivalent() {
+    let layout = Layout::default();
+    let fewer = PUZZLE.replacen('2', ".", 1);
+    assert!(!equivalent(&layout, &values(PUZZLE), &values(&fewer)).unwrap());
+    let mut ambiguous = QQWing::new();
+    ambiguous.set_puzzle(values(&fewer)).unwrap();
+    assert_eq!(ambiguous.count_solutions_limited().unwrap(), 2);
+}
+
+#[test]
+fn jigsaw_boards_have_no_canonical_form() {
+    let regions =
+        "111222333111223333114225563144255663444255669774455669777495669777799999888888888";
+    let layout = Layout::parse_regions(regions).unwrap();
+    assert!(canonical_form(&layout, &values(PUZZLE)).is_err());
+}
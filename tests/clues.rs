@@ -0,0 +1,52 @@
+mod common;
+
+use common::{assert_ambiguous, assert_latin, assert_solves_to, values, BOXES};
+use qqwing::clues::parse_clues;
+use qqwing::QQWing;
+
+const CLUES: &str =
+    "sandwich: r1=21; sandwich: c1=15; thermo: r5c1,r5c2,r5c3,r5c4; arrow: r3c9,r3c8,r2c9";
+const PUZZLE: &str =
+    ".......................23..................9....6..4...97..1..5..1.37..4..32.5..6";
+const SOLUTION: &str =
+    "972354168318976542564182379736549281145728693829613457297461835651837924483295716";
+
+fn with_clues() -> QQWing {
+    let mut qqwing = QQWing::new();
+    let clues = parse_clues(qqwing.get_layout(), CLUES).unwrap();
+    qqwing.set_clues(clues).unwrap();
+    qqwing
+}
+
+/// The sum of the values between the 1 and the 9 of a line of values.
+fn sandwich(line: &[u8]) -> u32 {
+    let one = line.iter().position(|&v| v == 1).unwrap();
+    let nine = line.iter().position(|&v| v == 9).unwrap();
+    let (low, high) = (one.min(nine), one.max(nine));
+    line[low + 1..high].iter().map(|&v| v as u32).sum()
+}
+
+#[test]
+fn solves_known_puzzle() {
+    assert_latin(SOLUTION, BOXES);
+    let grid = values(SOLUTION);
+    assert_eq!(sandwich(&grid[0..9]), 21);
+    let column: Vec<u8> = (0..9).map(|row| grid[row * 9]).collect();
+    assert_eq!(sandwich(&column), 15);
+    assert!(grid[36..40].windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(grid[26], grid[25] + grid[17]);
+    assert_solves_to(&mut with_clues(), PUZZLE, SOLUTION);
+}
+
+#[test]
+fn counts_ambiguous_puzzle() {
+    assert_ambiguous(
+        &mut with_clues(),
+        "........................3..................9....6..4...97..1..5..1.37..4..32.5..6",
+    );
+}
+
+#[test]
+fn clues_make_the_puzzle_unique() {
+    assert_ambiguous(&mut QQWing::new(), PUZZLE);
+}
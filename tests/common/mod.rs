@@ -0,0 +1,49 @@
+//! Helpers shared by the integration tests, for 9x9 boards.
+#![allow(dead_code)]
+
+use qqwing::QQWing;
+
+/// The region map of the boxes of a plain 9x9 board.
+pub const BOXES: &str =
+    "111222333111222333111222333444555666444555666444555666777888999777888999777888999";
+
+/// The values of a grid written one character per cell, 0 for '.'.
+pub fn values(grid: &str) -> Vec<u8> {
+    grid.chars()
+        .map(|c| c.to_digit(10).unwrap_or(0) as u8)
+        .collect()
+}
+
+/// Check, without the solver, that every row, column and region of a full
+/// grid holds each value once.
+pub fn assert_latin(grid: &str, regions: &str) {
+    let grid = values(grid);
+    let regions: Vec<char> = regions.chars().collect();
+    assert_eq!(grid.len(), 81);
+    for i in 0..81 {
+        assert!((1..=9).contains(&grid[i]), "cell {} is empty", i);
+        for j in i + 1..81 {
+            let same_unit = i / 9 == j / 9 || i % 9 == j % 9 || regions[i] == regions[j];
+            assert!(
+                !same_unit || grid[i] != grid[j],
+                "cells {} and {} repeat",
+                i,
+                j
+            );
+        }
+    }
+}
+
+/// Check that a puzzle has one solution, which the solver finds.
+pub fn assert_solves_to(qqwing: &mut QQWing, puzzle: &str, solution: &str) {
+    qqwing.set_puzzle(values(puzzle)).unwrap();
+    assert_eq!(qqwing.count_solutions_limited().unwrap(), 1);
+    assert!(qqwing.solve().unwrap());
+    assert_eq!(qqwing.get_solution(), values(solution));
+}
+
+/// Check that a puzzle has more than one solution.
+pub fn assert_ambiguous(qqwing: &mut QQWing, puzzle: &str) {
+    qqwing.set_puzzle(values(puzzle)).unwrap();
+    assert_eq!(qqwing.count_solutions_limited().unwrap(), 2);
+}
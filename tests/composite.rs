@@ -0,0 +1,72 @@
+mod common;
+
+use common::{assert_latin, values, BOXES};
+use qqwing::composite::{Composite, CompositeKind, CompositeLayout};
+use qqwing::options::GenerateOptions;
+use qqwing::QQWingError;
+use strum::IntoEnumIterator;
+
+const PUZZLE: &str =
+    "...85..1.9...328........5.65.....2..4..2.3..9.7.......2....6......1.....4....2.1...\
+    6.5.......7.....8...9...2.2.........6..457....3...1..39..8.6.28..7....";
+const SOLUTION: &str =
+    "627854913915632847843917526596748231481263759372591684239186475962183168475392\
+    815746754329168743925847591632523687491916234578654329817739158264281476359";
+
+fn two_grid(puzzle: &str) -> Composite {
+    let mut composite = Composite::new(CompositeLayout::new(CompositeKind::TWOGRID));
+    composite.set_puzzle(values(puzzle)).unwrap();
+    composite
+}
+
+#[test]
+fn each_kind_lines_up() {
+    for kind in CompositeKind::iter() {
+        let cells = match kind {
+            CompositeKind::SAMURAI => 5 * 81 - 4 * 9,
+            CompositeKind::TWOGRID => 2 * 81 - 9,
+            CompositeKind::BUTTERFLY => 12 * 12,
+        };
+        let layout = CompositeLayout::new(kind);
+        assert_eq!(layout.get_cell_count(), cells);
+        let offsets = layout.get_offsets();
+        assert_eq!(CompositeLayout::with_grids(offsets).ok(), Some(layout));
+    }
+}
+
+#[test]
+fn generate_refuses_options_it_ignores() {
+    let mut composite = Composite::new(CompositeLayout::new(CompositeKind::TWOGRID));
+    let options = GenerateOptions {
+        killer: true,
+        ..Default::default()
+    };
+    assert!(matches!(
+        composite.generate_puzzle_with_options(&options),
+        Err(QQWingError::UnsupportedCompositeOption("killer"))
+    ));
+}
+
+#[test]
+fn solves_known_puzzle() {
+    let solution = values(SOLUTION);
+    let layout = CompositeLayout::new(CompositeKind::TWOGRID);
+    for grid in 0..layout.get_offsets().len() {
+        let cells: String = layout
+            .grid_cells(grid)
+            .iter()
+            .map(|&cell| char::from(b'0' + solution[cell]))
+            .collect();
+        assert_latin(&cells, BOXES);
+    }
+    let mut composite = two_grid(PUZZLE);
+    assert_eq!(composite.count_solutions_limited(), 1);
+    assert!(composite.solve());
+    assert_eq!(composite.get_solution(), solution);
+}
+
+#[test]
+fn counts_ambiguous_puzzle() {
+    let puzzle = PUZZLE.replacen('8', ".", 1);
+    assert_eq!(two_grid(&puzzle).count_solutions_limited(), 2);
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use common::{assert_ambiguous, assert_latin, assert_solves_to, values, BOXES};
+use qqwing::constraint::Constraint;
+use qqwing::layout::Layout;
+use qqwing::QQWing;
+
+const PUZZLE: &str =
+    "......5...7............24..5.....7.64...9...8927.......6.8...32.8.7.....2.5......";
+const SOLUTION: &str =
+    "649381527172465983853972461518243796436597218927618345764859132381724659295136874";
+
+/// The main diagonal must hold every value, as in the README.
+struct Diagonal;
+
+impl Constraint for Diagonal {
+    fn peers(&self, _layout: &Layout, cell: usize) -> Vec<usize> {
+        match cell % 10 {
+            0 => (0..9).map(|i| i * 10).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn with_diagonal() -> QQWing {
+    let mut qqwing = QQWing::new();
+    qqwing.add_constraint(Box::new(Diagonal)).unwrap();
+    qqwing
+}
+
+#[test]
+fn solves_known_puzzle() {
+    assert_latin(SOLUTION, BOXES);
+    let mut diagonal: Vec<u8> = (0..9).map(|i| values(SOLUTION)[i * 10]).collect();
+    diagonal.sort_unstable();
+    assert_eq!(diagonal, (1..=9).collect::<Vec<u8>>());
+    assert_solves_to(&mut with_diagonal(), PUZZLE, SOLUTION);
+}
+
+#[test]
+fn counts_ambiguous_puzzle() {
+    assert_ambiguous(
+        &mut with_diagonal(),
+        "..........7............24..5.....7.64...9...8927.......6.8...32.8.7.....2.5......",
+    );
+}
+
+#[test]
+fn is_not_registered_when_the_givens_break_it() {
+    let mut qqwing = QQWing::new();
+    let mut puzzle = vec![0; 81];
+    puzzle[0] = 5;
+    puzzle[40] = 5;
+    qqwing.set_puzzle(puzzle).unwrap();
+    assert!(qqwing.add_constraint(Box::new(Diagonal)).is_err());
+    assert!(qqwing.get_constraints().is_empty());
+    assert!(qqwing.solve().unwrap());
+}
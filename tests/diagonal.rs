@@ -0,0 +1,39 @@
+mod common;
+
+use common::{assert_ambiguous, assert_latin, assert_solves_to, values, BOXES};
+use qqwing::layout::Layout;
+use qqwing::QQWing;
+
+const PUZZLE: &str =
+    ".6......1749.................76.....6..52...3....97.....59............1.37..6....";
+const SOLUTION: &str =
+    "562749381749813265813256794957638142684521973231497658125974836496382517378165429";
+
+#[test]
+fn solves_known_puzzle() {
+    assert_latin(SOLUTION, BOXES);
+    let grid = values(SOLUTION);
+    for i in 0..9 {
+        for j in i + 1..9 {
+            assert_ne!(grid[i * 10], grid[j * 10]);
+            assert_ne!(grid[i * 8 + 8], grid[j * 8 + 8]);
+        }
+    }
+    let mut qqwing = QQWing::with_layout(Layout::default().with_diagonals());
+    assert_solves_to(&mut qqwing, PUZZLE, SOLUTION);
+}
+
+#[test]
+fn counts_ambiguous_puzzle() {
+    let mut qqwing = QQWing::with_layout(Layout::default().with_diagonals());
+    assert_ambiguous(
+        &mut qqwing,
+        "........1749.................76.....6..52...3....97.....59............1.37..6....",
+    );
+}
+
+#[test]
+fn diagonals_make_the_puzzle_unique() {
+    let mut qqwing = QQWing::new();
+    assert_ambiguous(&mut qqwing, PUZZLE);
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use common::{assert_ambiguous, assert_latin, assert_solves_to};
+use qqwing::layout::Layout;
+use qqwing::QQWing;
+
+const REGIONS: &str =
+    "111222333111223333114225563144255663444255669774455669777495669777799999888888888";
+const PUZZLE: &str =
+    ".......649..4.2.............1.6......24.....3......3...4....6......8.5.9...53....";
+const SOLUTION: &str =
+    "385127964961452738476395281213678495524869173658941327849713652137286549792534816";
+
+fn jigsaw() -> QQWing {
+    QQWing::with_layout(Layout::parse_regions(REGIONS).unwrap())
+}
+
+#[test]
+fn solves_known_puzzle() {
+    assert_latin(SOLUTION, REGIONS);
+    assert_solves_to(&mut jigsaw(), PUZZLE, SOLUTION);
+}
+
+#[test]
+fn counts_ambiguous_puzzle() {
+    assert_ambiguous(
+        &mut jigsaw(),
+        "........49..4.2.............1.6......24.....3......3...4....6......8.5.9...53....",
+    );
+}
+
+#[test]
+fn rejects_regions_of_the_wrong_size() {
+    let regions = REGIONS.replacen('1', "2", 1);
+    assert!(Layout::parse_regions(&regions).is_err());
+}
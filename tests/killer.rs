@@ -0,0 +1,64 @@
+mod common;
+
+use common::{assert_ambiguous, assert_latin, assert_solves_to, values, BOXES};
+use qqwing::killer::parse_cages;
+use qqwing::QQWing;
+
+const CAGES: &str = "9: r1c1; 9: r1c2,r1c3; 28: r1c4,r1c5,r1c6,r2c4,r2c5; 15: r1c7,r1c8,r1c9; \
+    23: r2c1,r2c2,r2c3,r3c1,r3c2; 26: r2c6,r2c7,r2c8,r2c9,r3c9; 13: r3c3,r3c4,r3c5; \
+    5: r3c6,r3c7; 15: r3c8,r4c8; 16: r4c1,r4c2,r4c3; 12: r4c4,r5c4; 13: r4c5,r4c6; \
+    16: r4c7,r5c6,r5c7; 4: r4c9,r5c9; 20: r5c1,r5c2,r5c3,r6c1,r6c2; 3: r5c5,r6c5; \
+    25: r5c8,r6c8,r7c7,r7c8; 22: r6c3,r6c4,r7c3; 7: r6c6,r6c7; 16: r6c9,r7c9,r8c9; \
+    23: r7c1,r7c2,r8c1,r8c2,r8c3; 13: r7c4,r7c5,r8c5; 19: r7c6,r8c6,r8c7,r8c8; 8: r8c4; \
+    4: r9c1; 13: r9c2,r9c3,r9c4; 28: r9c5,r9c6,r9c7,r9c8,r9c9";
+const PUZZLE: &str =
+    "...................6........................3..........9....8......3........9....";
+const SOLUTION: &str =
+    "972354168318976542564182379736549281145728693829613457297461835651837924483295716";
+
+fn killer() -> QQWing {
+    let mut qqwing = QQWing::new();
+    let cages = parse_cages(qqwing.get_layout(), CAGES).unwrap();
+    qqwing.set_cages(cages).unwrap();
+    qqwing
+}
+
+#[test]
+fn solves_known_puzzle() {
+    assert_latin(SOLUTION, BOXES);
+    let qqwing = killer();
+    let grid = values(SOLUTION);
+    for cage in qqwing.get_cages() {
+        let cells = cage.get_cells();
+        let sum: u32 = cells.iter().map(|&cell| grid[cell] as u32).sum();
+        assert_eq!(sum, cage.get_sum());
+        for (i, &cell) in cells.iter().enumerate() {
+            assert!(cells[i + 1..]
+                .iter()
+                .all(|&other| grid[other] != grid[cell]));
+        }
+    }
+    assert_solves_to(&mut killer(), PUZZLE, SOLUTION);
+}
+
+#[test]
+fn counts_ambiguous_puzzle() {
+    assert_ambiguous(
+        &mut killer(),
+        "............................................3..........9....8......3........9....",
+    );
+}
+
+#[test]
+fn keeps_the_old_cages_when_a_given_repeats_in_a_new_one() {
+    let mut qqwing = QQWing::new();
+    let mut puzzle = vec![0; 81];
+    puzzle[2] = 4;
+    puzzle[12] = 4;
+    qqwing.set_puzzle(puzzle).unwrap();
+    let cages = parse_cages(qqwing.get_layout(), "9: r1c2,r1c3").unwrap();
+    qqwing.set_cages(cages).unwrap();
+    let cages = parse_cages(qqwing.get_layout(), "12: r1c3,r1c4,r2c4").unwrap();
+    assert!(qqwing.set_cages(cages).is_err());
+    assert_eq!(qqwing.get_cages()[0].get_sum(), 9);
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use common::{assert_ambiguous, assert_latin, assert_solves_to, values, BOXES};
+use qqwing::kropki::{parse_dots, DotColor};
+use qqwing::layout::Layout;
+use qqwing::QQWing;
+
+const NON_CONSECUTIVE_PUZZLE: &str =
+    ".7..........2.........1...2.6.......5.....8......37.........9...............5....";
+const NON_CONSECUTIVE_SOLUTION: &str =
+    "271648395495273168638519742863195274517462839942837516726384951159726483384951627";
+
+const DOTS: &str = "w: r1c1,r2c1; b: r1c3,r1c4; w: r1c5,r2c5; w: r1c6,r2c6; w: r1c8,r1c9; \
+    b: r2c2,r3c2; w: r2c6,r2c7; w: r2c7,r3c7; w: r2c8,r2c9; w: r2c9,r3c9; w: r3c1,r4c1; \
+    w: r3c1,r3c2; w: r3c4,r4c4; w: r3c4,r3c5; b: r3c7,r4c7; w: r3c9,r4c9; w: r4c7,r4c8; \
+    w: r4c8,r4c9; w: r5c1,r6c1; b: r5c2,r5c3; w: r5c4,r6c4; b: r6c1,r6c2; w: r6c3,r7c3; \
+    w: r6c8,r6c9; w: r7c1,r8c1; w: r7c1,r7c2; b: r7c4,r7c5; w: r7c5,r8c5; w: r7c9,r8c9; \
+    w: r8c2,r8c3; w: r8c5,r9c5; b: r8c5,r8c6; w: r8c6,r9c6; b: r8c7,r9c7; w: r8c7,r8c8; \
+    w: r8c8,r9c8; w: r9c2,r9c3; w: r9c6,r9c7";
+const KROPKI_PUZZLE: &str =
+    "......................................................................5..........";
+const KROPKI_SOLUTION: &str =
+    "964253187817964523325781694279618345536472918481395276652849731798136452143527869";
+
+fn consecutive(first: u8, second: u8) -> bool {
+    first.abs_diff(second) == 1
+}
+
+fn double(first: u8, second: u8) -> bool {
+    first == 2 * second || second == 2 * first
+}
+
+/// The pairs of cells next to each other in a row or column.
+fn neighbours() -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    for cell in 0..81 {
+        if cell % 9 < 8 {
+            pairs.push((cell, cell + 1));
+        }
+        if cell < 72 {
+            pairs.push((cell, cell + 9));
+        }
+    }
+    pairs
+}
+
+#[test]
+fn solves_known_non_consecutive_puzzle() {
+    assert_latin(NON_CONSECUTIVE_SOLUTION, BOXES);
+    let grid = values(NON_CONSECUTIVE_SOLUTION);
+    for (first, second) in neighbours() {
+        assert!(!consecutive(grid[first], grid[second]));
+    }
+    let mut qqwing = QQWing::with_layout(Layout::default().with_non_consecutive());
+    assert_solves_to(
+        &mut qqwing,
+        NON_CONSECUTIVE_PUZZLE,
+        NON_CONSECUTIVE_SOLUTION,
+    );
+}
+
+#[test]
+fn counts_ambiguous_non_consecutive_puzzle() {
+    let mut qqwing = QQWing::with_layout(Layout::default().with_non_consecutive());
+    assert_ambiguous(
+        &mut qqwing,
+        ".7..........2.............2.6.......5.....8......37.........9...............5....",
+    );
+}
+
+fn kropki() -> QQWing {
+    let mut qqwing = QQWing::new();
+    let dots = parse_dots(qqwing.get_layout(), DOTS).unwrap();
+    qqwing.set_dots(dots, true).unwrap();
+    qqwing
+}
+
+#[test]
+fn solves_known_kropki_puzzle() {
+    assert_latin(KROPKI_SOLUTION, BOXES);
+    let qqwing = kropki();
+    let grid = values(KROPKI_SOLUTION);
+    for (first, second) in neighbours() {
+        let (a, b) = (grid[first], grid[second]);
+        let dot = qqwing.get_dots().iter().find(|dot| {
+            let cells = dot.get_cells();
+            cells.contains(&first) && cells.contains(&second)
+        });
+        match dot.map(|dot| dot.get_color()) {
+            Some(DotColor::White) => assert!(consecutive(a, b)),
+            Some(DotColor::Black) => assert!(double(a, b)),
+            None => assert!(!consecutive(a, b) && !double(a, b)),
+        }
+    }
+    assert_solves_to(&mut kropki(), KROPKI_PUZZLE, KROPKI_SOLUTION);
+}
+
+#[test]
+fn counts_ambiguous_kropki_puzzle() {
+    // Without the negative rule and the dot between r1c3 and r1c4, the
+    // puzzle has more than one solution.
+    let mut qqwing = QQWing::new();
+    let mut dots = parse_dots(qqwing.get_layout(), DOTS).unwrap();
+    dots.retain(|dot| dot.get_cells() != [2, 3]);
+    qqwing.set_dots(dots, false).unwrap();
+    assert_ambiguous(&mut qqwing, KROPKI_PUZZLE);
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use common::{assert_ambiguous, assert_latin, assert_solves_to, values, BOXES};
+use qqwing::markers::{parse_markers, MarkerKind};
+use qqwing::QQWing;
+
+const XV_MARKERS: &str = "x: r1c1,r2c1; v: r1c4,r1c5; x: r1c8,r1c9; x: r2c7,r2c8; v: r2c8,r2c9; \
+    x: r3c3,r3c4; v: r4c2,r4c3; v: r4c6,r4c7; x: r4c7,r4c8; x: r5c4,r5c5; x: r5c8,r5c9; \
+    v: r6c1,r6c2; x: r6c3,r7c3; x: r7c4,r7c5; x: r7c9,r8c9; v: r8c4,r8c5; x: r8c5,r9c5; \
+    x: r8c7,r9c7";
+const XV_PUZZLE: &str =
+    "......6....8......1............6...................................2.1.7.5.......";
+const XV_SOLUTION: &str =
+    "475238691698451732123796845814562379567913428239874516781649253946325187352187964";
+
+const GREATER_THAN_MARKERS: &str = ">: r1c1,r2c1; >: r1c2,r2c2; >: r1c3,r2c3; >: r2c4,r1c4; \
+    >: r1c5,r1c4; >: r2c5,r1c5; >: r1c6,r1c5; >: r1c8,r1c7; >: r2c1,r3c1; >: r2c2,r3c2; \
+    >: r2c3,r2c2; >: r3c4,r2c4; >: r2c6,r2c5; >: r3c8,r2c8; >: r3c9,r2c9; >: r3c4,r3c3; \
+    >: r4c5,r3c5; >: r3c6,r3c7; >: r3c7,r3c8; >: r3c8,r4c8; >: r4c5,r5c5; >: r5c6,r4c6; \
+    >: r5c8,r4c8; >: r5c1,r6c1; >: r5c2,r5c1; >: r5c2,r6c2; >: r5c5,r6c5; >: r6c6,r5c6; \
+    >: r5c9,r5c8; >: r6c1,r7c1; >: r6c2,r6c3; >: r6c5,r7c5; >: r6c7,r6c8; >: r6c8,r7c8; \
+    >: r6c8,r6c9; >: r6c9,r7c9; >: r8c3,r7c3; >: r7c6,r7c7; >: r7c8,r7c7; >: r7c8,r8c8; \
+    >: r7c9,r8c9; >: r9c1,r8c1; >: r8c2,r8c3; >: r9c6,r8c6; >: r8c8,r8c9; >: r9c1,r9c2; \
+    >: r9c2,r9c3; >: r9c3,r9c4; >: r9c6,r9c7";
+const GREATER_THAN_PUZZLE: &str =
+    ".......................................................5...........5.........4...";
+const GREATER_THAN_SOLUTION: &str =
+    "987136452623549718415827639748691523569283147231475986152968374394752861876314295";
+
+fn with_markers(text: &str) -> QQWing {
+    let mut qqwing = QQWing::new();
+    let markers = parse_markers(qqwing.get_layout(), text).unwrap();
+    qqwing.set_markers(markers).unwrap();
+    qqwing
+}
+
+/// Check, without the solver, that a full grid keeps to every marker.
+fn assert_keeps_markers(qqwing: &QQWing, solution: &str) {
+    assert_latin(solution, BOXES);
+    let grid = values(solution);
+    for marker in qqwing.get_markers() {
+        let [first, second] = marker.get_cells();
+        let (a, b) = (grid[first], grid[second]);
+        match marker.get_kind() {
+            MarkerKind::X => assert_eq!(a + b, 10),
+            MarkerKind::V => assert_eq!(a + b, 5),
+            MarkerKind::Greater => assert!(a > b),
+        }
+    }
+}
+
+#[test]
+fn solves_known_xv_puzzle() {
+    let mut qqwing = with_markers(XV_MARKERS);
+    assert_keeps_markers(&qqwing, XV_SOLUTION);
+    assert_solves_to(&mut qqwing, XV_PUZZLE, XV_SOLUTION);
+}
+
+#[test]
+fn counts_ambiguous_xv_puzzle() {
+    assert_ambiguous(
+        &mut with_markers(XV_MARKERS),
+        "...........8......1............6...................................2.1.7.5.......",
+    );
+}
+
+#[test]
+fn solves_known_greater_than_puzzle() {
+    let mut qqwing = with_markers(GREATER_THAN_MARKERS);
+    assert_keeps_markers(&qqwing, GREATER_THAN_SOLUTION);
+    assert_solves_to(&mut qqwing, GREATER_THAN_PUZZLE, GREATER_THAN_SOLUTION);
+}
+
+#[test]
+fn counts_ambiguous_greater_than_puzzle() {
+    assert_ambiguous(
+        &mut with_markers(GREATER_THAN_MARKERS),
+        "...................................................................5.........4...",
+    );
+}
+
+#[test]
+fn keeps_the_old_markers_when_the_givens_break_new_ones() {
+    let mut qqwing = with_markers("v: r1c1,r1c2");
+    qqwing
+        .set_puzzle(values(&format!("14{}", ".".repeat(79))))
+        .unwrap();
+    let markers = parse_markers(qqwing.get_layout(), "x: r1c1,r1c2").unwrap();
+    assert!(qqwing.set_markers(markers).is_err());
+    assert_eq!(qqwing.get_markers()[0].get_kind(), MarkerKind::V);
+    assert_eq!(qqwing.count_solutions_limited().unwrap(), 2);
+}
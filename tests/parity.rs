@@ -0,0 +1,50 @@
+mod common;
+
+use common::{assert_ambiguous, assert_latin, assert_solves_to, values, BOXES};
+use qqwing::parity::{parse_parity, Parity};
+use qqwing::QQWing;
+
+const SHADING: &str =
+    ".e..e....e...e.e..e....ee.e..o......o.o...................e..o................o.o";
+const PUZZLE: &str =
+    "........2..9..1.......3.......4.6..7...............5.49..2..............3..1...7.";
+const SOLUTION: &str =
+    "583649712469721853217538496835496127741852369692317584974285631156973248328164975";
+
+fn even_odd() -> QQWing {
+    let mut qqwing = QQWing::new();
+    let shading = parse_parity(qqwing.get_layout(), SHADING).unwrap();
+    qqwing.set_parity(shading).unwrap();
+    qqwing
+}
+
+#[test]
+fn solves_known_puzzle() {
+    assert_latin(SOLUTION, BOXES);
+    let qqwing = even_odd();
+    for (&value, shading) in values(SOLUTION).iter().zip(qqwing.get_parity()) {
+        if let Some(parity) = shading {
+            assert_eq!(Parity::of(value), *parity);
+        }
+    }
+    assert_solves_to(&mut even_odd(), PUZZLE, SOLUTION);
+}
+
+#[test]
+fn counts_ambiguous_puzzle() {
+    assert_ambiguous(
+        &mut even_odd(),
+        "...........9..1.......3.......4.6..7...............5.49..2..............3..1...7.",
+    );
+}
+
+#[test]
+fn keeps_the_old_shading_when_a_given_has_the_wrong_parity() {
+    let mut qqwing = even_odd();
+    qqwing.set_puzzle(values(PUZZLE)).unwrap();
+    let mut shading = qqwing.get_parity().to_vec();
+    shading[8] = Some(Parity::Odd);
+    assert!(qqwing.set_parity(shading).is_err());
+    assert_eq!(qqwing.get_parity()[8], None);
+    assert_eq!(qqwing.count_solutions_limited().unwrap(), 1);
+}
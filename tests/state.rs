@@ -0,0 +1,48 @@
+mod common;
+
+use common::values;
+use qqwing::layout::Layout;
+use qqwing::{QQWing, QQWingError};
+
+const PUZZLE: &str =
+    ".2....1.4..173.....6.....8.7.5.12....1...8.6....5..7..2.....53.5.8...6.1........8";
+
+#[test]
+fn restores_the_board_after_a_solve() {
+    let mut qqwing = QQWing::new();
+    qqwing.set_puzzle(values(PUZZLE)).unwrap();
+    let before = qqwing.save_state();
+    assert!(qqwing.solve().unwrap());
+    let solution = qqwing.get_solution();
+    let solved = qqwing.save_state();
+
+    qqwing.restore_state(&before).unwrap();
+    assert!(!qqwing.is_solved());
+    assert_eq!(qqwing.get_solution(), before.get_values());
+    qqwing.restore_state(&solved).unwrap();
+    assert!(qqwing.is_solved());
+    assert_eq!(qqwing.get_solution(), solution);
+
+    // The board goes on solving from a restored snapshot as from any other.
+    qqwing.restore_state(&before).unwrap();
+    assert!(qqwing.solve().unwrap());
+    assert_eq!(qqwing.get_solution(), solution);
+}
+
+#[test]
+fn refuses_snapshots_taken_under_other_rules() {
+    let mut qqwing = QQWing::new();
+    qqwing.set_puzzle(values(PUZZLE)).unwrap();
+    let state = qqwing.save_state();
+    qqwing.set_layout(Layout::default().with_diagonals());
+    assert!(matches!(
+        qqwing.restore_state(&state),
+        Err(QQWingError::StateMismatch)
+    ));
+
+    let mut other = QQWing::new();
+    assert!(matches!(
+        other.restore_state(&state),
+        Err(QQWingError::StateMismatch)
+    ));
+}
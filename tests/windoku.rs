@@ -0,0 +1,42 @@
+mod common;
+
+use common::{assert_ambiguous, assert_latin, assert_solves_to, values, BOXES};
+use qqwing::layout::Layout;
+use qqwing::QQWing;
+
+const PUZZLE: &str =
+    "............1.9..8...8.....2........9.75...624.89.25....4.........7...9..........";
+const SOLUTION: &str =
+    "186253974372149658549867321235678149917534862468912537624391785851726493793485216";
+
+#[test]
+fn solves_known_puzzle() {
+    assert_latin(SOLUTION, BOXES);
+    let grid = values(SOLUTION);
+    for (top, left) in [(1, 1), (1, 5), (5, 1), (5, 5)] {
+        let mut window: Vec<u8> = (0..9)
+            .map(|i| grid[(top + i / 3) * 9 + left + i % 3])
+            .collect();
+        window.sort_unstable();
+        assert_eq!(window, (1..=9).collect::<Vec<u8>>());
+    }
+    let mut qqwing = QQWing::with_layout(Layout::default().with_windows());
+    assert_solves_to(&mut qqwing, PUZZLE, SOLUTION);
+}
+
+#[test]
+fn counts_ambiguous_puzzle() {
+    let mut qqwing = QQWing::with_layout(Layout::default().with_windows());
+    assert_ambiguous(
+        &mut qqwing,
+        "..............9..8...8.....2........9.75...624.89.25....4.........7...9..........",
+    );
+}
+
+#[test]
+fn extra_region_map_matches_windows() {
+    let map = "..........aaa.bbb..aaa.bbb..aaa.bbb...........ccc.ddd..ccc.ddd..ccc.ddd..........";
+    let layout = Layout::default().with_extra_region_map(map).unwrap();
+    let mut qqwing = QQWing::with_layout(layout);
+    assert_solves_to(&mut qqwing, PUZZLE, SOLUTION);
+}